@@ -0,0 +1,404 @@
+// The front end of the game: everything from the title screen to the results
+// screen at the end of a game. Each screen is a state, and running a state gives
+// back the state that comes after it.
+
+use crate::config::{self, Config};
+use crate::highscores::{self, HighScores};
+use crate::menu::{self, EditorChoice, MainMenuChoice, PuzzleChoice, ReplayChoice};
+use crate::puzzle::{self, Puzzle};
+use crate::replay::{self, Replay};
+use crate::save::{self, SavedGame};
+use crate::screen::Screen;
+use crate::tetris::{self, GameMode, Ranking, Tetris};
+use crate::Ending;
+
+enum State {
+    Title,
+    MainMenu,
+    ModeSelect,
+    Playing(GameMode),
+    Continuing(SavedGame),
+    Congratulations(Tetris, Replay),
+    Results(GameMode, Tetris),
+    HighScores(GameMode, Option<usize>),
+    Replays,
+    Puzzles,
+    Settings,
+    Quit,
+}
+
+pub struct App {
+    screen: Screen,
+    config: Config,
+    high_scores: HighScores,
+    saved_game: Option<SavedGame>,
+    // Shown on the next menu that has room for it.
+    message: Option<String>,
+    // The mode that was played last, so that it's already selected next time.
+    last_mode: GameMode,
+    // Boards that were exported as fumen. These get printed once the screen is
+    // gone, so that they don't get drawn over.
+    exports: Vec<String>,
+}
+
+impl App {
+    pub fn new(config: Config) -> App {
+        let (saved_game, message) = match SavedGame::load() {
+            Ok(saved_game) => (saved_game, None),
+            Err(error) => (None, Some(format!("Can't continue: {}", error))),
+        };
+
+        let (high_scores, warning) = HighScores::load_default();
+
+        App {
+            screen: crate::create_screen(&config.settings),
+            config,
+            high_scores,
+            saved_game,
+            // There's only room for one of these. The high score warning goes
+            // first, since the broken save will still be there next time.
+            message: warning.or(message),
+            last_mode: GameMode::Marathon,
+            exports: Vec::new(),
+        }
+    }
+
+    pub fn run(mut self) {
+        let mut state = State::Title;
+
+        loop {
+            state = match state {
+                State::Quit => break,
+                state => self.step(state),
+            };
+
+            // If we got told to stop during a game, then the game has already
+            // been saved by now.
+            if save::termination_requested() {
+                break;
+            }
+        }
+
+        drop(self.screen);
+
+        for fumen in self.exports {
+            println!("{}", fumen);
+        }
+    }
+
+    fn step(&mut self, state: State) -> State {
+        match state {
+            State::Title => {
+                if menu::title_screen(&mut self.screen) {
+                    State::MainMenu
+                } else {
+                    State::Quit
+                }
+            }
+            State::MainMenu => {
+                let can_continue = self.saved_game.is_some();
+
+                match menu::main_menu(&mut self.screen, can_continue, self.message.take()) {
+                    MainMenuChoice::Continue => match self.saved_game.take() {
+                        Some(saved_game) => State::Continuing(saved_game),
+                        None => State::MainMenu,
+                    },
+                    MainMenuChoice::Play => State::ModeSelect,
+                    MainMenuChoice::Puzzles => State::Puzzles,
+                    MainMenuChoice::HighScores => State::HighScores(self.last_mode, None),
+                    MainMenuChoice::Replays => State::Replays,
+                    MainMenuChoice::Settings => State::Settings,
+                    MainMenuChoice::Quit => State::Quit,
+                }
+            }
+            State::ModeSelect => {
+                match menu::mode_select(&mut self.screen, self.last_mode, &self.config.settings) {
+                    Some((mode, settings)) => {
+                        self.last_mode = mode;
+
+                        // Options like the start level are remembered for next time.
+                        if settings != self.config.settings {
+                            self.config.settings = settings;
+                            self.save_config();
+                        }
+
+                        State::Playing(mode)
+                    }
+                    None => State::MainMenu,
+                }
+            }
+            State::Playing(mode) => {
+                let (mut game, replay) = crate::new_game(&self.config, mode);
+                game.compare_splits_with(self.high_scores.personal_best_splits(mode));
+
+                let ending = crate::play(&mut self.screen, &self.config, game, replay, None);
+                self.finish(ending)
+            }
+            State::Continuing(SavedGame { mut game, replay }) => {
+                game.compare_splits_with(self.high_scores.personal_best_splits(replay.mode));
+
+                crate::fit_screen(&mut self.screen, &replay.settings);
+                let ending = crate::play(&mut self.screen, &self.config, game, replay, None);
+                crate::fit_screen(&mut self.screen, &self.config.settings);
+
+                // The save is only needed until the game is over.
+                if let Ending::Finished(..) = ending {
+                    SavedGame::delete();
+                }
+
+                self.finish(ending)
+            }
+            State::Congratulations(mut game, mut replay) => {
+                if !menu::congratulations(&mut self.screen, &game) {
+                    return self.record(game, replay);
+                }
+
+                game.keep_going();
+                replay.endless = true;
+
+                crate::fit_screen(&mut self.screen, &replay.settings);
+                let ending = crate::play(&mut self.screen, &self.config, game, replay, None);
+                crate::fit_screen(&mut self.screen, &self.config.settings);
+
+                self.finish(ending)
+            }
+            State::Results(mode, game) => {
+                menu::results(&mut self.screen, mode, &game);
+
+                let mut entry = highscores::Entry {
+                    name: String::new(),
+                    score: game.score(),
+                    lines: game.lines_cleared(),
+                    level: game.level(),
+                    time_ms: game.time_ms(),
+                    date: highscores::now(),
+                    splits: game.splits().to_vec(),
+                };
+
+                // A time only means something if the game was actually finished.
+                let (is_eligible, result) = match mode.rules().ranking {
+                    Ranking::Score => (true, format!("{}", game.score())),
+                    Ranking::Time => (game.is_cleared(), tetris::format_time_ms(game.time_ms())),
+                    Ranking::Unranked => (false, String::new()),
+                };
+
+                if !is_eligible || !self.high_scores.qualifies(mode, &entry) {
+                    return State::MainMenu;
+                }
+
+                entry.name = menu::enter_name(&mut self.screen, &result);
+                let rank = self.high_scores.insert(mode, entry);
+
+                if let Err(error) = self.high_scores.save() {
+                    self.message = Some(format!("Failed to save the high scores: {}", error));
+                }
+
+                State::HighScores(mode, rank)
+            }
+            State::HighScores(mode, highlight) => {
+                menu::show_high_scores(&mut self.screen, &self.high_scores, mode, highlight);
+                State::MainMenu
+            }
+            State::Replays => self.replays(),
+            State::Puzzles => self.puzzles(),
+            State::Settings => {
+                if let Some(config) = menu::settings_screen(&mut self.screen, &self.config) {
+                    crate::fit_screen(&mut self.screen, &config.settings);
+                    self.config = config;
+                    self.save_config();
+                }
+
+                State::MainMenu
+            }
+            State::Quit => State::Quit,
+        }
+    }
+
+    fn save_config(&mut self) {
+        if let Some(path) = config::config_path() {
+            if let Err(error) = self.config.save(&path) {
+                self.message = Some(format!("Failed to save the config: {}", error));
+            }
+        }
+    }
+
+    // Deals with a game that has just ended. Finished games go to the results
+    // screen (unless the player can keep going), and suspended ones get saved
+    // for later.
+    fn finish(&mut self, ending: Ending) -> State {
+        match ending {
+            Ending::Finished(mut game, replay) => {
+                self.exports.extend(game.take_exports());
+
+                let can_keep_going = replay.mode.rules().endless_after_goal && !replay.endless;
+
+                if game.is_cleared() && can_keep_going {
+                    State::Congratulations(game, replay)
+                } else {
+                    self.record(game, replay)
+                }
+            }
+            Ending::Suspended(mut saved_game) => {
+                self.exports.extend(saved_game.game.take_exports());
+
+                if let Err(error) = saved_game.save() {
+                    self.message = Some(format!("Failed to save the game: {}", error));
+                }
+
+                self.saved_game = Some(saved_game);
+                State::MainMenu
+            }
+        }
+    }
+
+    // Puts the replay of a finished game into the library, and moves on to the
+    // results.
+    fn record(&mut self, game: Tetris, mut replay: Replay) -> State {
+        replay.finish(&game);
+
+        if let Err(error) = replay.save_to_library() {
+            self.message = Some(format!("Failed to save the replay: {}", error));
+        }
+
+        State::Results(replay.mode, game)
+    }
+
+    // The replay library. Watching a replay comes back here afterwards, so that
+    // another one can be picked.
+    fn replays(&mut self) -> State {
+        let paths = replay::list_library();
+        let mut message = None;
+
+        loop {
+            // These get loaded again every time, since they are given away to
+            // the player when they get watched.
+            let mut replays: Vec<_> = paths.iter().map(|path| Replay::load(path)).collect();
+
+            match menu::replay_list(&mut self.screen, &replays, message.take()) {
+                ReplayChoice::Watch(index) => {
+                    if let Ok(replay) = replays.swap_remove(index) {
+                        crate::fit_screen(&mut self.screen, &replay.settings);
+                        let verification = crate::play_replay(&mut self.screen, replay);
+                        if save::termination_requested() {
+                            return State::Quit;
+                        }
+                        crate::fit_screen(&mut self.screen, &self.config.settings);
+
+                        message = verification.map(|verification| match verification {
+                            replay::Verification::Matched => String::from("Replay verified"),
+                            replay::Verification::Desync { .. } => {
+                                String::from("Replay desynced from the original game!")
+                            }
+                        });
+                    }
+                }
+                ReplayChoice::Back => return State::MainMenu,
+            }
+        }
+    }
+
+    // The puzzle list. Puzzles aren't saved or recorded: quitting one just
+    // gives up on it, and a failed puzzle stays selected so it can be retried
+    // right away.
+    fn puzzles(&mut self) -> State {
+        let mut puzzles = puzzle::load_all();
+        let mut selected = 0;
+        let mut message = None;
+
+        loop {
+            let solved = puzzle::load_solved();
+
+            match menu::puzzle_list(
+                &mut self.screen,
+                &puzzles,
+                &solved,
+                selected,
+                message.take(),
+            ) {
+                PuzzleChoice::Play(index) => {
+                    selected = index;
+
+                    if let (name, Ok(puzzle)) = &puzzles[index] {
+                        let solved = self.play_puzzle(puzzle);
+                        if save::termination_requested() {
+                            return State::Quit;
+                        }
+
+                        message = match solved {
+                            Some(true) => Some(match puzzle::mark_solved(name) {
+                                Ok(()) => String::from("Solved!"),
+                                Err(error) => format!("Solved, but failed to save it: {}", error),
+                            }),
+                            Some(false) => Some(String::from("Not quite, [ENTER] to try again")),
+                            None => None,
+                        };
+                    }
+                }
+                PuzzleChoice::Edit(index) => {
+                    let puzzle = index
+                        .and_then(|index| puzzles[index].1.as_ref().ok().cloned())
+                        .unwrap_or_else(|| Puzzle::new(self.config.settings.board_width));
+
+                    message = self.edit_puzzle(puzzle);
+                    if save::termination_requested() {
+                        return State::Quit;
+                    }
+
+                    // The puzzle might have been saved as a new one.
+                    puzzles = puzzle::load_all();
+                    selected = selected.min(puzzles.len() - 1);
+                }
+                PuzzleChoice::Back => return State::MainMenu,
+            }
+        }
+    }
+
+    // The board editor. Trying out the puzzle comes back to the editor, and
+    // saving it goes back to the puzzle list with a message about how that went.
+    fn edit_puzzle(&mut self, mut puzzle: Puzzle) -> Option<String> {
+        let mut message = None;
+
+        loop {
+            crate::fit_screen(&mut self.screen, &puzzle.settings(&self.config.settings));
+            let choice = menu::board_editor(&mut self.screen, &mut puzzle, message.take());
+            crate::fit_screen(&mut self.screen, &self.config.settings);
+
+            match choice {
+                EditorChoice::Play => {
+                    let solved = self.play_puzzle(&puzzle);
+                    if save::termination_requested() {
+                        return None;
+                    }
+
+                    message = match solved {
+                        Some(true) => Some(String::from("Solved!")),
+                        Some(false) => Some(String::from("Not quite")),
+                        None => None,
+                    };
+                }
+                EditorChoice::Save => match puzzle.save() {
+                    Ok(()) => return Some(format!("Saved \"{}\"", puzzle.title)),
+                    Err(error) => message = Some(format!("Failed to save: {}", error)),
+                },
+                EditorChoice::Back => return None,
+            }
+        }
+    }
+
+    // Plays a puzzle, and tells whether it got solved. Nothing comes back if
+    // the player quit before it was over.
+    fn play_puzzle(&mut self, puzzle: &Puzzle) -> Option<bool> {
+        let settings = puzzle.settings(&self.config.settings);
+        let game = Tetris::from_puzzle(puzzle, &settings);
+        let replay = Replay::new(0, GameMode::Puzzle, &settings);
+
+        crate::fit_screen(&mut self.screen, &settings);
+        let ending = crate::play(&mut self.screen, &self.config, game, replay, None);
+        crate::fit_screen(&mut self.screen, &self.config.settings);
+
+        match ending {
+            Ending::Finished(game, _) => Some(game.is_cleared()),
+            Ending::Suspended(_) => None,
+        }
+    }
+}
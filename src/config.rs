@@ -0,0 +1,710 @@
+// The config file. It lives in the config directory and looks a lot like TOML,
+// although only the parts of TOML that we actually need are supported:
+//
+//     # Comments start with a hash.
+//     frame_rate = 60
+//     fall_speed = 1.0
+//
+//     [controls]
+//     move_left = "a"
+//     hard_drop = "space"
+//
+// Anything that isn't in the file keeps its default value.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::tetris::{Action, Settings};
+
+const CONFIG_FILE: &str = "config";
+
+// The limits for each of the settings. These are also used by the settings screen.
+pub const FRAME_RATE_RANGE: (u8, u8) = (10, 240);
+pub const FALL_SPEED_RANGE: (f32, f32) = (0.1, 20.0);
+pub const BOARD_WIDTH_RANGE: (u32, u32) = (4, 40);
+pub const BOARD_HEIGHT_RANGE: (u32, u32) = (4, 60);
+pub const START_LEVEL_RANGE: (u32, u32) = (1, crate::tetris::MAX_LEVEL);
+// Zero means no gravity at all.
+pub const PRACTICE_GRAVITY_RANGE: (u32, u32) = (0, crate::tetris::MAX_LEVEL);
+pub const GARBAGE_ROWS_RANGE: (u32, u32) = (1, 30);
+// This one is a percentage.
+pub const GARBAGE_MESSINESS_RANGE: (u32, u32) = (0, 100);
+// In milliseconds.
+pub const LINE_CLEAR_DELAY_RANGE: (u32, u32) = (0, 1000);
+
+// Which key does what. The keys are stored in the same order as Action::ALL.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Controls {
+    keys: [char; Action::ALL.len()],
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Controls {
+            keys: ['a', 'd', 'w', 's', 'z', 'x', 'h', ' ', 'u', 'r', 'e', 'q'],
+        }
+    }
+}
+
+impl Controls {
+    fn index(action: Action) -> usize {
+        Action::ALL
+            .iter()
+            .position(|other| *other == action)
+            .unwrap()
+    }
+
+    pub fn action_for(&self, key: char) -> Option<Action> {
+        self.keys
+            .iter()
+            .position(|other| *other == key)
+            .map(|index| Action::ALL[index])
+    }
+
+    pub fn key(&self, action: Action) -> char {
+        self.keys[Controls::index(action)]
+    }
+
+    // Binds a key to an action. If the key was already being used by another
+    // action, then the two actions swap keys, so that no key ever does two things.
+    pub fn set_key(&mut self, action: Action, key: char) {
+        let index = Controls::index(action);
+
+        if let Some(other) = self.keys.iter().position(|other| *other == key) {
+            self.keys[other] = self.keys[index];
+        }
+
+        self.keys[index] = key;
+    }
+
+    // How the key is shown to the player.
+    pub fn key_name(&self, action: Action) -> String {
+        match self.key(action) {
+            ' ' => String::from("[SPACE]"),
+            '\t' => String::from("[TAB]"),
+            key => key.to_string(),
+        }
+    }
+}
+
+// How keys are written in the config file. Most keys are just themselves, but
+// spaces and tabs are hard to see so they get names.
+fn key_to_string(key: char) -> String {
+    match key {
+        ' ' => String::from("space"),
+        '\t' => String::from("tab"),
+        key => key.to_string(),
+    }
+}
+
+fn key_from_string(text: &str) -> Option<char> {
+    match text {
+        "space" => Some(' '),
+        "tab" => Some('\t'),
+        _ => {
+            let mut characters = text.chars();
+            let key = characters.next()?;
+
+            if characters.next().is_some() || key.is_control() {
+                None
+            } else {
+                Some(key)
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Config {
+    pub settings: Settings,
+    pub controls: Controls,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    // The line couldn't be understood at all.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    // The line is fine, but the value that it sets isn't.
+    InvalidValue {
+        line: usize,
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "{}", error),
+            ConfigError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ConfigError::InvalidValue { line, key, message } => {
+                write!(f, "line {}: invalid value for '{}': {}", line, key, message)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+// Checks that the settings make sense together. If they don't, this returns the
+// name of the setting that is wrong along with what is wrong with it.
+pub fn validate_settings(settings: &Settings) -> Result<(), (&'static str, String)> {
+    fn check<T: PartialOrd + fmt::Display>(
+        key: &'static str,
+        value: T,
+        (min, max): (T, T),
+    ) -> Result<(), (&'static str, String)> {
+        if value < min || value > max {
+            Err((key, format!("must be between {} and {}", min, max)))
+        } else {
+            Ok(())
+        }
+    }
+
+    check("frame_rate", settings.frame_rate, FRAME_RATE_RANGE)?;
+    check("fall_speed", settings.fall_speed, FALL_SPEED_RANGE)?;
+    check("board_width", settings.board_width, BOARD_WIDTH_RANGE)?;
+    check("board_height", settings.board_height, BOARD_HEIGHT_RANGE)?;
+    check("start_level", settings.start_level, START_LEVEL_RANGE)?;
+    check(
+        "practice_gravity",
+        settings.practice_gravity,
+        PRACTICE_GRAVITY_RANGE,
+    )?;
+    check("garbage_rows", settings.garbage_rows, GARBAGE_ROWS_RANGE)?;
+
+    // The board is really half as big in big mode, and that still has to be
+    // big enough to play on.
+    if settings.big_mode {
+        let (min_width, max_width) = BOARD_WIDTH_RANGE;
+        let (min_height, max_height) = BOARD_HEIGHT_RANGE;
+
+        check(
+            "board_width",
+            settings.board_width,
+            (min_width * 2, max_width),
+        )?;
+        check(
+            "board_height",
+            settings.board_height,
+            (min_height * 2, max_height),
+        )?;
+    }
+
+    check(
+        "garbage_messiness",
+        settings.garbage_messiness,
+        GARBAGE_MESSINESS_RANGE,
+    )?;
+    check(
+        "spawn_x",
+        settings.spawn_x as u32,
+        (1, settings.board_width),
+    )?;
+    check(
+        "line_clear_delay",
+        settings.line_clear_delay,
+        LINE_CLEAR_DELAY_RANGE,
+    )?;
+
+    crate::pieces::load(&settings.piece_set).map_err(|error| ("piece_set", error.to_string()))?;
+
+    Ok(())
+}
+
+// Where the config file is kept.
+pub fn config_path() -> Option<PathBuf> {
+    Some(crate::paths::config_dir()?.join(CONFIG_FILE))
+}
+
+// A value on the right hand side of an equals sign.
+pub enum Value {
+    Number(String),
+    Text(String),
+}
+
+// Splits a line into its key and value, dropping any comment at the end.
+pub fn parse_line(line: &str) -> Result<(&str, Value), String> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| String::from("expected 'key = value'"))?;
+
+    let key = key.trim();
+    let value = value.trim();
+
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid key '{}'", key));
+    }
+
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut text = String::new();
+        let mut characters = rest.chars();
+
+        loop {
+            match characters.next() {
+                Some('"') => break,
+                Some('\\') => match characters.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('t') => text.push('\t'),
+                    _ => return Err(String::from("invalid escape sequence")),
+                },
+                Some(character) => text.push(character),
+                None => return Err(String::from("unterminated string")),
+            }
+        }
+
+        let rest: String = characters.collect();
+        let rest = rest.trim();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(String::from("unexpected text after the value"));
+        }
+
+        Ok((key, Value::Text(text)))
+    } else {
+        let value = match value.split_once('#') {
+            Some((value, _)) => value.trim(),
+            None => value,
+        };
+
+        if value.is_empty() {
+            return Err(format!("missing value for '{}'", key));
+        }
+
+        Ok((key, Value::Number(value.to_string())))
+    }
+}
+
+impl Config {
+    // Loads the config file from the config directory. If there isn't one, then
+    // the defaults are used.
+    pub fn load_default() -> Result<Config, ConfigError> {
+        match config_path() {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Config::parse(&contents),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+
+        // Remember where each setting was set so that the errors from checking
+        // them all together can still point at the right line.
+        let mut setting_lines: HashMap<&str, usize> = HashMap::new();
+        let mut section = "";
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line_number = line_number + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                section = match name.split_once(']') {
+                    Some(("controls", "")) => "controls",
+                    Some((name, _)) => {
+                        return Err(ConfigError::Syntax {
+                            line: line_number,
+                            message: format!("unknown section '{}'", name),
+                        })
+                    }
+                    None => {
+                        return Err(ConfigError::Syntax {
+                            line: line_number,
+                            message: String::from("expected ']'"),
+                        })
+                    }
+                };
+
+                continue;
+            }
+
+            let (key, value) = parse_line(line).map_err(|message| ConfigError::Syntax {
+                line: line_number,
+                message,
+            })?;
+
+            let invalid = |message: &str| ConfigError::InvalidValue {
+                line: line_number,
+                key: key.to_string(),
+                message: message.to_string(),
+            };
+
+            if section == "controls" {
+                let action = Action::from_name(key).ok_or_else(|| ConfigError::Syntax {
+                    line: line_number,
+                    message: format!("unknown action '{}'", key),
+                })?;
+
+                let key = match value {
+                    Value::Text(text) => key_from_string(&text),
+                    Value::Number(_) => None,
+                }
+                .ok_or_else(|| invalid("expected a single key in quotes, or \"space\""))?;
+
+                if let Some(other) = config.controls.action_for(key) {
+                    if other != action && setting_lines.contains_key(other.name()) {
+                        return Err(invalid(&format!(
+                            "that key is already used for '{}'",
+                            other.name()
+                        )));
+                    }
+                }
+
+                config.controls.set_key(action, key);
+                setting_lines.insert(action.name(), line_number);
+                continue;
+            }
+
+            // The piece set is the only setting that isn't a number. Whether
+            // it actually exists gets checked along with everything else.
+            if key == "piece_set" {
+                config.settings.piece_set = match value {
+                    Value::Text(text) => text,
+                    Value::Number(_) => return Err(invalid("expected a name in quotes")),
+                };
+
+                setting_lines.insert("piece_set", line_number);
+                continue;
+            }
+
+            let number = match value {
+                Value::Number(number) => number,
+                Value::Text(_) => return Err(invalid("expected a number")),
+            };
+
+            let settings = &mut config.settings;
+
+            let key = match key {
+                "frame_rate" => {
+                    settings.frame_rate = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "frame_rate"
+                }
+                "fall_speed" => {
+                    settings.fall_speed =
+                        number.parse().map_err(|_| invalid("expected a number"))?;
+                    "fall_speed"
+                }
+                "board_width" => {
+                    settings.board_width = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "board_width"
+                }
+                "board_height" => {
+                    settings.board_height = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "board_height"
+                }
+                "spawn_x" => {
+                    settings.spawn_x = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "spawn_x"
+                }
+                "start_level" => {
+                    settings.start_level = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "start_level"
+                }
+                "practice_gravity" => {
+                    settings.practice_gravity = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "practice_gravity"
+                }
+                "garbage_rows" => {
+                    settings.garbage_rows = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "garbage_rows"
+                }
+                "garbage_messiness" => {
+                    settings.garbage_messiness = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "garbage_messiness"
+                }
+                "big_mode" => {
+                    settings.big_mode = number
+                        .parse()
+                        .map_err(|_| invalid("expected true or false"))?;
+                    "big_mode"
+                }
+                "line_clear_delay" => {
+                    settings.line_clear_delay = number
+                        .parse()
+                        .map_err(|_| invalid("expected a whole number"))?;
+                    "line_clear_delay"
+                }
+                "animations" => {
+                    settings.animations = number
+                        .parse()
+                        .map_err(|_| invalid("expected true or false"))?;
+                    "animations"
+                }
+                _ => {
+                    return Err(ConfigError::Syntax {
+                        line: line_number,
+                        message: format!("unknown setting '{}'", key),
+                    })
+                }
+            };
+
+            setting_lines.insert(key, line_number);
+        }
+
+        // Unless it's been set, the pieces come out in the middle of the
+        // board, however wide it is.
+        if !setting_lines.contains_key("spawn_x") {
+            config.settings.spawn_x = (config.settings.board_width / 2) as u16;
+        }
+
+        validate_settings(&config.settings).map_err(|(key, message)| {
+            ConfigError::InvalidValue {
+                // If the bad setting isn't in the file, it must be the default
+                // that doesn't fit with something else, so blame the last line
+                // that changed a setting instead.
+                line: setting_lines
+                    .get(key)
+                    .or_else(|| setting_lines.values().max())
+                    .copied()
+                    .unwrap_or(0),
+                key: key.to_string(),
+                message,
+            }
+        })?;
+
+        Ok(config)
+    }
+
+    // Saves the config file. Like the high scores, this goes through a temporary
+    // file so that a half-written config can't be left behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let contents = self.write();
+
+        let mut temporary_path = path.to_path_buf().into_os_string();
+        temporary_path.push(".tmp");
+
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        fs::rename(&temporary_path, path)
+    }
+
+    // Writes the config out in the same format that parse() reads.
+    fn write(&self) -> String {
+        let settings = &self.settings;
+
+        let mut contents = String::from("# tetris-cli configuration\n\n");
+        contents += &format!("frame_rate = {}\n", settings.frame_rate);
+        contents += &format!("fall_speed = {:?}\n", settings.fall_speed);
+        contents += &format!("board_width = {}\n", settings.board_width);
+        contents += &format!("board_height = {}\n", settings.board_height);
+        contents += &format!("spawn_x = {}\n", settings.spawn_x);
+        contents += &format!("start_level = {}\n", settings.start_level);
+        contents += &format!("practice_gravity = {}\n", settings.practice_gravity);
+        contents += &format!("garbage_rows = {}\n", settings.garbage_rows);
+        contents += &format!("garbage_messiness = {}\n", settings.garbage_messiness);
+        contents += &format!("big_mode = {}\n", settings.big_mode);
+        contents += &format!("piece_set = \"{}\"\n", settings.piece_set);
+        contents += &format!("line_clear_delay = {}\n", settings.line_clear_delay);
+        contents += &format!("animations = {}\n", settings.animations);
+
+        contents += "\n[controls]\n";
+        for action in Action::ALL.iter() {
+            let key = key_to_string(self.controls.key(*action));
+            let key = key.replace('\\', "\\\\").replace('"', "\\\"");
+
+            contents += &format!("{} = \"{}\"\n", action.name(), key);
+        }
+
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(contents: &str) -> String {
+        Config::parse(contents).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn written_configs_read_back_in() {
+        assert_eq!(
+            Config::parse(&Config::default().write()).unwrap(),
+            Config::default()
+        );
+
+        let mut config = Config::default();
+        config.settings.frame_rate = 144;
+        config.settings.fall_speed = 2.5;
+        config.settings.board_width = 16;
+        config.settings.board_height = 30;
+        config.settings.spawn_x = 3;
+        config.settings.big_mode = true;
+        config.settings.piece_set = String::from("pentominoes");
+        config.settings.animations = false;
+        config.controls.set_key(Action::MoveLeft, '"');
+        config.controls.set_key(Action::MoveRight, '\\');
+        config.controls.set_key(Action::Hold, '\t');
+        config.controls.set_key(Action::HardDrop, ' ');
+
+        assert_eq!(Config::parse(&config.write()).unwrap(), config);
+    }
+
+    #[test]
+    fn anything_left_out_keeps_its_default() {
+        let config = Config::parse(
+            "# Just the one setting.\n\
+             board_width = 20 # and a comment\n\
+             \n\
+             [controls]\n\
+             hard_drop = \"tab\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.settings.board_width, 20);
+        // The pieces come out in the middle, unless that's been set too.
+        assert_eq!(config.settings.spawn_x, 10);
+        assert_eq!(config.settings.frame_rate, Settings::default().frame_rate);
+        assert_eq!(config.controls.key(Action::HardDrop), '\t');
+        assert_eq!(config.controls.key(Action::MoveLeft), 'a');
+    }
+
+    #[test]
+    fn syntax_errors_point_at_their_line() {
+        assert_eq!(
+            parse_error("frame_rate = 60\nfall_speed\n"),
+            "line 2: expected 'key = value'"
+        );
+        assert_eq!(
+            parse_error("[graphics]\nframe_rate = 60\n"),
+            "line 1: unknown section 'graphics'"
+        );
+        assert_eq!(parse_error("[controls\n"), "line 1: expected ']'");
+        assert_eq!(
+            parse_error("piece_set = \"standard\n"),
+            "line 1: unterminated string"
+        );
+        assert_eq!(
+            parse_error("piece_set = \"a\\qb\"\n"),
+            "line 1: invalid escape sequence"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert_eq!(
+            parse_error("frame_rate = 60\n\nspeed = 3\n"),
+            "line 3: unknown setting 'speed'"
+        );
+        assert_eq!(
+            parse_error("spawn_y = 2\n"),
+            "line 1: unknown setting 'spawn_y'"
+        );
+        assert_eq!(
+            parse_error("[controls]\nteleport = \"t\"\n"),
+            "line 2: unknown action 'teleport'"
+        );
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        assert_eq!(
+            parse_error("frame_rate = fast\n"),
+            "line 1: invalid value for 'frame_rate': expected a whole number"
+        );
+        assert_eq!(
+            parse_error("big_mode = yes\n"),
+            "line 1: invalid value for 'big_mode': expected true or false"
+        );
+        assert_eq!(
+            parse_error("piece_set = 3\n"),
+            "line 1: invalid value for 'piece_set': expected a name in quotes"
+        );
+        assert_eq!(
+            parse_error("[controls]\nmove_left = \"j\"\nmove_right = \"j\"\n"),
+            "line 3: invalid value for 'move_right': that key is already used for 'move_left'"
+        );
+    }
+
+    #[test]
+    fn out_of_range_settings_are_blamed_on_their_line() {
+        assert_eq!(
+            parse_error("board_width = 10\nframe_rate = 5\n"),
+            "line 2: invalid value for 'frame_rate': must be between 10 and 240"
+        );
+        assert_eq!(
+            parse_error("spawn_x = 12\nboard_width = 10\n"),
+            "line 1: invalid value for 'spawn_x': must be between 1 and 10"
+        );
+        assert_eq!(
+            parse_error("piece_set = \"../nothing\"\n"),
+            "line 1: invalid value for 'piece_set': there's no piece set called '../nothing'"
+        );
+    }
+
+    #[test]
+    fn settings_are_checked_together() {
+        assert_eq!(validate_settings(&Settings::default()), Ok(()));
+
+        // Big mode needs twice the room.
+        let settings = Settings {
+            board_width: 6,
+            big_mode: true,
+            ..Settings::default()
+        };
+        assert_eq!(
+            validate_settings(&settings),
+            Err(("board_width", String::from("must be between 8 and 40")))
+        );
+
+        let settings = Settings {
+            fall_speed: 0.0,
+            ..Settings::default()
+        };
+        assert_eq!(validate_settings(&settings).unwrap_err().0, "fall_speed");
+
+        let settings = Settings {
+            line_clear_delay: 5000,
+            ..Settings::default()
+        };
+        assert_eq!(
+            validate_settings(&settings).unwrap_err().0,
+            "line_clear_delay"
+        );
+    }
+}
@@ -0,0 +1,770 @@
+// Reading and writing fumen, the format that pretty much everyone uses to share
+// boards. A fumen is something like "v115@vhAAgH": the version, and then a list
+// of pages packed into base 64. Every page has a board, a piece that's being
+// placed and a comment. To keep things short, a page only stores how its board
+// differs from the one that the previous page ends up with, and the comment is
+// only stored when it changes.
+//
+// Fumen boards are always 10 wide and 23 high, with one more row underneath for
+// garbage that can be pushed up into the board.
+
+use std::fmt;
+
+pub const FIELD_WIDTH: usize = 10;
+pub const FIELD_HEIGHT: usize = 23;
+// The board along with the garbage row.
+const FIELD_BLOCKS: usize = FIELD_WIDTH * (FIELD_HEIGHT + 1);
+
+const VERSION_PREFIX: &str = "v115@";
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// Comments get escaped first, so they only ever use these characters.
+const COMMENT_TABLE: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+// Four characters of a comment go into every five digits, counting in this
+// base. It's one more than the size of the table, for whatever reason.
+const COMMENT_BASE: usize = 96;
+const MAX_COMMENT_LENGTH: usize = 4095;
+
+// The pieces in the order that fumen numbers them, starting at 1. Garbage is X.
+const PIECES: [char; 8] = ['I', 'L', 'O', 'Z', 'T', 'J', 'S', 'X'];
+
+// Where the minos of each piece are, around the piece's center, when it's in
+// its spawn orientation (with y going up).
+const MINOS: [(char, [(i32, i32); 4]); 7] = [
+    ('I', [(0, 0), (-1, 0), (1, 0), (2, 0)]),
+    ('T', [(0, 0), (-1, 0), (1, 0), (0, 1)]),
+    ('O', [(0, 0), (1, 0), (0, 1), (1, 1)]),
+    ('L', [(0, 0), (-1, 0), (1, 0), (1, 1)]),
+    ('J', [(0, 0), (-1, 0), (1, 0), (-1, 1)]),
+    ('S', [(0, 0), (-1, 0), (0, 1), (1, 1)]),
+    ('Z', [(0, 0), (1, 0), (0, 1), (-1, 1)]),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rotation {
+    Spawn,
+    Right,
+    Reverse,
+    Left,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::Spawn,
+        Rotation::Right,
+        Rotation::Reverse,
+        Rotation::Left,
+    ];
+
+    // Fumen has its own order for these.
+    fn to_number(self) -> u32 {
+        match self {
+            Rotation::Reverse => 0,
+            Rotation::Right => 1,
+            Rotation::Spawn => 2,
+            Rotation::Left => 3,
+        }
+    }
+
+    fn from_number(number: u32) -> Rotation {
+        match number % 4 {
+            0 => Rotation::Reverse,
+            1 => Rotation::Right,
+            2 => Rotation::Spawn,
+            _ => Rotation::Left,
+        }
+    }
+}
+
+// A piece on a page. The position is where the center of the piece is, with
+// (0, 0) being the bottom left of the board.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Placement {
+    pub letter: char,
+    pub rotation: Rotation,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Placement {
+    // The cells that the piece takes up.
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        let minos = MINOS
+            .iter()
+            .find(|(letter, _)| *letter == self.letter)
+            .map_or(&[][..], |(_, minos)| &minos[..]);
+
+        minos
+            .iter()
+            .map(|&(x, y)| match self.rotation {
+                Rotation::Spawn => (x, y),
+                Rotation::Right => (y, -x),
+                Rotation::Reverse => (-x, -y),
+                Rotation::Left => (-y, x),
+            })
+            .map(|(x, y)| (self.x + x, self.y + y))
+            .collect()
+    }
+
+    // Works out how a piece was placed from the cells that it took up, if
+    // they're the shape of that piece at all.
+    pub fn from_cells(letter: char, cells: &[(i32, i32)]) -> Option<Placement> {
+        let mut cells = cells.to_vec();
+        cells.sort();
+
+        Rotation::ALL.iter().find_map(|rotation| {
+            // Try every cell as the center, since that's easier than working
+            // out which one it has to be.
+            cells.iter().find_map(|&(x, y)| {
+                let placement = Placement {
+                    letter,
+                    rotation: *rotation,
+                    x,
+                    y,
+                };
+
+                let mut placed = placement.cells();
+                placed.sort();
+
+                (placed == cells).then_some(placement)
+            })
+        })
+    }
+
+    // In the fumen itself, a few of the pieces are stored a cell off from
+    // their center.
+    fn offset(&self) -> (i32, i32) {
+        match (self.letter, self.rotation) {
+            ('O', Rotation::Left) => (1, -1),
+            ('O', Rotation::Reverse) => (1, 0),
+            ('O', Rotation::Spawn) => (0, -1),
+            ('I', Rotation::Reverse) => (1, 0),
+            ('I', Rotation::Left) => (0, -1),
+            ('S', Rotation::Spawn) => (0, -1),
+            ('S', Rotation::Right) => (-1, 0),
+            ('Z', Rotation::Spawn) => (0, -1),
+            ('Z', Rotation::Left) => (1, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Page {
+    // The board from the top down, FIELD_HEIGHT rows of FIELD_WIDTH cells.
+    // Every cell is the letter of a piece, or X for garbage.
+    pub field: Vec<Vec<Option<char>>>,
+    // The row under the board.
+    pub garbage: Vec<Option<char>>,
+    pub piece: Option<Placement>,
+    pub comment: String,
+    // Whether the piece gets put onto the board (and full lines cleared) for
+    // the next page.
+    pub lock: bool,
+    // Whether the garbage row gets pushed up into the board for the next page.
+    pub rise: bool,
+    // Whether the board gets flipped for the next page.
+    pub mirror: bool,
+}
+
+impl Page {
+    pub fn new(field: Vec<Vec<Option<char>>>) -> Page {
+        Page {
+            field,
+            garbage: vec![None; FIELD_WIDTH],
+            piece: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FumenError {
+    UnsupportedVersion,
+    // The data ended in the middle of a page.
+    Truncated,
+    InvalidCharacter(char),
+    Invalid(String),
+}
+
+impl fmt::Display for FumenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FumenError::UnsupportedVersion => write!(f, "only v115 fumen is supported"),
+            FumenError::Truncated => write!(f, "the fumen ends too early"),
+            FumenError::InvalidCharacter(character) => {
+                write!(f, "unexpected '{}' in the fumen", character)
+            }
+            FumenError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// The board as fumen numbers it: 0 is empty, then the pieces. It goes from the
+// top left to the bottom right, with the garbage row at the end.
+type Field = [u8; FIELD_BLOCKS];
+
+fn cell_number(cell: Option<char>) -> u8 {
+    cell.and_then(|cell| PIECES.iter().position(|letter| *letter == cell))
+        .map_or(0, |index| index as u8 + 1)
+}
+
+fn cell_letter(number: u8) -> Option<char> {
+    PIECES.get((number as usize).checked_sub(1)?).copied()
+}
+
+fn to_field(page: &Page) -> Field {
+    let mut field = [0; FIELD_BLOCKS];
+
+    page.field
+        .iter()
+        .chain(std::iter::once(&page.garbage))
+        .take(FIELD_HEIGHT + 1)
+        .enumerate()
+        .for_each(|(y, row)| {
+            row.iter()
+                .take(FIELD_WIDTH)
+                .enumerate()
+                .for_each(|(x, cell)| {
+                    field[y * FIELD_WIDTH + x] = cell_number(*cell);
+                })
+        });
+
+    field
+}
+
+fn from_field(field: &Field) -> (Vec<Vec<Option<char>>>, Vec<Option<char>>) {
+    let mut rows: Vec<Vec<Option<char>>> = field
+        .chunks(FIELD_WIDTH)
+        .map(|row| row.iter().map(|number| cell_letter(*number)).collect())
+        .collect();
+    let garbage = rows.pop().unwrap_or_default();
+
+    (rows, garbage)
+}
+
+// Where a cell of the board is in the field, with y going up from the bottom.
+fn field_index(x: i32, y: i32) -> Option<usize> {
+    if (0..FIELD_WIDTH as i32).contains(&x) && (0..FIELD_HEIGHT as i32).contains(&y) {
+        Some((FIELD_HEIGHT - 1 - y as usize) * FIELD_WIDTH + x as usize)
+    } else {
+        None
+    }
+}
+
+// What the board looks like once a page is done with it, which is what the
+// next page's board gets compared to.
+fn next_field(field: &Field, page: &Page) -> Field {
+    let mut field = *field;
+
+    if !page.lock {
+        return field;
+    }
+
+    if let Some(piece) = page.piece {
+        for (x, y) in piece.cells() {
+            if let Some(index) = field_index(x, y) {
+                field[index] = cell_number(Some(piece.letter));
+            }
+        }
+    }
+
+    // Clear the full rows, letting everything above them fall down.
+    let board = FIELD_WIDTH * FIELD_HEIGHT;
+    let rows: Vec<[u8; FIELD_WIDTH]> = field[..board]
+        .chunks(FIELD_WIDTH)
+        .filter(|row| row.contains(&0))
+        .map(|row| row.try_into().unwrap())
+        .collect();
+
+    field[..board].fill(0);
+    let top = board - rows.len() * FIELD_WIDTH;
+    for (i, row) in rows.iter().enumerate() {
+        field[top + i * FIELD_WIDTH..top + (i + 1) * FIELD_WIDTH].copy_from_slice(row);
+    }
+
+    if page.rise {
+        field.copy_within(FIELD_WIDTH.., 0);
+        field[board..].fill(0);
+    }
+
+    if page.mirror {
+        field[..board]
+            .chunks_mut(FIELD_WIDTH)
+            .for_each(|row| row.reverse());
+    }
+
+    field
+}
+
+// Reads the base 64 numbers out of the data, a few characters at a time.
+struct Reader {
+    values: Vec<u32>,
+    position: usize,
+}
+
+impl Reader {
+    fn is_empty(&self) -> bool {
+        self.position >= self.values.len()
+    }
+
+    // Numbers are stored with the lowest digit first.
+    fn read(&mut self, length: usize) -> Result<u32, FumenError> {
+        let digits = self
+            .values
+            .get(self.position..self.position + length)
+            .ok_or(FumenError::Truncated)?;
+        self.position += length;
+
+        Ok(digits
+            .iter()
+            .rev()
+            .fold(0, |value, digit| value * 64 + digit))
+    }
+}
+
+fn write_number(out: &mut String, mut value: u32, length: usize) {
+    for _ in 0..length {
+        out.push(ALPHABET[(value % 64) as usize] as char);
+        value /= 64;
+    }
+}
+
+// Comments are escaped the same way that JavaScript's escape() does it, since
+// that's what fumen was written in.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(character)
+                if character.is_ascii_alphanumeric() || "@*_+-./".contains(character) =>
+            {
+                escaped.push(character)
+            }
+            _ if unit < 0x100 => escaped += &format!("%{:02X}", unit),
+            _ => escaped += &format!("%u{:04X}", unit),
+        }
+    }
+
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let characters: Vec<char> = text.chars().collect();
+    let mut units = Vec::new();
+    let mut i = 0;
+
+    let hex = |digits: &[char]| {
+        let digits: String = digits.iter().collect();
+        u16::from_str_radix(&digits, 16).ok()
+    };
+
+    while i < characters.len() {
+        if characters[i] == '%' {
+            if characters.get(i + 1) == Some(&'u') {
+                if let Some(unit) = characters.get(i + 2..i + 6).and_then(hex) {
+                    units.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(unit) = characters.get(i + 1..i + 3).and_then(hex) {
+                units.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+
+        let mut buffer = [0; 2];
+        units.extend_from_slice(characters[i].encode_utf16(&mut buffer));
+        i += 1;
+    }
+
+    String::from_utf16_lossy(&units)
+}
+
+// Reads all of the pages out of a fumen. The fumen can also be the whole URL.
+pub fn decode(fumen: &str) -> Result<Vec<Page>, FumenError> {
+    let data = match fumen.trim().split_once(VERSION_PREFIX) {
+        Some((_, data)) => data,
+        None => return Err(FumenError::UnsupportedVersion),
+    };
+
+    let values = data
+        .chars()
+        .filter(|character| *character != '?')
+        .map(|character| {
+            ALPHABET
+                .iter()
+                .position(|other| *other as char == character)
+                .map(|value| value as u32)
+                .ok_or(FumenError::InvalidCharacter(character))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reader = Reader {
+        values,
+        position: 0,
+    };
+
+    let mut pages = Vec::new();
+    let mut previous_field: Field = [0; FIELD_BLOCKS];
+    let mut comment = String::new();
+    // How many of the next pages have the same board as the one before them.
+    let mut repeats = 0;
+
+    while !reader.is_empty() {
+        let mut field = previous_field;
+
+        if repeats > 0 {
+            repeats -= 1;
+        } else {
+            let mut index = 0;
+            let mut is_changed = true;
+
+            while index < FIELD_BLOCKS {
+                let value = reader.read(2)? as usize;
+                let (difference, count) = (value / FIELD_BLOCKS, value % FIELD_BLOCKS + 1);
+
+                if difference == 8 && count == FIELD_BLOCKS {
+                    is_changed = false;
+                }
+
+                for _ in 0..count {
+                    let cell = field
+                        .get_mut(index)
+                        .ok_or_else(|| FumenError::Invalid(String::from("the board is too big")))?;
+
+                    *cell = (*cell as usize + difference)
+                        .checked_sub(8)
+                        .filter(|cell| *cell <= PIECES.len())
+                        .ok_or_else(|| FumenError::Invalid(String::from("invalid block")))?
+                        as u8;
+                    index += 1;
+                }
+            }
+
+            if !is_changed {
+                repeats = reader.read(1)?;
+            }
+        }
+
+        let mut action = reader.read(3)?;
+        let mut take = |count: u32| {
+            let value = action % count;
+            action /= count;
+            value
+        };
+
+        let piece_number = take(8) as u8;
+        let rotation = Rotation::from_number(take(4));
+        let position = take(FIELD_BLOCKS as u32) as i32;
+        let rise = take(2) == 1;
+        let mirror = take(2) == 1;
+        let _colorize = take(2);
+        let has_comment = take(2) == 1;
+        let lock = take(2) == 0;
+
+        if has_comment {
+            let length = reader.read(2)? as usize;
+            let table: Vec<char> = COMMENT_TABLE.chars().collect();
+            let mut escaped = String::new();
+
+            for _ in 0..length.div_ceil(4) {
+                let mut value = reader.read(5)? as usize;
+
+                for _ in 0..4 {
+                    escaped.push(table.get(value % COMMENT_BASE).copied().unwrap_or(' '));
+                    value /= COMMENT_BASE;
+                }
+            }
+
+            comment = unescape(&escaped.chars().take(length).collect::<String>());
+        }
+
+        let piece = match cell_letter(piece_number) {
+            Some('X') | None => None,
+            Some(letter) => {
+                let mut piece = Placement {
+                    letter,
+                    rotation,
+                    x: position % FIELD_WIDTH as i32,
+                    y: FIELD_HEIGHT as i32 - position / FIELD_WIDTH as i32 - 1,
+                };
+                let (dx, dy) = piece.offset();
+                piece.x += dx;
+                piece.y += dy;
+
+                Some(piece)
+            }
+        };
+
+        let (rows, garbage) = from_field(&field);
+        let page = Page {
+            field: rows,
+            garbage,
+            piece,
+            comment: comment.clone(),
+            lock,
+            rise,
+            mirror,
+        };
+
+        previous_field = next_field(&field, &page);
+        pages.push(page);
+    }
+
+    if pages.is_empty() {
+        return Err(FumenError::Truncated);
+    }
+
+    Ok(pages)
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let fields: Vec<Field> = pages.iter().map(to_field).collect();
+
+    // The board that every page gets compared to.
+    let mut previous_fields = vec![[0; FIELD_BLOCKS]];
+    for (field, page) in fields.iter().zip(pages) {
+        previous_fields.push(next_field(field, page));
+    }
+
+    let mut data = String::new();
+    let mut repeats = 0;
+    let mut previous_comment = "";
+
+    for (i, page) in pages.iter().enumerate() {
+        let field = &fields[i];
+
+        if repeats > 0 {
+            repeats -= 1;
+        } else {
+            let differences: Vec<usize> = field
+                .iter()
+                .zip(previous_fields[i].iter())
+                .map(|(cell, previous)| *cell as usize + 8 - *previous as usize)
+                .collect();
+
+            // Runs of the same difference get stored together.
+            let mut start = 0;
+            while start < FIELD_BLOCKS {
+                let length = differences[start..]
+                    .iter()
+                    .take_while(|difference| **difference == differences[start])
+                    .count();
+
+                write_number(
+                    &mut data,
+                    (differences[start] * FIELD_BLOCKS + length - 1) as u32,
+                    2,
+                );
+                start += length;
+            }
+
+            // Pages with the same board as before are common enough that
+            // they get counted instead.
+            if differences.iter().all(|difference| *difference == 8) {
+                repeats = (i + 1..pages.len())
+                    .take_while(|j| fields[*j] == previous_fields[*j])
+                    .take(63)
+                    .count();
+
+                write_number(&mut data, repeats as u32, 1);
+            }
+        }
+
+        let (piece_number, rotation, position) = match page.piece {
+            Some(piece) => {
+                let (dx, dy) = piece.offset();
+                let (x, y) = (piece.x - dx, piece.y - dy);
+                let position = (FIELD_HEIGHT as i32 - y - 1) * FIELD_WIDTH as i32 + x;
+
+                (
+                    cell_number(Some(piece.letter)) as u32,
+                    piece.rotation.to_number(),
+                    position.clamp(0, FIELD_BLOCKS as i32 - 1) as u32,
+                )
+            }
+            None => (0, 0, 0),
+        };
+
+        let has_comment = page.comment != previous_comment;
+        let flags = [!page.lock, has_comment, i == 0, page.mirror, page.rise];
+        let action = flags
+            .iter()
+            .fold(0, |action, flag| action * 2 + *flag as u32);
+        let action = ((action * FIELD_BLOCKS as u32 + position) * 4 + rotation) * 8 + piece_number;
+
+        write_number(&mut data, action, 3);
+
+        if has_comment {
+            let escaped: Vec<char> = escape(&page.comment)
+                .chars()
+                .take(MAX_COMMENT_LENGTH)
+                .collect();
+
+            write_number(&mut data, escaped.len() as u32, 2);
+
+            for chunk in escaped.chunks(4) {
+                let value = chunk.iter().rev().fold(0, |value, character| {
+                    let index = COMMENT_TABLE.find(*character).unwrap_or(0);
+                    value * COMMENT_BASE as u32 + index as u32
+                });
+
+                write_number(&mut data, value, 5);
+            }
+
+            previous_comment = &page.comment;
+        }
+    }
+
+    // Fumen breaks the data up with question marks so that it wraps nicely. The
+    // first piece is shorter, to make up for the version in front of it.
+    let mut fumen = String::from(VERSION_PREFIX);
+    if data.len() <= 42 {
+        fumen += &data;
+    } else {
+        fumen += &data[..42];
+
+        for chunk in data.as_bytes()[42..].chunks(47) {
+            fumen.push('?');
+            fumen += std::str::from_utf8(chunk).unwrap();
+        }
+    }
+
+    fumen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_field() -> Vec<Vec<Option<char>>> {
+        vec![vec![None; FIELD_WIDTH]; FIELD_HEIGHT]
+    }
+
+    fn placed(letter: char, rotation: Rotation, x: i32, y: i32) -> Page {
+        Page {
+            piece: Some(Placement {
+                letter,
+                rotation,
+                x,
+                y,
+            }),
+            ..Page::new(empty_field())
+        }
+    }
+
+    #[test]
+    fn empty_page_round_trips() {
+        let pages = decode("v115@vhAAgH").unwrap();
+
+        assert_eq!(pages, vec![Page::new(empty_field())]);
+        assert_eq!(encode(&pages), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn whole_urls_can_be_decoded() {
+        let pages = decode("https://fumen.zui.jp/?v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn pages_with_pieces_round_trip() {
+        let bottom = FIELD_HEIGHT - 1;
+
+        // The T locks into the board, so it's there from the second page on.
+        let mut field = empty_field();
+        field[bottom][3..6].fill(Some('T'));
+        field[bottom - 1][4] = Some('T');
+
+        let mut garbage = vec![Some('X'); FIELD_WIDTH];
+        garbage[3] = None;
+
+        let pages = vec![
+            placed('T', Rotation::Spawn, 4, 0),
+            Page {
+                field: field.clone(),
+                lock: false,
+                ..placed('I', Rotation::Right, 0, 5)
+            },
+            Page {
+                field: field.clone(),
+                garbage,
+                rise: true,
+                ..placed('O', Rotation::Spawn, 8, 1)
+            },
+            Page {
+                field,
+                mirror: true,
+                ..placed('S', Rotation::Left, 6, 3)
+            },
+        ];
+
+        let fumen = encode(&pages);
+        assert_eq!(decode(&fumen).unwrap(), pages);
+    }
+
+    #[test]
+    fn comments_round_trip() {
+        let pages = vec![
+            Page {
+                comment: String::from("Hello, world! 100% テトリス"),
+                ..Page::new(empty_field())
+            },
+            // The comment carries on to the next page unless it changes.
+            Page {
+                comment: String::from("Hello, world! 100% テトリス"),
+                ..placed('L', Rotation::Reverse, 4, 1)
+            },
+            placed('J', Rotation::Spawn, 4, 3),
+        ];
+
+        let fumen = encode(&pages);
+        let decoded = decode(&fumen).unwrap();
+
+        assert_eq!(decoded[0].comment, pages[0].comment);
+        assert_eq!(decoded[1].comment, pages[1].comment);
+        assert_eq!(decoded[2].comment, "");
+        assert_eq!(encode(&decoded), fumen);
+    }
+
+    #[test]
+    fn bad_prefixes_are_rejected() {
+        assert!(matches!(
+            decode("v110@vhAAgH"),
+            Err(FumenError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            decode("vhAAgH"),
+            Err(FumenError::UnsupportedVersion)
+        ));
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        assert!(matches!(decode("v115@"), Err(FumenError::Truncated)));
+        assert!(matches!(decode("v115@vhAA"), Err(FumenError::Truncated)));
+        assert!(matches!(decode("v115@jf"), Err(FumenError::Truncated)));
+    }
+
+    #[test]
+    fn bad_characters_are_rejected() {
+        assert!(matches!(
+            decode("v115@vh!AgH"),
+            Err(FumenError::InvalidCharacter('!'))
+        ));
+    }
+
+    // A run of 100 cells followed by one of 240 goes past the end of the board.
+    #[test]
+    fn boards_that_are_too_big_are_rejected() {
+        assert!(matches!(
+            decode("v115@jfvhAAgH"),
+            Err(FumenError::Invalid(message)) if message == "the board is too big"
+        ));
+    }
+}
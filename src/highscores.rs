@@ -0,0 +1,577 @@
+// The high score table. It gets saved in the data directory so that the scores are
+// still around the next time the game is started.
+//
+// The file is plain text so that it can be looked at (and, fine, edited) by hand.
+// It has a section for every game mode, with one entry per line:
+//
+//     tetris-cli-highscores 2
+//     [marathon]
+//     <score>\t<lines>\t<level>\t<time in ms>\t<date as a UNIX timestamp>\t<splits>\t<name>
+//
+// The splits are the times (in ms) of every split in modes that have them, like
+// Sprint, separated by commas. Version 1 files didn't have them at all.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::tetris::{self, GameMode, Ranking};
+
+const HIGH_SCORES_HEADER: &str = "tetris-cli-highscores";
+const HIGH_SCORES_VERSION: u32 = 2;
+const HIGH_SCORES_FILE: &str = "highscores";
+
+// The number of entries that are kept for each game mode.
+pub const MAX_ENTRIES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 12;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    pub time_ms: u64,
+    // When the score was set, in seconds since the UNIX epoch.
+    pub date: u64,
+    pub splits: Vec<u64>,
+}
+
+impl Entry {
+    fn parse(line: &str, version: u32) -> Option<Entry> {
+        let field_count = if version < 2 { 6 } else { 7 };
+        let mut fields = line.splitn(field_count, '\t');
+
+        let entry = Entry {
+            score: fields.next()?.parse().ok()?,
+            lines: fields.next()?.parse().ok()?,
+            level: fields.next()?.parse().ok()?,
+            time_ms: fields.next()?.parse().ok()?,
+            date: fields.next()?.parse().ok()?,
+            splits: if version < 2 {
+                Vec::new()
+            } else {
+                tetris::times_from_string(fields.next()?).ok()?
+            },
+            name: fields.next()?.to_string(),
+        };
+
+        if entry.name.is_empty() || entry.name.chars().count() > MAX_NAME_LENGTH {
+            return None;
+        }
+
+        Some(entry)
+    }
+
+    // Whether this entry belongs above another one on the table.
+    fn beats(&self, other: &Entry, ranking: Ranking) -> bool {
+        match ranking {
+            Ranking::Score => self.score > other.score,
+            Ranking::Time => self.time_ms < other.time_ms,
+            Ranking::Unranked => false,
+        }
+    }
+}
+
+// Modes that we don't know about (from a newer version, say) are sorted by score.
+fn ranking_of(mode: &str) -> Ranking {
+    GameMode::from_key(mode)
+        .map(|mode| mode.rules().ranking)
+        .unwrap_or(Ranking::Score)
+}
+
+pub struct HighScores {
+    path: PathBuf,
+    tables: BTreeMap<String, Vec<Entry>>,
+}
+
+impl HighScores {
+    // Loads the high scores from the data directory. If that doesn't exist, the
+    // table simply stays empty and never gets saved.
+    pub fn load_default() -> (HighScores, Option<String>) {
+        match crate::paths::data_dir() {
+            Some(directory) => HighScores::load(&directory.join(HIGH_SCORES_FILE)),
+            None => (
+                HighScores {
+                    path: PathBuf::new(),
+                    tables: BTreeMap::new(),
+                },
+                None,
+            ),
+        }
+    }
+
+    // Loads the high scores from a file. This never fails: anything that can't be
+    // read is skipped, and if the file turns out to be damaged, a copy of it is
+    // put aside (as <file>.corrupt) before it gets overwritten on the next save.
+    // Along with the scores comes a warning for the player if any of that
+    // happened, since the screen is already up by now and can't be printed to.
+    pub fn load(path: &Path) -> (HighScores, Option<String>) {
+        let mut high_scores = HighScores {
+            path: path.to_path_buf(),
+            tables: BTreeMap::new(),
+        };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return (high_scores, None),
+            Err(error) => {
+                high_scores.back_up_corrupted_file();
+                let warning = format!("Failed to read the high scores: {}", error);
+                return (high_scores, Some(warning));
+            }
+        };
+
+        let mut is_corrupted = false;
+        let mut lines = contents.lines();
+
+        let version = match lines.next().and_then(|header| header.split_once(' ')) {
+            Some((HIGH_SCORES_HEADER, version)) => version.parse().ok(),
+            _ => None,
+        };
+
+        let version = match version {
+            Some(version) if version <= HIGH_SCORES_VERSION => version,
+            _ => {
+                is_corrupted = true;
+                HIGH_SCORES_VERSION
+            }
+        };
+
+        let mut current_table: Option<&mut Vec<Entry>> = None;
+
+        for line in lines {
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(mode) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                current_table = Some(high_scores.tables.entry(mode.to_string()).or_default());
+                continue;
+            }
+
+            match (current_table.as_mut(), Entry::parse(line, version)) {
+                (Some(table), Some(entry)) => table.push(entry),
+                _ => is_corrupted = true,
+            }
+        }
+
+        // Just in case the file has been messed with, make sure that the tables
+        // are still sorted and not too long.
+        high_scores.tables.iter_mut().for_each(|(mode, table)| {
+            match ranking_of(mode) {
+                Ranking::Score => table.sort_by_key(|entry| std::cmp::Reverse(entry.score)),
+                Ranking::Time => table.sort_by_key(|entry| entry.time_ms),
+                Ranking::Unranked => (),
+            }
+
+            table.truncate(MAX_ENTRIES);
+        });
+
+        let warning = if is_corrupted {
+            high_scores.back_up_corrupted_file()
+        } else {
+            None
+        };
+
+        (high_scores, warning)
+    }
+
+    // Returns what to tell the player if the copy was made.
+    fn back_up_corrupted_file(&self) -> Option<String> {
+        let mut backup_path = self.path.clone().into_os_string();
+        backup_path.push(".corrupt");
+
+        fs::copy(&self.path, &backup_path).ok()?;
+
+        Some(format!(
+            "The high scores were damaged, a copy was saved to {}",
+            Path::new(&backup_path).display()
+        ))
+    }
+
+    pub fn entries(&self, mode: GameMode) -> &[Entry] {
+        self.tables
+            .get(mode.key())
+            .map(|table| table.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // Whether an entry is good enough to make it onto the table. In modes that
+    // are about time, it's up to the caller to only offer games that were
+    // actually finished.
+    pub fn qualifies(&self, mode: GameMode, entry: &Entry) -> bool {
+        let ranking = mode.rules().ranking;
+        let entries = self.entries(mode);
+
+        let is_valid = match ranking {
+            Ranking::Score => entry.score > 0,
+            Ranking::Time => entry.time_ms > 0,
+            Ranking::Unranked => false,
+        };
+
+        is_valid
+            && (entries.len() < MAX_ENTRIES
+                || entries
+                    .iter()
+                    .any(|existing| entry.beats(existing, ranking)))
+    }
+
+    // The splits of the best entry of a mode, which is what the splits of a
+    // new game get compared against.
+    pub fn personal_best_splits(&self, mode: GameMode) -> Vec<u64> {
+        self.entries(mode)
+            .first()
+            .map(|entry| entry.splits.clone())
+            .unwrap_or_default()
+    }
+
+    // Puts an entry onto the table, and returns where it ended up (starting from
+    // zero) if it made it.
+    pub fn insert(&mut self, mode: GameMode, entry: Entry) -> Option<usize> {
+        if !self.qualifies(mode, &entry) {
+            return None;
+        }
+
+        let ranking = mode.rules().ranking;
+        let table = self.tables.entry(mode.key().to_string()).or_default();
+
+        // Ties go below the existing entries. First come, first served.
+        let rank = table
+            .iter()
+            .position(|existing| entry.beats(existing, ranking))
+            .unwrap_or(table.len());
+
+        table.insert(rank, entry);
+        table.truncate(MAX_ENTRIES);
+
+        Some(rank)
+    }
+
+    // Saves the high scores. This writes to a temporary file and then moves it
+    // over the real one so that the table can't be left half-written if the game
+    // gets killed at the wrong moment.
+    pub fn save(&self) -> io::Result<()> {
+        if self.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let mut contents = format!("{} {}\n", HIGH_SCORES_HEADER, HIGH_SCORES_VERSION);
+
+        for (mode, table) in self.tables.iter() {
+            contents += &format!("[{}]\n", mode);
+
+            for entry in table.iter() {
+                contents += &format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    entry.score,
+                    entry.lines,
+                    entry.level,
+                    entry.time_ms,
+                    entry.date,
+                    tetris::times_to_string(&entry.splits),
+                    entry.name
+                );
+            }
+        }
+
+        let mut temporary_path = self.path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        fs::rename(&temporary_path, &self.path)
+    }
+}
+
+// Turns a name that the player typed in into something that is safe to put in
+// the file.
+pub fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|character| !character.is_control())
+        .take(MAX_NAME_LENGTH)
+        .collect();
+
+    let name = name.trim();
+
+    if name.is_empty() {
+        String::from("Player")
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn now() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// Formats a UNIX timestamp as YYYY-MM-DD (in UTC). We don't want to pull in a
+// whole date library just for this, so this is Howard Hinnant's civil_from_days
+// algorithm.
+pub fn format_date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64 + 719468;
+
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32, time_ms: u64) -> Entry {
+        Entry {
+            name: name.to_string(),
+            score,
+            lines: 40,
+            level: 5,
+            time_ms,
+            date: 1700000000,
+            splits: Vec::new(),
+        }
+    }
+
+    fn empty() -> HighScores {
+        HighScores {
+            path: PathBuf::new(),
+            tables: BTreeMap::new(),
+        }
+    }
+
+    // A file in the temporary directory that's gone (along with its backup)
+    // once the test is done with it.
+    struct TemporaryFile(PathBuf);
+
+    impl TemporaryFile {
+        fn new(name: &str) -> TemporaryFile {
+            let file_name = format!("tetris-cli-{}-{}", std::process::id(), name);
+            TemporaryFile(std::env::temp_dir().join(file_name))
+        }
+
+        fn backup(&self) -> PathBuf {
+            let mut path = self.0.clone().into_os_string();
+            path.push(".corrupt");
+            PathBuf::from(path)
+        }
+    }
+
+    impl Drop for TemporaryFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(self.backup());
+        }
+    }
+
+    #[test]
+    fn entries_of_both_versions_parse() {
+        assert_eq!(
+            Entry::parse("1200\t40\t5\t65432\t1700000000\tOld timer", 1),
+            Some(Entry {
+                score: 1200,
+                time_ms: 65432,
+                ..entry("Old timer", 0, 0)
+            })
+        );
+
+        assert_eq!(
+            Entry::parse("0\t40\t5\t61000\t1700000000\t15000,31000\tSpeedy", 2),
+            Some(Entry {
+                splits: vec![15000, 31000],
+                ..entry("Speedy", 0, 61000)
+            })
+        );
+
+        assert_eq!(
+            Entry::parse("10\t1\t1\t500\t1700000000\t-\tNo splits", 2).map(|entry| entry.splits),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn bad_entries_are_rejected() {
+        // A version 2 line without the splits.
+        assert_eq!(Entry::parse("10\t1\t1\t500\t1700000000\tName", 2), None);
+
+        assert_eq!(Entry::parse("ten\t1\t1\t500\t1700000000\t-\tName", 2), None);
+        assert_eq!(
+            Entry::parse("10\t1\t1\t500\t1700000000\t1,x\tName", 2),
+            None
+        );
+        assert_eq!(Entry::parse("10\t1\t1\t500\t1700000000\t-\t", 2), None);
+        assert_eq!(
+            Entry::parse("10\t1\t1\t500\t1700000000\t-\tThis name is too long", 2),
+            None
+        );
+    }
+
+    #[test]
+    fn only_good_enough_entries_qualify() {
+        let mut high_scores = empty();
+
+        assert!(!high_scores.qualifies(GameMode::Marathon, &entry("Zero", 0, 1000)));
+        assert!(!high_scores.qualifies(GameMode::Sprint, &entry("Zero", 100, 0)));
+        assert!(!high_scores.qualifies(GameMode::Zen, &entry("Zen", 100, 1000)));
+
+        for score in 1..=MAX_ENTRIES as u32 {
+            high_scores.insert(GameMode::Marathon, entry("Player", score * 100, 1000));
+        }
+
+        assert!(!high_scores.qualifies(GameMode::Marathon, &entry("Low", 100, 1000)));
+        assert!(high_scores.qualifies(GameMode::Marathon, &entry("High", 101, 1000)));
+        assert!(high_scores.qualifies(GameMode::Ultra, &entry("First", 1, 1000)));
+    }
+
+    #[test]
+    fn inserting_keeps_the_table_in_order() {
+        let mut high_scores = empty();
+
+        assert_eq!(
+            high_scores.insert(GameMode::Marathon, entry("A", 500, 0)),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.insert(GameMode::Marathon, entry("B", 900, 0)),
+            Some(0)
+        );
+        // Ties go below the score that was there first.
+        assert_eq!(
+            high_scores.insert(GameMode::Marathon, entry("C", 500, 0)),
+            Some(2)
+        );
+
+        let names: Vec<&str> = high_scores
+            .entries(GameMode::Marathon)
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["B", "A", "C"]);
+
+        // Lower is better when it comes to time.
+        assert_eq!(
+            high_scores.insert(GameMode::Sprint, entry("Slow", 0, 90000)),
+            Some(0)
+        );
+        assert_eq!(
+            high_scores.insert(GameMode::Sprint, entry("Fast", 0, 60000)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn tables_stop_at_the_maximum() {
+        let mut high_scores = empty();
+
+        for time_ms in 1..=MAX_ENTRIES as u64 + 5 {
+            high_scores.insert(GameMode::Sprint, entry("Player", 0, time_ms * 1000));
+        }
+
+        let entries = high_scores.entries(GameMode::Sprint);
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries.last().unwrap().time_ms, MAX_ENTRIES as u64 * 1000);
+
+        assert_eq!(
+            high_scores.insert(GameMode::Sprint, entry("Late", 0, 999999)),
+            None
+        );
+        assert_eq!(
+            high_scores.insert(GameMode::Sprint, entry("Early", 0, 500)),
+            Some(0)
+        );
+        assert_eq!(high_scores.entries(GameMode::Sprint).len(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn saved_scores_load_back_in() {
+        let file = TemporaryFile::new("highscores-round-trip");
+
+        let mut high_scores = empty();
+        high_scores.path = file.0.clone();
+        high_scores.insert(GameMode::Marathon, entry("Mara Thon", 1500, 123456));
+        high_scores.insert(GameMode::Marathon, entry("Second", 700, 99999));
+        high_scores.insert(
+            GameMode::Sprint,
+            Entry {
+                splits: vec![20000, 41000, 62000],
+                ..entry("Sprinter", 0, 62000)
+            },
+        );
+        high_scores.save().unwrap();
+
+        let (loaded, warning) = HighScores::load(&file.0);
+        assert_eq!(warning, None);
+        assert_eq!(loaded.tables, high_scores.tables);
+        assert!(!file.backup().exists());
+    }
+
+    #[test]
+    fn damaged_files_get_backed_up() {
+        let file = TemporaryFile::new("highscores-damaged");
+        let contents = "tetris-cli-highscores 1\n\
+                        [marathon]\n\
+                        300\t10\t2\t50000\t1700000000\tKept\n\
+                        this line is broken\n\
+                        900\t30\t4\t90000\t1700000000\tAlso kept\n";
+        fs::write(&file.0, contents).unwrap();
+
+        let (loaded, warning) = HighScores::load(&file.0);
+        assert!(warning.unwrap().contains(".corrupt"));
+        assert_eq!(fs::read_to_string(file.backup()).unwrap(), contents);
+
+        // What could be read is still there, and in order.
+        let names: Vec<&str> = loaded
+            .entries(GameMode::Marathon)
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["Also kept", "Kept"]);
+    }
+
+    #[test]
+    fn files_from_newer_versions_count_as_damaged() {
+        let file = TemporaryFile::new("highscores-newer");
+        fs::write(&file.0, "tetris-cli-highscores 3\n[marathon]\n").unwrap();
+
+        let (_, warning) = HighScores::load(&file.0);
+        assert!(warning.is_some());
+        assert!(file.backup().exists());
+    }
+
+    #[test]
+    fn missing_files_are_just_empty() {
+        let file = TemporaryFile::new("highscores-missing");
+
+        let (loaded, warning) = HighScores::load(&file.0);
+        assert_eq!(warning, None);
+        assert!(loaded.tables.is_empty());
+    }
+}
@@ -1,50 +1,292 @@
+mod app;
+mod config;
+mod fumen;
+mod highscores;
+mod menu;
+mod paths;
+mod pieces;
+mod puzzle;
+mod renderer;
+mod replay;
+mod save;
 mod screen;
+#[cfg(test)]
+mod snapshot_tests;
 mod system;
 mod tetris;
+mod unicode;
 
+use std::path::Path;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
+use app::App;
+use config::Config;
+use renderer::MemoryRenderer;
+use replay::Replay;
+use save::SavedGame;
+use screen::Screen;
+use tetris::{Action, GameMode, Tetris};
+
+// The default frame rate. The menus always run at this speed, but the game
+// itself uses whatever is in the config.
 const FRAME_RATE: u8 = 60;
 
-// Unicode literals that might be useful in future.
-mod unicode {
-    pub const FULL_BLOCK: char = '\u{2588}';
-    pub const LIGHT_SHADE: char = '\u{2591}';
+// Sleeps for whatever is left of the current frame.
+pub fn wait_for_next_frame(start: Instant, frame_rate: u8) {
+    let elapsed_time = start.elapsed();
 
-    // Basic box drawing.
-    pub const BOX_DRAWINGS_LIGHT_HORIZONTAL: char = '\u{2500}';
-    pub const BOX_DRAWINGS_LIGHT_VERTICAL: char = '\u{2502}';
+    // If the uncapped framerate is less than 30, then we simply
+    // leave it be. There's nothing we can do about that here.
+    if elapsed_time.as_millis() > 1000 / frame_rate as u128 {
+        return;
+    }
 
-    // Box drawing corners.
-    pub const BOX_DRAWINGS_LIGHT_DOWN_AND_RIGHT: char = '\u{250C}';
-    pub const BOX_DRAWINGS_LIGHT_DOWN_AND_LEFT: char = '\u{2510}';
-    pub const BOX_DRAWINGS_LIGHT_UP_AND_RIGHT: char = '\u{2514}';
-    pub const BOX_DRAWINGS_LIGHT_UP_AND_LEFT: char = '\u{2518}';
+    let target_frame_duration = Duration::from_millis(1000 / frame_rate as u64);
+    if target_frame_duration > elapsed_time {
+        let wait_duration = target_frame_duration - elapsed_time;
+        thread::sleep(wait_duration);
+    }
 }
 
-fn main() {
-    let mut game = tetris::Tetris::new().expect("Uh oh");
+// How a game came to an end.
+enum Ending {
+    // The board filled up, or the goal of the mode was reached. The replay
+    // still has to be finished.
+    Finished(Tetris, Replay),
+    // The player quit (or we were told to stop) in the middle of the game, so
+    // it should be saved for later.
+    Suspended(SavedGame),
+}
 
-    while game.is_running() {
+// Plays a game until it either ends or gets suspended. The replay has to belong
+// to the game, since the inputs get added to it. If a path is given, the replay
+// is saved there at the end.
+fn play(
+    screen: &mut Screen,
+    config: &Config,
+    mut game: Tetris,
+    mut replay: Replay,
+    record_path: Option<&Path>,
+) -> Ending {
+    save::catch_termination();
+
+    while game.is_running() && !save::termination_requested() {
         let start = Instant::now();
 
-        game.update();
-        game.render();
+        let input = screen
+            .read_input()
+            .ok()
+            .and_then(|key| config.controls.action_for(key));
+
+        // Quitting isn't recorded, since it only suspends the game. The replay
+        // ends at the last frame anyways.
+        if let Some(action) = input {
+            if action != Action::Quit {
+                replay.record(game.frame(), action);
+            }
+        }
+
+        game.update(input);
+        game.render(screen);
+        game.render_controls(screen, &config.controls);
+        screen.present();
+
+        wait_for_next_frame(start, replay.settings.frame_rate);
+    }
+
+    save::release_termination();
+
+    if let Some(path) = record_path {
+        replay.finish(&game);
+
+        if let Err(error) = replay.save(path) {
+            eprintln!("Failed to save the replay to {}: {}", path.display(), error);
+        }
+    }
+
+    if game.has_ended() {
+        Ending::Finished(game, replay)
+    } else {
+        Ending::Suspended(SavedGame { game, replay })
+    }
+}
+
+// Sets up a brand new game, along with the replay that goes with it.
+fn new_game(config: &Config, mode: GameMode) -> (Tetris, Replay) {
+    let mut settings = config.settings.clone();
+
+    if !mode.rules().selectable_start_level {
+        settings.start_level = 1;
+    }
+
+    let seed = tetris::random_seed();
+    let game = Tetris::new(seed, mode, &settings);
+    let replay = Replay::new(seed, mode, &settings);
+
+    (game, replay)
+}
+
+// Plays back a replay until the player quits, and returns whether it matched
+// the original game (if it got that far).
+fn play_replay(screen: &mut Screen, replay: Replay) -> Option<replay::Verification> {
+    let frame_rate = replay.settings.frame_rate;
+    let mut player = replay::Player::new(replay);
+
+    // There's nothing to save, but the terminal still has to be put back the
+    // way it was if we get told to stop.
+    save::catch_termination();
+
+    while player.is_running() && !save::termination_requested() {
+        let start = Instant::now();
+
+        if let Ok(key) = screen.read_input() {
+            player.handle_key(key);
+        }
+
+        player.update();
+        player.render(screen);
+        screen.present();
+
+        wait_for_next_frame(start, frame_rate);
+    }
+
+    save::release_termination();
 
-        let elapsed_time = start.elapsed();
+    player.verify()
+}
+
+fn create_screen(settings: &tetris::Settings) -> Screen {
+    let (width, height) = settings.screen_size();
+    Screen::new(width, height).expect("Uh oh")
+}
+
+// Makes sure that the screen is the right size for a game with these settings.
+fn fit_screen(screen: &mut Screen, settings: &tetris::Settings) {
+    let (width, height) = settings.screen_size();
+
+    if screen.width() != width || screen.height() != height {
+        screen.resize(width, height);
+    }
+}
 
-        // If the uncapped framerate is less than 30, then we simply
-        // leave it be. There's nothing we can do about that here.
-        if elapsed_time.as_millis() > 1000 / FRAME_RATE as u128 {
-            continue;
+fn load_replay(path: &str) -> Replay {
+    match Replay::load(Path::new(path)) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("Failed to load the replay {}: {}", path, error);
+            std::process::exit(1);
         }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let config = match Config::load_default() {
+        Ok(config) => config,
+        Err(error) => {
+            let path = config::config_path().unwrap_or_default();
+            eprintln!("Invalid config file {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    };
+
+    match args.get(1).map(String::as_str) {
+        None => App::new(config).run(),
+        Some("--record") if args.len() == 3 => {
+            let mut screen = create_screen(&config.settings);
+            let record_path = Path::new(&args[2]);
+
+            let (game, replay) = new_game(&config, GameMode::Marathon);
+            let ending = play(&mut screen, &config, game, replay, Some(record_path));
 
-        let target_frame_duration = Duration::from_millis(1000 / FRAME_RATE as u64);
-        if target_frame_duration > elapsed_time {
-            let wait_duration = target_frame_duration - elapsed_time;
-            thread::sleep(wait_duration);
+            if let Ending::Suspended(saved_game) = ending {
+                if let Err(error) = saved_game.save() {
+                    eprintln!("Failed to save the game: {}", error);
+                }
+            }
+        }
+        Some("--replay") if args.len() == 3 => {
+            let replay = load_replay(&args[2]);
+            let mut screen = create_screen(&replay.settings);
+
+            if let Some(verification) = play_replay(&mut screen, replay) {
+                println!("{}", verification);
+            }
+        }
+        // Plays a replay to the end without showing it, and prints out how the
+        // board ended up.
+        Some("--print-replay") if args.len() == 3 => {
+            let replay = load_replay(&args[2]);
+
+            let renderer = MemoryRenderer::default();
+            let frames = renderer.frames();
+
+            let (width, height) = replay.settings.screen_size();
+            let mut screen =
+                Screen::with_renderer(width, height, Box::new(renderer)).expect("Uh oh");
+
+            let mut player = replay::Player::new(replay);
+            while player.verify().is_none() {
+                player.update();
+            }
+
+            player.render(&mut screen);
+            screen.present();
+
+            if let Some(frame) = frames.borrow().last() {
+                println!("{}", frame);
+            }
+
+            if let Some(verification) = player.verify() {
+                println!("{}", verification);
+            }
+        }
+        Some("--fumen") if args.len() == 3 => {
+            let pages = match fumen::decode(&args[2]) {
+                Ok(pages) => pages,
+                Err(error) => {
+                    eprintln!("Invalid fumen: {}", error);
+                    std::process::exit(1);
+                }
+            };
+
+            // Fumen boards are always 10 wide, with the standard pieces.
+            let settings = tetris::Settings {
+                board_width: fumen::FIELD_WIDTH as u32,
+                spawn_x: fumen::FIELD_WIDTH as u16 / 2,
+                big_mode: false,
+                piece_set: String::from("standard"),
+                ..config.settings.clone()
+            };
+
+            let seed = tetris::random_seed();
+            let game = Tetris::from_fumen(&pages, seed, &settings);
+            let replay = Replay::new(seed, GameMode::Zen, &settings);
+
+            // The game doesn't get saved if the player quits, since the board
+            // didn't come from the seed.
+            let mut screen = create_screen(&settings);
+            let ending = play(&mut screen, &config, game, replay, None);
+            drop(screen);
+
+            let mut game = match ending {
+                Ending::Finished(game, _) => game,
+                Ending::Suspended(saved_game) => saved_game.game,
+            };
+
+            for fumen in game.take_exports() {
+                println!("{}", fumen);
+            }
+        }
+        _ => {
+            eprintln!(
+                "Usage: {} [--record <file> | --replay <file> | --print-replay <file> | --fumen <fumen>]",
+                args[0]
+            );
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,1037 @@
+// All of the screens that aren't the game itself: the main menu, the high score
+// table and so on.
+
+use crate::config::{
+    validate_settings, Config, BOARD_HEIGHT_RANGE, BOARD_WIDTH_RANGE, FALL_SPEED_RANGE,
+    FRAME_RATE_RANGE, GARBAGE_ROWS_RANGE, LINE_CLEAR_DELAY_RANGE, PRACTICE_GRAVITY_RANGE,
+    START_LEVEL_RANGE,
+};
+use crate::highscores::{self, HighScores, MAX_NAME_LENGTH};
+use crate::pieces;
+use crate::puzzle::{self, Objective, Puzzle, PuzzleError};
+use crate::replay::{Replay, ReplayError};
+use crate::screen::{
+    Align, Color, Constraint, Label, List, Menu, Panel, Pixel, Rect, Screen, Split, Widget,
+};
+use crate::tetris::{self, Action, GameMode, Ranking, Settings, Tetris, GAME_HEIGHT};
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+// Runs a screen until it decides that it's done. The closure gets called once
+// every frame with whatever key was pressed, draws the screen, and returns
+// Some(...) once it wants to exit.
+fn run<T>(screen: &mut Screen, mut frame: impl FnMut(&mut Screen, Option<char>) -> Option<T>) -> T {
+    loop {
+        let start = Instant::now();
+
+        let input = screen.read_input().ok();
+
+        screen.clear();
+        let result = frame(screen, input);
+        screen.present();
+
+        if let Some(result) = result {
+            return result;
+        }
+
+        crate::wait_for_next_frame(start, crate::FRAME_RATE);
+    }
+}
+
+// Draws a line of text in the middle of the screen.
+fn draw_centered(screen: &mut Screen, y: u32, text: &str) {
+    let screen_width = screen.width();
+    screen.draw_aligned_text(0, y, screen_width, text, Color::Default, Align::Center);
+}
+
+// Moves a selection up or down through a list, wrapping around at the ends.
+fn move_selection(selected: usize, count: usize, key: char) -> usize {
+    match key {
+        'w' | 'k' => (selected + count - 1) % count,
+        's' | 'j' => (selected + 1) % count,
+        _ => selected,
+    }
+}
+
+fn is_confirm_key(key: char) -> bool {
+    key == '\n' || key == '\r' || key == ' '
+}
+
+// The first thing that is shown when the game starts. Returns false if the
+// player wants to quit right away.
+pub fn title_screen(screen: &mut Screen) -> bool {
+    run(screen, |screen, input| {
+        match input {
+            Some('q') | Some('\x1B') => return Some(false),
+            Some(_) => return Some(true),
+            None => (),
+        }
+
+        draw_centered(screen, 8, "T E T R I S");
+        draw_centered(screen, 14, "Press any key to start");
+        draw_centered(screen, 20, "q to quit");
+
+        None
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MainMenuChoice {
+    Continue,
+    Play,
+    Puzzles,
+    HighScores,
+    Replays,
+    Settings,
+    Quit,
+}
+
+// The main menu. "Continue" is only there if there is a saved game, and the
+// message (if there is one) is shown at the bottom.
+pub fn main_menu(
+    screen: &mut Screen,
+    can_continue: bool,
+    message: Option<String>,
+) -> MainMenuChoice {
+    let mut choices = vec![
+        ("Play", MainMenuChoice::Play),
+        ("Puzzles", MainMenuChoice::Puzzles),
+        ("High Scores", MainMenuChoice::HighScores),
+        ("Replays", MainMenuChoice::Replays),
+        ("Settings", MainMenuChoice::Settings),
+        ("Quit", MainMenuChoice::Quit),
+    ];
+
+    if can_continue {
+        choices.insert(0, ("Continue", MainMenuChoice::Continue));
+    }
+
+    let mut selected = 0;
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            selected = move_selection(selected, choices.len(), key);
+
+            if is_confirm_key(key) {
+                return Some(choices[selected].1);
+            }
+
+            if key == 'q' {
+                return Some(MainMenuChoice::Quit);
+            }
+        }
+
+        draw_centered(screen, 4, "T E T R I S");
+
+        let menu = Menu {
+            items: choices.iter().map(|(label, _)| label.to_string()).collect(),
+            selected,
+            spacing: 2,
+        };
+
+        let area = screen.area();
+        menu.draw(screen, Rect { y: 8, ..area });
+
+        if let Some(message) = message.as_ref() {
+            draw_centered(screen, 21, message);
+        }
+
+        draw_centered(screen, 23, "w/s to move, [ENTER] to select");
+
+        None
+    })
+}
+
+// Lets the player pick what kind of game to play, along with whatever options
+// that mode has (like the start level in Marathon). The options are changed in
+// a copy of the settings, which is given back with the mode. Returns None if
+// they backed out to the main menu.
+pub fn mode_select(
+    screen: &mut Screen,
+    mode: GameMode,
+    settings: &Settings,
+) -> Option<(GameMode, Settings)> {
+    let mut selected = GameMode::ALL
+        .iter()
+        .position(|other| *other == mode)
+        .unwrap_or(0);
+    let mut settings = settings.clone();
+
+    run(screen, |screen, input| {
+        let mode = GameMode::ALL[selected];
+
+        if let Some(key) = input {
+            selected = move_selection(selected, GameMode::ALL.len(), key);
+
+            // Only one option is shown at a time, so a and d always change that one.
+            let option = if mode.rules().selectable_start_level {
+                Some((&mut settings.start_level, START_LEVEL_RANGE))
+            } else if mode.rules().practice_gravity {
+                Some((&mut settings.practice_gravity, PRACTICE_GRAVITY_RANGE))
+            } else if mode.rules().garbage {
+                // Garbage never fills up more than half of the board.
+                let (min, max) = GARBAGE_ROWS_RANGE;
+                let max = max.min(settings.board_height / 2).max(min);
+
+                Some((&mut settings.garbage_rows, (min, max)))
+            } else {
+                None
+            };
+
+            if let Some((value, (min, max))) = option {
+                match key {
+                    'a' => *value = value.saturating_sub(1).max(min),
+                    'd' => *value = (*value + 1).min(max),
+                    _ => (),
+                }
+            }
+
+            if is_confirm_key(key) {
+                return Some(Some((mode, settings.clone())));
+            }
+
+            if key == 'q' || key == '\x1B' {
+                return Some(None);
+            }
+        }
+
+        draw_centered(screen, 2, "SELECT MODE");
+
+        let menu = Menu {
+            items: GameMode::ALL
+                .iter()
+                .map(|mode| mode.name().to_string())
+                .collect(),
+            selected,
+            spacing: 1,
+        };
+
+        let area = screen.area();
+        menu.draw(screen, Rect { y: 5, ..area });
+
+        let mode = GameMode::ALL[selected];
+
+        if mode.rules().selectable_start_level {
+            draw_centered(
+                screen,
+                17,
+                &format!("Start level  < {} >", settings.start_level),
+            );
+            draw_centered(screen, 18, "(a/d to change)");
+        } else if mode.rules().practice_gravity {
+            let gravity = match settings.practice_gravity {
+                0 => String::from("Off"),
+                level => level.to_string(),
+            };
+
+            draw_centered(screen, 17, &format!("Gravity  < {} >", gravity));
+            draw_centered(screen, 18, "(a/d to change)");
+        } else if mode.rules().garbage {
+            draw_centered(
+                screen,
+                17,
+                &format!("Garbage rows  < {} >", settings.garbage_rows),
+            );
+            draw_centered(screen, 18, "(a/d to change)");
+        }
+
+        draw_centered(screen, 21, mode.description());
+        draw_centered(screen, 23, "[ENTER] to play, q to go back");
+
+        None
+    })
+}
+
+// Shows how a game went once it's over.
+pub fn results(screen: &mut Screen, mode: GameMode, game: &Tetris) {
+    let mut rows = vec![
+        ("Time", tetris::format_time_ms(game.time_ms())),
+        ("Score", format!("{}", game.score())),
+        ("Lines", format!("{}", game.lines_cleared())),
+        ("Level", format!("{}", game.level())),
+        ("Pieces", format!("{}", game.pieces_spawned())),
+        ("PPS", format!("{:.2}", game.pieces_per_second())),
+        ("Finesse", format!("{} faults", game.finesse_faults())),
+    ];
+
+    if let Some(grade) = game.grade() {
+        rows.insert(0, ("Grade", grade.to_string()));
+    }
+
+    // In modes that are about time, the score and level don't mean much.
+    if mode.rules().ranking == Ranking::Time {
+        rows.retain(|(label, _)| *label != "Score" && *label != "Level");
+    }
+
+    let title = match (game.is_cleared(), mode.rules().time_limit_ms) {
+        (true, Some(_)) => "TIME'S UP!",
+        (true, None) => "FINISHED!",
+        (false, _) => "GAME OVER",
+    };
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            if is_confirm_key(key) || key == 'q' {
+                return Some(());
+            }
+        }
+
+        draw_centered(screen, 3, title);
+
+        // The stats go in a box with a row for each of them, with the labels on
+        // the left and the numbers lined up on the right.
+        let stats = Panel {
+            title: Some(mode.name().to_string()),
+            content: Box::new(Split::vertical(
+                rows.iter()
+                    .map(|(label, value)| {
+                        let row: Box<dyn Widget> = Box::new(Split::horizontal(vec![
+                            (Constraint::Percentage(50), Box::new(Label::new(*label))),
+                            (
+                                Constraint::Fill,
+                                Box::new(Label::aligned(value.as_str(), Align::Right)),
+                            ),
+                        ]));
+
+                        (Constraint::Length(1), row)
+                    })
+                    .collect(),
+            )),
+        };
+
+        let area = screen.area().centered(14, rows.len() as u32 + 2);
+        stats.draw(screen, Rect { y: 7, ..area });
+
+        draw_centered(screen, 22, "[ENTER] to continue");
+
+        None
+    })
+}
+
+// Shown when the player reaches the goal of a mode that can keep going after
+// that. Returns true if they want to keep playing.
+pub fn congratulations(screen: &mut Screen, game: &Tetris) -> bool {
+    let mut keep_going = true;
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            keep_going = match key {
+                'w' | 'k' | 's' | 'j' => !keep_going,
+                _ => keep_going,
+            };
+
+            if is_confirm_key(key) {
+                return Some(keep_going);
+            }
+
+            if key == 'q' {
+                return Some(false);
+            }
+        }
+
+        draw_centered(screen, 3, "CONGRATULATIONS!");
+        draw_centered(
+            screen,
+            6,
+            &format!("You cleared {} lines", game.lines_cleared()),
+        );
+        draw_centered(screen, 8, &format!("Score {}", game.score()));
+
+        let menu = Menu {
+            items: vec![
+                String::from("Keep playing (endless)"),
+                String::from("Finish"),
+            ],
+            selected: if keep_going { 0 } else { 1 },
+            spacing: 2,
+        };
+
+        let area = screen.area();
+        menu.draw(screen, Rect { y: 12, ..area });
+
+        draw_centered(screen, 23, "w/s to move, [ENTER] to select");
+
+        None
+    })
+}
+
+// What the player wants to do with a replay from the list.
+pub enum ReplayChoice {
+    Watch(usize),
+    Back,
+}
+
+// Lists the replays in the library, newest first. A replay that couldn't be
+// loaded is still listed, along with why.
+pub fn replay_list(
+    screen: &mut Screen,
+    replays: &[Result<Replay, ReplayError>],
+    message: Option<String>,
+) -> ReplayChoice {
+    const VISIBLE_ROWS: usize = 16;
+
+    let mut selected = 0;
+    let mut scroll = 0;
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            if !replays.is_empty() {
+                selected = move_selection(selected, replays.len(), key);
+            }
+
+            if is_confirm_key(key) && replays.get(selected).is_some_and(|replay| replay.is_ok()) {
+                return Some(ReplayChoice::Watch(selected));
+            }
+
+            if key == 'q' || key == '\x1B' {
+                return Some(ReplayChoice::Back);
+            }
+        }
+
+        // Keep the selection on the screen.
+        if selected < scroll {
+            scroll = selected;
+        } else if selected >= scroll + VISIBLE_ROWS {
+            scroll = selected + 1 - VISIBLE_ROWS;
+        }
+
+        draw_centered(screen, 1, "REPLAYS");
+
+        screen.draw_text(
+            1,
+            3,
+            &format!(
+                "  {:<10} {:<10} {:>8} {:>6} {:>8}",
+                "DATE", "MODE", "SCORE", "PIECES", "TIME"
+            ),
+        );
+
+        if replays.is_empty() {
+            draw_centered(screen, 6, "No replays yet!");
+        }
+
+        replays
+            .iter()
+            .enumerate()
+            .skip(scroll)
+            .take(VISIBLE_ROWS)
+            .for_each(|(i, replay)| {
+                let marker = if i == selected { '>' } else { ' ' };
+
+                let row = match replay {
+                    Ok(replay) => format!(
+                        "{:<10} {:<10} {:>8} {:>6} {:>8}",
+                        highscores::format_date(replay.date),
+                        replay.mode.name(),
+                        replay.score,
+                        replay.pieces,
+                        tetris::format_time_ms(replay.time_ms())
+                    ),
+                    Err(error) => format!("broken replay: {}", error),
+                };
+
+                screen.draw_text(0, 4 + (i - scroll) as u32, &format!(" {} {}", marker, row));
+            });
+
+        if let Some(message) = message.as_ref() {
+            draw_centered(screen, 21, message);
+        }
+
+        draw_centered(screen, 23, "[ENTER] to watch, q to go back");
+
+        None
+    })
+}
+
+// What the player wants to do with a puzzle from the list.
+pub enum PuzzleChoice {
+    Play(usize),
+    // Open the puzzle in the editor, or a new one if there's no index.
+    Edit(Option<usize>),
+    Back,
+}
+
+// Lists the puzzles, with a mark next to the ones that have been solved. Like
+// with replays, puzzles that couldn't be loaded are listed along with why.
+pub fn puzzle_list(
+    screen: &mut Screen,
+    puzzles: &[(String, Result<Puzzle, PuzzleError>)],
+    solved: &HashSet<String>,
+    mut selected: usize,
+    message: Option<String>,
+) -> PuzzleChoice {
+    const VISIBLE_ROWS: u32 = 16;
+
+    // This is kept between frames so that it remembers how far it's scrolled.
+    let mut list = List::new(Vec::new());
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            if !puzzles.is_empty() {
+                selected = move_selection(selected, puzzles.len(), key);
+            }
+
+            if is_confirm_key(key)
+                && puzzles
+                    .get(selected)
+                    .is_some_and(|(_, puzzle)| puzzle.is_ok())
+            {
+                return Some(PuzzleChoice::Play(selected));
+            }
+
+            if key == 'e'
+                && puzzles
+                    .get(selected)
+                    .is_some_and(|(_, puzzle)| puzzle.is_ok())
+            {
+                return Some(PuzzleChoice::Edit(Some(selected)));
+            }
+
+            if key == 'n' {
+                return Some(PuzzleChoice::Edit(None));
+            }
+
+            if key == 'q' || key == '\x1B' {
+                return Some(PuzzleChoice::Back);
+            }
+        }
+
+        draw_centered(screen, 1, "PUZZLES");
+
+        list.items = puzzles
+            .iter()
+            .map(|(name, puzzle)| {
+                let check = if solved.contains(name) { '*' } else { ' ' };
+
+                match puzzle {
+                    Ok(puzzle) => format!(
+                        "{} {:<24} {}",
+                        check,
+                        puzzle.title,
+                        puzzle.objective.description()
+                    ),
+                    Err(error) => format!("  broken puzzle '{}': {}", name, error),
+                }
+            })
+            .collect();
+        list.selected = Some(selected);
+
+        let width = screen.width();
+        list.draw(screen, Rect::new(1, 3, width - 1, VISIBLE_ROWS));
+
+        if let Some(message) = message.as_ref() {
+            draw_centered(screen, 21, message);
+        }
+
+        draw_centered(
+            screen,
+            23,
+            "[ENTER] to play, e to edit, n for new, q to go back",
+        );
+
+        None
+    })
+}
+
+// What the player wants to do with the puzzle in the editor.
+pub enum EditorChoice {
+    Play,
+    Save,
+    Back,
+}
+
+// What can be painted onto the board in the editor: garbage, and then every
+// piece.
+const BRUSHES: [char; 8] = ['X', 'I', 'O', 'T', 'S', 'Z', 'J', 'L'];
+
+const EDITOR_HELP: [&str; 9] = [
+    "wasd   move",
+    "[SPACE] paint, e erase",
+    "1-8    brush",
+    "IOTSZJL add to the queue",
+    "[BACKSPACE] remove one",
+    "h      hold piece",
+    "o + -  objective",
+    "c clear, t title",
+    "p play, [ENTER] save",
+];
+
+// Goes through the kinds of objective, keeping the amount where it makes
+// sense.
+fn next_objective(objective: Objective) -> Objective {
+    match objective {
+        Objective::Lines(lines) => Objective::TSpin(lines.min(3)),
+        Objective::TSpin(lines) => Objective::PerfectClear(lines.max(1)),
+        Objective::PerfectClear(pieces) => Objective::Lines(pieces),
+    }
+}
+
+fn adjust_objective(objective: Objective, step: i32) -> Objective {
+    let nudge =
+        |value: u32, min: u32, max: u32| (value as i32 + step).clamp(min as i32, max as i32) as u32;
+
+    match objective {
+        Objective::Lines(lines) => Objective::Lines(nudge(lines, 1, 99)),
+        Objective::TSpin(lines) => Objective::TSpin(nudge(lines, 0, 3)),
+        Objective::PerfectClear(pieces) => Objective::PerfectClear(nudge(pieces, 1, 99)),
+    }
+}
+
+fn cell_pixel(cell: char) -> Pixel {
+    Pixel {
+        shape: [crate::unicode::FULL_BLOCK, crate::unicode::FULL_BLOCK],
+        color: Color::Basic(puzzle::cell_color(cell)),
+        styles: None,
+    }
+}
+
+// Lets the player build a puzzle: paint the board, pick the pieces and say what
+// has to be done. The puzzle gets changed in place, so that it's still there
+// after the player tries it out.
+pub fn board_editor(
+    screen: &mut Screen,
+    puzzle: &mut Puzzle,
+    message: Option<String>,
+) -> EditorChoice {
+    let width = puzzle.width().max(1) as usize;
+
+    // The editor always works on the whole board, even if the puzzle only
+    // has the bottom of it.
+    while puzzle.board.len() < GAME_HEIGHT as usize {
+        puzzle.board.insert(0, vec![None; width]);
+    }
+
+    let height = puzzle.board.len();
+    let mut cursor: (usize, usize) = (0, height - 1);
+    let mut brush = 0;
+    // Set while the title is being typed in.
+    let mut is_typing_title = false;
+    let mut message = message;
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            let (x, y) = cursor;
+
+            if is_typing_title {
+                match key {
+                    '\n' | '\r' | '\x1B' => is_typing_title = false,
+                    '\x7F' | '\x08' => {
+                        puzzle.title.pop();
+                    }
+                    character if !character.is_control() && puzzle.title.chars().count() < 24 => {
+                        puzzle.title.push(character)
+                    }
+                    _ => (),
+                }
+            } else {
+                match key {
+                    'w' => cursor.1 = y.saturating_sub(1),
+                    's' => cursor.1 = (y + 1).min(height - 1),
+                    'a' => cursor.0 = x.saturating_sub(1),
+                    'd' => cursor.0 = (x + 1).min(width - 1),
+                    ' ' => puzzle.board[y][x] = Some(BRUSHES[brush]),
+                    'e' => puzzle.board[y][x] = None,
+                    '1'..='8' => brush = key as usize - '1' as usize,
+                    '\x7F' | '\x08' => {
+                        puzzle.pieces.pop();
+                    }
+                    'h' => {
+                        // No hold piece, and then every piece in turn.
+                        let index = puzzle
+                            .hold
+                            .and_then(|hold| BRUSHES.iter().position(|brush| *brush == hold))
+                            .unwrap_or(0);
+
+                        puzzle.hold = BRUSHES.get(index + 1).copied();
+                    }
+                    'o' => puzzle.objective = next_objective(puzzle.objective),
+                    '+' | '=' => puzzle.objective = adjust_objective(puzzle.objective, 1),
+                    '-' => puzzle.objective = adjust_objective(puzzle.objective, -1),
+                    'c' => puzzle.board.iter_mut().for_each(|row| row.fill(None)),
+                    't' => is_typing_title = true,
+                    'p' | '\n' | '\r' if puzzle.pieces.is_empty() && puzzle.hold.is_none() => {
+                        message = Some(String::from("Add some pieces first"));
+                    }
+                    '\n' | '\r' if puzzle.title.is_empty() => {
+                        message = Some(String::from("Give the puzzle a title first"));
+                    }
+                    'p' => return Some(EditorChoice::Play),
+                    '\n' | '\r' => return Some(EditorChoice::Save),
+                    'q' | '\x1B' => return Some(EditorChoice::Back),
+                    letter if puzzle::is_piece_letter(letter) => puzzle.pieces.push(letter),
+                    _ => (),
+                }
+            }
+        }
+
+        let board_top = 2;
+        screen
+            .draw_box(0, board_top, width as u16 + 1, height as u16 + 1)
+            .unwrap();
+
+        for (y, row) in puzzle.board.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if let Some(cell) = cell {
+                    screen[x as u32 + 1][board_top as usize + 1 + y] = cell_pixel(*cell);
+                }
+            }
+        }
+
+        // The cursor shows what the brush would paint.
+        let (x, y) = cursor;
+        screen[x as u32 + 1][board_top as usize + 1 + y] = Pixel {
+            shape: ['[', ']'],
+            color: Color::Basic(puzzle::cell_color(BRUSHES[brush])),
+            styles: None,
+        };
+
+        let panel_x = width as u32 + 3;
+        let title = if is_typing_title {
+            format!("{}_", puzzle.title)
+        } else {
+            puzzle.title.clone()
+        };
+        let pieces: String = puzzle.pieces.iter().collect();
+        let hold = puzzle.hold.map_or(String::from("-"), String::from);
+
+        screen.draw_text(panel_x, 0, "EDITOR");
+        screen.draw_text(panel_x, 2, &format!("TITLE {}", title));
+        screen.draw_text(
+            panel_x,
+            3,
+            &format!("GOAL  {}", puzzle.objective.description()),
+        );
+        screen.draw_text(panel_x, 4, &format!("QUEUE {}", pieces));
+        screen.draw_text(panel_x, 5, &format!("HOLD  {}", hold));
+        screen.draw_text(panel_x, 6, "BRUSH");
+        screen[panel_x + 3][6] = cell_pixel(BRUSHES[brush]);
+
+        EDITOR_HELP.iter().enumerate().for_each(|(i, line)| {
+            screen.draw_text(panel_x, 9 + i as u32, line);
+        });
+
+        if let Some(message) = message.as_ref() {
+            screen.draw_text(panel_x, 21, message);
+        }
+
+        None
+    })
+}
+
+// Asks the player for their name after they got a high score.
+pub fn enter_name(screen: &mut Screen, result: &str) -> String {
+    let mut name = String::new();
+
+    run(screen, |screen, input| {
+        match input {
+            Some('\n') | Some('\r') => return Some(highscores::sanitize_name(&name)),
+            Some('\x7F') | Some('\x08') => {
+                name.pop();
+            }
+            Some(character)
+                if !character.is_control() && name.chars().count() < MAX_NAME_LENGTH =>
+            {
+                name.push(character)
+            }
+            _ => (),
+        }
+
+        draw_centered(screen, 6, "NEW HIGH SCORE!");
+        draw_centered(screen, 8, result);
+        draw_centered(screen, 11, "Enter your name:");
+        draw_centered(
+            screen,
+            13,
+            &format!("{:_<width$}", name, width = MAX_NAME_LENGTH),
+        );
+        draw_centered(screen, 20, "[ENTER] to confirm");
+
+        None
+    })
+}
+
+// Shows the high score table of a game mode. The entry at `highlight`, if there
+// is one, is the one that the player just got.
+pub fn show_high_scores(
+    screen: &mut Screen,
+    high_scores: &HighScores,
+    mode: GameMode,
+    highlight: Option<usize>,
+) {
+    let highlighted_mode = mode;
+
+    // Modes that aren't ranked don't have a table.
+    let modes: Vec<GameMode> = GameMode::ALL
+        .iter()
+        .copied()
+        .filter(|mode| mode.rules().ranking != Ranking::Unranked)
+        .collect();
+
+    let mut mode_index = modes.iter().position(|other| *other == mode).unwrap_or(0);
+
+    run(screen, |screen, input| {
+        match input {
+            Some('q') | Some('\n') | Some('\r') | Some(' ') => return Some(()),
+            Some('a') => mode_index = (mode_index + modes.len() - 1) % modes.len(),
+            Some('d') => mode_index = (mode_index + 1) % modes.len(),
+            _ => (),
+        }
+
+        let mode = modes[mode_index];
+
+        draw_centered(screen, 1, "HIGH SCORES");
+        draw_centered(screen, 2, &format!("< {} >", mode.name()));
+
+        screen.draw_text(
+            1,
+            4,
+            &format!(
+                "{:>2} {:<12} {:>7} {:>5} {:>3} {:>8} {:<10}",
+                "#", "NAME", "SCORE", "LINES", "LV", "TIME", "DATE"
+            ),
+        );
+
+        let entries = high_scores.entries(mode);
+
+        if entries.is_empty() {
+            draw_centered(screen, 7, "No scores yet!");
+        }
+
+        entries.iter().enumerate().for_each(|(i, entry)| {
+            let marker = if Some(i) == highlight && mode == highlighted_mode {
+                '>'
+            } else {
+                ' '
+            };
+
+            screen.draw_text(
+                0,
+                6 + i as u32,
+                &format!(
+                    " {}{:>2} {:<12} {:>7} {:>5} {:>3} {:>8} {:<10}",
+                    marker,
+                    i + 1,
+                    entry.name,
+                    entry.score,
+                    entry.lines,
+                    entry.level,
+                    tetris::format_time_ms(entry.time_ms),
+                    highscores::format_date(entry.date)
+                ),
+            );
+        });
+
+        draw_centered(screen, 20, "a/d to change mode, [ENTER] to go back");
+
+        None
+    })
+}
+
+// The settings that are shown on the settings screen, in order. The controls
+// come right after these.
+const SETTING_LABELS: [&str; 10] = [
+    "Frame rate",
+    "Fall speed",
+    "Board width",
+    "Board height",
+    "Spawn x",
+    "Start level",
+    "Piece set",
+    "Line clear delay",
+    "Animations",
+    "Big mode",
+];
+
+// The settings and controls don't all fit on the screen at once, so only this
+// many rows of them are shown, scrolling along with the selection.
+const SETTINGS_VISIBLE_ROWS: usize = 21;
+
+fn setting_value(settings: &Settings, index: usize) -> String {
+    match index {
+        0 => format!("{}", settings.frame_rate),
+        1 => format!("{:.1}", settings.fall_speed),
+        2 => format!("{}", settings.board_width),
+        3 => format!("{}", settings.board_height),
+        4 => format!("{}", settings.spawn_x),
+        5 => format!("{}", settings.start_level),
+        6 => settings.piece_set.clone(),
+        7 => format!("{}ms", settings.line_clear_delay),
+        8 => String::from(if settings.animations { "On" } else { "Off" }),
+        _ => String::from(if settings.big_mode { "On" } else { "Off" }),
+    }
+}
+
+// Nudges a setting up or down by one step, keeping everything within its limits.
+fn adjust_setting(settings: &mut Settings, index: usize, step: i32) {
+    fn nudge<T: TryFrom<i64> + Into<i64> + Copy>(value: T, step: i32, (min, max): (T, T)) -> T {
+        let value: i64 = value.into() + step as i64;
+        T::try_from(value.clamp(min.into(), max.into())).unwrap_or(min)
+    }
+
+    match index {
+        0 => settings.frame_rate = nudge(settings.frame_rate, step, FRAME_RATE_RANGE),
+        1 => {
+            let (min, max) = FALL_SPEED_RANGE;
+            let value = settings.fall_speed + 0.1 * step as f32;
+            settings.fall_speed = ((value * 10.0).round() / 10.0).clamp(min, max);
+        }
+        2 => {
+            settings.board_width = nudge(settings.board_width, step, BOARD_WIDTH_RANGE);
+
+            // The pieces keep coming out in the middle of the board.
+            settings.spawn_x = (settings.board_width / 2) as u16;
+        }
+        3 => settings.board_height = nudge(settings.board_height, step, BOARD_HEIGHT_RANGE),
+        4 => settings.spawn_x = nudge(settings.spawn_x, step, (1, u16::MAX)),
+        5 => settings.start_level = nudge(settings.start_level, step, START_LEVEL_RANGE),
+        6 => {
+            // This goes through the built in sets and then the ones in the
+            // pieces folder, wrapping around at the ends.
+            let names = pieces::available();
+            let index = names
+                .iter()
+                .position(|name| *name == settings.piece_set)
+                .unwrap_or(0) as i32;
+
+            settings.piece_set =
+                names[(index + step).rem_euclid(names.len() as i32) as usize].clone();
+        }
+        7 => {
+            let value = settings.line_clear_delay as i32 + 50 * step;
+            let (min, max) = LINE_CLEAR_DELAY_RANGE;
+            settings.line_clear_delay = (value.max(0) as u32).clamp(min, max);
+        }
+        8 => settings.animations = !settings.animations,
+        _ => settings.big_mode = !settings.big_mode,
+    }
+
+    // Shrinking the board can leave the spawn position outside of it.
+    settings.spawn_x = settings.spawn_x.min(settings.board_width as u16);
+}
+
+// Lets the player change the config. Returns the new config if it was saved, or
+// None if the player backed out.
+pub fn settings_screen(screen: &mut Screen, config: &Config) -> Option<Config> {
+    let mut config = config.clone();
+
+    let control_count = Action::ALL.len();
+    let save_index = SETTING_LABELS.len() + control_count;
+    let cancel_index = save_index + 1;
+
+    let mut selected = 0;
+    // The first row of settings and controls that's on the screen.
+    let mut scroll = 0;
+    // Set when the player is about to press the new key for a control.
+    let mut rebinding = false;
+    // This only gets checked again when a setting changes, since checking the
+    // piece set can mean reading it from disk.
+    let mut validation = validate_settings(&config.settings);
+
+    run(screen, |screen, input| {
+        if let Some(key) = input {
+            if rebinding {
+                if key != '\x1B' && (!key.is_control() || key == '\t') {
+                    let action = Action::ALL[selected - SETTING_LABELS.len()];
+                    config.controls.set_key(action, key);
+                }
+
+                rebinding = false;
+            } else {
+                selected = move_selection(selected, cancel_index + 1, key);
+
+                match key {
+                    'a' | 'd' if selected < SETTING_LABELS.len() => {
+                        let step = if key == 'a' { -1 } else { 1 };
+                        adjust_setting(&mut config.settings, selected, step);
+                        validation = validate_settings(&config.settings);
+                    }
+                    'q' | '\x1B' => return Some(None),
+                    key if is_confirm_key(key) => {
+                        if selected == save_index {
+                            if validation.is_ok() {
+                                return Some(Some(config.clone()));
+                            }
+                        } else if selected == cancel_index {
+                            return Some(None);
+                        } else if selected >= SETTING_LABELS.len() {
+                            rebinding = true;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let marker = |index: usize| if index == selected { "> " } else { "  " };
+
+        // The rows are the settings, then the controls header, then the
+        // controls. Save and cancel stay put at the bottom.
+        let mut rows: Vec<String> = SETTING_LABELS
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                format!(
+                    "{}{:<20}{}",
+                    marker(i),
+                    label,
+                    setting_value(&config.settings, i)
+                )
+            })
+            .collect();
+
+        rows.push(String::from(" CONTROLS"));
+
+        rows.extend(Action::ALL.iter().enumerate().map(|(i, action)| {
+            let index = SETTING_LABELS.len() + i;
+
+            let key = if rebinding && index == selected {
+                String::from("...")
+            } else {
+                config.controls.key_name(*action)
+            };
+
+            format!("{}{:<26}{}", marker(index), action.label(), key)
+        }));
+
+        if selected < save_index {
+            let row = if selected < SETTING_LABELS.len() {
+                selected
+            } else {
+                selected + 1
+            };
+
+            scroll = scroll.clamp((row + 1).saturating_sub(SETTINGS_VISIBLE_ROWS), row);
+        }
+
+        draw_centered(screen, 1, "SETTINGS");
+
+        rows.iter()
+            .skip(scroll)
+            .take(SETTINGS_VISIBLE_ROWS)
+            .enumerate()
+            .for_each(|(i, row)| screen.draw_text(2, 2 + i as u32, row));
+
+        // Hints that there's more above or below.
+        if scroll > 0 {
+            screen.draw_text(0, 2, "^");
+        }
+
+        if scroll + SETTINGS_VISIBLE_ROWS < rows.len() {
+            screen.draw_text(0, 22, "v");
+        }
+
+        screen.draw_text(2, 23, &format!("{}Save", marker(save_index)));
+        screen.draw_text(8, 23, &format!("{}Cancel", marker(cancel_index)));
+
+        if rebinding {
+            draw_centered(screen, 24, "Press the new key, or [ESC] to cancel");
+        } else if let Err((key, message)) = &validation {
+            draw_centered(screen, 24, &format!("{}: {}", key, message));
+        } else {
+            draw_centered(screen, 24, "w/s move, a/d change, [ENTER] select");
+        }
+
+        None
+    })
+}
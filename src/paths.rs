@@ -0,0 +1,45 @@
+// Figures out where the game should keep its files. On UNIX systems, this follows
+// the XDG base directory specification, and on Windows everything goes into the
+// roaming app data folder.
+
+use std::env;
+use std::path::PathBuf;
+
+const APP_DIRECTORY: &str = "tetris-cli";
+
+// Reads an environment variable as a path, ignoring it if it's empty (which is
+// what the XDG spec says to do).
+fn env_path(name: &str) -> Option<PathBuf> {
+    match env::var_os(name) {
+        Some(value) if !value.is_empty() => Some(PathBuf::from(value)),
+        _ => None,
+    }
+}
+
+// Where things like high scores and replays go.
+#[cfg(target_family = "unix")]
+pub fn data_dir() -> Option<PathBuf> {
+    let base = env_path("XDG_DATA_HOME")
+        .or_else(|| env_path("HOME").map(|home| home.join(".local").join("share")))?;
+
+    Some(base.join(APP_DIRECTORY))
+}
+
+#[cfg(target_family = "windows")]
+pub fn data_dir() -> Option<PathBuf> {
+    Some(env_path("APPDATA")?.join(APP_DIRECTORY))
+}
+
+// Where the config file goes.
+#[cfg(target_family = "unix")]
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env_path("XDG_CONFIG_HOME")
+        .or_else(|| env_path("HOME").map(|home| home.join(".config")))?;
+
+    Some(base.join(APP_DIRECTORY))
+}
+
+#[cfg(target_family = "windows")]
+pub fn config_dir() -> Option<PathBuf> {
+    Some(env_path("APPDATA")?.join(APP_DIRECTORY))
+}
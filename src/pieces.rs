@@ -0,0 +1,534 @@
+// The sets of pieces that the game can be played with. Besides the usual seven
+// tetrominoes, there are trominoes and pentominoes, and players can make their
+// own sets by putting files into the "pieces" folder of the config directory.
+// A piece set file looks a lot like the config file, with a section for every
+// piece in the set:
+//
+//     # An S made out of five blocks.
+//     [piece]
+//     cells = ".##/.#./##."
+//     color = 32
+//     glyphs = "[]"
+//     pivot = "1,1"
+//     kicks = "none"
+//
+// The cells go from the top row down, with the rows split up by slashes. The
+// pivot is the cell that the piece turns around, counting from 0,0 at the top
+// left. Everything apart from the cells can be left out.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::{self, Value};
+use crate::screen::{colors::basic::*, Color, Pixel, Shape};
+use crate::unicode::FULL_BLOCK;
+
+const PIECES_DIRECTORY: &str = "pieces";
+
+// Pieces can't be any bigger than this, since they wouldn't fit in the hold box
+// (or on a lot of boards) anymore.
+pub const MAX_PIECE_SIZE: usize = 9;
+
+// The piece sets that come with the game. These can't be replaced by files.
+pub const BUILT_IN: [&str; 3] = ["standard", "trominoes", "pentominoes"];
+
+// The standard tetrominoes. These are kept separate since the T is needed for
+// T-spins.
+pub const SQUARE: [(i16, i16); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+pub const STRAIGHT: [(i16, i16); 4] = [(0, -1), (0, 0), (0, 1), (0, 2)];
+pub const TEE: [(i16, i16); 4] = [(0, -1), (0, 0), (-1, 0), (1, 0)];
+pub const LEFT_SKEWED: [(i16, i16); 4] = [(-1, 0), (0, 0), (0, -1), (1, -1)];
+pub const RIGHT_SKEWED: [(i16, i16); 4] = [(-1, -1), (0, 0), (0, -1), (1, 0)];
+pub const LEFT_L: [(i16, i16); 4] = [(-1, 0), (0, 0), (0, -1), (0, -2)];
+pub const RIGHT_L: [(i16, i16); 4] = [(1, 0), (0, 0), (0, -1), (0, -2)];
+
+const STANDARD: [(&[(i16, i16)], u8); 7] = [
+    (&SQUARE, BRIGHT_YELLOW),
+    (&STRAIGHT, CYAN),
+    (&TEE, MAGENTA),
+    (&LEFT_SKEWED, GREEN),
+    (&RIGHT_SKEWED, RED),
+    (&LEFT_L, BLUE),
+    (&RIGHT_L, YELLOW),
+];
+
+// The letters that the standard pieces go by, in the same order as above.
+const LETTERS: [char; 7] = ['O', 'I', 'T', 'S', 'Z', 'J', 'L'];
+
+// There are only two trominoes that can't be turned into each other.
+const TROMINOES: [(&[(i16, i16)], u8); 2] = [
+    (&[(0, -1), (0, 0), (0, 1)], CYAN),
+    (&[(0, -1), (0, 0), (1, 0)], BLUE),
+];
+
+// All 18 of the pentominoes, counting the mirror images since pieces can't be
+// flipped over. The mirror images share a color.
+const PENTOMINOES: [(&[(i16, i16)], u8); 18] = [
+    // F
+    (&[(0, -1), (1, -1), (-1, 0), (0, 0), (0, 1)], GREEN),
+    (&[(-1, -1), (0, -1), (0, 0), (1, 0), (0, 1)], GREEN),
+    // I
+    (&[(0, -2), (0, -1), (0, 0), (0, 1), (0, 2)], CYAN),
+    // L
+    (&[(0, -2), (0, -1), (0, 0), (0, 1), (1, 1)], YELLOW),
+    (&[(0, -2), (0, -1), (0, 0), (0, 1), (-1, 1)], BLUE),
+    // N
+    (&[(0, -1), (0, 0), (-1, 1), (0, 1), (-1, 2)], BRIGHT_BLUE),
+    (&[(0, -1), (0, 0), (0, 1), (1, 1), (1, 2)], BRIGHT_BLUE),
+    // P
+    (&[(0, -1), (1, -1), (0, 0), (1, 0), (0, 1)], BRIGHT_YELLOW),
+    (&[(-1, -1), (0, -1), (-1, 0), (0, 0), (0, 1)], BRIGHT_YELLOW),
+    // T
+    (&[(-1, -1), (0, -1), (1, -1), (0, 0), (0, 1)], MAGENTA),
+    // U
+    (
+        &[(-1, -1), (1, -1), (-1, 0), (0, 0), (1, 0)],
+        BRIGHT_MAGENTA,
+    ),
+    // V
+    (&[(-1, -1), (-1, 0), (-1, 1), (0, 1), (1, 1)], BRIGHT_CYAN),
+    // W
+    (&[(-1, -1), (-1, 0), (0, 0), (0, 1), (1, 1)], BRIGHT_GREEN),
+    // X
+    (&[(0, -1), (-1, 0), (0, 0), (1, 0), (0, 1)], BRIGHT_WHITE),
+    // Y
+    (&[(0, -1), (-1, 0), (0, 0), (0, 1), (0, 2)], BRIGHT_RED),
+    (&[(0, -1), (0, 0), (1, 0), (0, 1), (0, 2)], BRIGHT_RED),
+    // Z and S
+    (&[(-1, -1), (0, -1), (0, 0), (0, 1), (1, 1)], RED),
+    (&[(0, -1), (1, -1), (0, 0), (-1, 1), (0, 1)], RED),
+];
+
+#[derive(Debug)]
+pub enum PieceSetError {
+    Io(io::Error),
+    // There's no built in set or file with that name.
+    Unknown(String),
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for PieceSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PieceSetError::Io(error) => write!(f, "{}", error),
+            PieceSetError::Unknown(name) => write!(f, "there's no piece set called '{}'", name),
+            PieceSetError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for PieceSetError {
+    fn from(error: io::Error) -> Self {
+        PieceSetError::Io(error)
+    }
+}
+
+fn shape(pixels: &[(i16, i16)], color: u8) -> Shape {
+    Shape {
+        pixels: pixels.to_vec(),
+        fill_pixel: Pixel {
+            shape: [FULL_BLOCK, FULL_BLOCK],
+            color: Color::Basic(color),
+            styles: None,
+        },
+    }
+}
+
+fn built_in_shapes(pieces: &[(&[(i16, i16)], u8)]) -> Vec<Shape> {
+    pieces
+        .iter()
+        .map(|(pixels, color)| shape(pixels, *color))
+        .collect()
+}
+
+pub fn standard() -> Vec<Shape> {
+    built_in_shapes(&STANDARD)
+}
+
+// One of the standard pieces, by its letter.
+pub fn tetromino(letter: char) -> Option<Shape> {
+    let index = LETTERS.iter().position(|other| *other == letter)?;
+    let (pixels, color) = STANDARD[index];
+
+    Some(shape(pixels, color))
+}
+
+// The letter of a standard piece, as long as it hasn't been turned.
+pub fn letter(shape: &Shape) -> Option<char> {
+    STANDARD
+        .iter()
+        .position(|(pixels, _)| shape.pixels == *pixels)
+        .map(|index| LETTERS[index])
+}
+
+// Where the players' own piece sets go.
+fn pieces_dir() -> Option<PathBuf> {
+    Some(crate::paths::config_dir()?.join(PIECES_DIRECTORY))
+}
+
+// Piece sets are stored in the replays and saved games by name, so the name
+// can't have spaces in it. This also stops names from going outside of the
+// pieces folder.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// The names of every piece set that can be played, with the built in ones first.
+pub fn available() -> Vec<String> {
+    let mut names: Vec<String> = BUILT_IN.iter().map(|name| name.to_string()).collect();
+
+    let mut files: Vec<String> = pieces_dir()
+        .and_then(|directory| fs::read_dir(directory).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| is_valid_name(name) && !BUILT_IN.contains(&name.as_str()))
+        .collect();
+    files.sort();
+
+    names.append(&mut files);
+    names
+}
+
+// Loads the pieces of a set, either one of the built in ones or one from the
+// pieces folder.
+pub fn load(name: &str) -> Result<Vec<Shape>, PieceSetError> {
+    match name {
+        "standard" => return Ok(built_in_shapes(&STANDARD)),
+        "trominoes" => return Ok(built_in_shapes(&TROMINOES)),
+        "pentominoes" => return Ok(built_in_shapes(&PENTOMINOES)),
+        _ => (),
+    }
+
+    let path = match pieces_dir() {
+        Some(directory) if is_valid_name(name) => directory.join(name),
+        _ => return Err(PieceSetError::Unknown(name.to_string())),
+    };
+
+    match fs::read_to_string(path) {
+        Ok(contents) => parse(&contents),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Err(PieceSetError::Unknown(name.to_string()))
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+// One piece of a set, while the file is still being read.
+struct PieceDefinition {
+    // The line that the piece starts on, for the errors.
+    line: usize,
+    cells: Vec<(i16, i16)>,
+    color: u8,
+    glyphs: [char; 2],
+    pivot: Option<(i16, i16)>,
+}
+
+fn parse(contents: &str) -> Result<Vec<Shape>, PieceSetError> {
+    let mut definitions: Vec<PieceDefinition> = Vec::new();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        let line_number = line_number + 1;
+        let line = line.trim();
+
+        let invalid = |message: &str| PieceSetError::Invalid {
+            line: line_number,
+            message: message.to_string(),
+        };
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line == "[piece]" {
+            definitions.push(PieceDefinition {
+                line: line_number,
+                cells: Vec::new(),
+                color: BRIGHT_BLACK,
+                glyphs: [FULL_BLOCK, FULL_BLOCK],
+                pivot: None,
+            });
+            continue;
+        }
+
+        if line.starts_with('[') {
+            return Err(invalid("expected '[piece]'"));
+        }
+
+        let (key, value) = config::parse_line(line).map_err(|message| invalid(&message))?;
+        let value = match value {
+            Value::Number(text) | Value::Text(text) => text,
+        };
+
+        let definition = definitions
+            .last_mut()
+            .ok_or_else(|| invalid("expected '[piece]' before the first piece"))?;
+
+        match key {
+            "cells" => {
+                definition.cells = parse_cells(&value).map_err(|message| invalid(&message))?
+            }
+            "color" => {
+                definition.color = value
+                    .parse()
+                    .ok()
+                    .filter(|color| matches!(color, 30..=37 | 90..=97))
+                    .ok_or_else(|| invalid("expected a color code from 30 to 37 or 90 to 97"))?
+            }
+            "glyphs" => {
+                let glyphs: Vec<char> = value.chars().collect();
+
+                // These would get in the way of how boards are written in
+                // saved games.
+                let is_usable =
+                    |c: &char| !c.is_whitespace() && !c.is_control() && !"/;@".contains(*c);
+
+                definition.glyphs = match glyphs[..] {
+                    [left, right] if glyphs.iter().all(is_usable) => [left, right],
+                    _ => return Err(invalid("expected two characters")),
+                }
+            }
+            "pivot" => {
+                let pivot = value
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+
+                definition.pivot = Some(pivot.ok_or_else(|| invalid("expected 'x,y'"))?);
+            }
+            // Rotations that don't fit just don't happen, so there's only the
+            // one kick table for now.
+            "kicks" if value == "none" => (),
+            "kicks" => return Err(invalid(&format!("unknown kick table '{}'", value))),
+            _ => return Err(invalid(&format!("unknown key '{}'", key))),
+        }
+    }
+
+    if definitions.is_empty() {
+        return Err(PieceSetError::Invalid {
+            line: 0,
+            message: String::from("there are no pieces in the set"),
+        });
+    }
+
+    definitions
+        .into_iter()
+        .map(|definition| {
+            let invalid = |message: &str| PieceSetError::Invalid {
+                line: definition.line,
+                message: message.to_string(),
+            };
+
+            if definition.cells.is_empty() {
+                return Err(invalid("the piece has no cells"));
+            }
+
+            if definition.cells.len() > MAX_PIECE_SIZE {
+                return Err(invalid(&format!(
+                    "pieces can't have more than {} cells",
+                    MAX_PIECE_SIZE
+                )));
+            }
+
+            if !is_connected(&definition.cells) {
+                return Err(invalid("the cells of the piece aren't all connected"));
+            }
+
+            // Unless it's been set, the piece turns around the cell in the
+            // middle of it (or near enough).
+            let (pivot_x, pivot_y) = definition.pivot.unwrap_or_else(|| {
+                let width = definition
+                    .cells
+                    .iter()
+                    .map(|(x, _)| x + 1)
+                    .max()
+                    .unwrap_or(1);
+                let height = definition
+                    .cells
+                    .iter()
+                    .map(|(_, y)| y + 1)
+                    .max()
+                    .unwrap_or(1);
+
+                ((width - 1) / 2, (height - 1) / 2)
+            });
+
+            let pixels: Vec<(i16, i16)> = definition
+                .cells
+                .iter()
+                .map(|(x, y)| (x - pivot_x, y - pivot_y))
+                .collect();
+
+            let mut shape = shape(&pixels, definition.color);
+            shape.fill_pixel.shape = definition.glyphs;
+
+            Ok(shape)
+        })
+        .collect()
+}
+
+// Reads the cells of a piece, like ".##/##./.#.", into their positions.
+fn parse_cells(text: &str) -> Result<Vec<(i16, i16)>, String> {
+    let mut cells = Vec::new();
+
+    for (y, row) in text.split('/').enumerate() {
+        for (x, cell) in row.chars().enumerate() {
+            match cell {
+                '#' => cells.push((x as i16, y as i16)),
+                '.' => (),
+                _ => return Err(format!("unexpected '{}', expected '#' or '.'", cell)),
+            }
+        }
+    }
+
+    Ok(cells)
+}
+
+// Checks that every cell can be reached from every other one by going through
+// cells that share a side.
+fn is_connected(cells: &[(i16, i16)]) -> bool {
+    let cells: HashSet<(i16, i16)> = cells.iter().copied().collect();
+    let mut reached = HashSet::new();
+    let mut to_visit: Vec<(i16, i16)> = cells.iter().take(1).copied().collect();
+
+    while let Some((x, y)) = to_visit.pop() {
+        if !cells.contains(&(x, y)) || !reached.insert((x, y)) {
+            continue;
+        }
+
+        to_visit.extend([(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+    }
+
+    reached.len() == cells.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes a piece out the way it would be in a piece set file.
+    fn write_piece(shape: &Shape) -> String {
+        let min_x = shape.pixels.iter().map(|(x, _)| *x).min().unwrap();
+        let min_y = shape.pixels.iter().map(|(_, y)| *y).min().unwrap();
+        let max_x = shape.pixels.iter().map(|(x, _)| *x).max().unwrap();
+        let max_y = shape.pixels.iter().map(|(_, y)| *y).max().unwrap();
+
+        let rows: Vec<String> = (min_y..=max_y)
+            .map(|y| {
+                (min_x..=max_x)
+                    .map(|x| {
+                        if shape.pixels.contains(&(x, y)) {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let color = match shape.fill_pixel.color {
+            Color::Basic(color) => color,
+            _ => panic!("the built in pieces only use basic colors"),
+        };
+
+        format!(
+            "[piece]\ncells = \"{}\"\ncolor = {}\npivot = \"{},{}\"\n",
+            rows.join("/"),
+            color,
+            -min_x,
+            -min_y
+        )
+    }
+
+    fn sorted(mut shapes: Vec<Shape>) -> Vec<Shape> {
+        for shape in shapes.iter_mut() {
+            shape.pixels.sort();
+        }
+
+        shapes
+    }
+
+    fn invalid_line(contents: &str) -> (usize, String) {
+        match parse(contents) {
+            Err(PieceSetError::Invalid { line, message }) => (line, message),
+            Err(error) => panic!("expected an invalid piece set, got {}", error),
+            Ok(_) => panic!("expected an invalid piece set, but it loaded"),
+        }
+    }
+
+    #[test]
+    fn built_in_sets_round_trip() {
+        for name in BUILT_IN {
+            let shapes = load(name).unwrap();
+            let contents: String = shapes.iter().map(write_piece).collect();
+
+            assert_eq!(sorted(parse(&contents).unwrap()), sorted(shapes));
+        }
+    }
+
+    #[test]
+    fn every_key_is_read() {
+        let shapes = parse(
+            "# An S made out of five blocks.\n\
+             [piece]\n\
+             cells = \".##/.#./##.\"\n\
+             color = 32\n\
+             glyphs = \"[]\"\n\
+             pivot = \"1,1\"\n\
+             kicks = \"none\"\n\
+             \n\
+             [piece]\n\
+             cells = \"###\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(
+            shapes[0].pixels,
+            vec![(0, -1), (1, -1), (0, 0), (-1, 1), (0, 1)]
+        );
+        assert_eq!(shapes[0].fill_pixel.color, Color::Basic(32));
+        assert_eq!(shapes[0].fill_pixel.shape, ['[', ']']);
+
+        // Without a pivot, the piece turns around its middle.
+        assert_eq!(shapes[1].pixels, vec![(-1, 0), (0, 0), (1, 0)]);
+        assert_eq!(shapes[1].fill_pixel.color, Color::Basic(BRIGHT_BLACK));
+    }
+
+    #[test]
+    fn only_touching_sides_are_connected() {
+        assert!(is_connected(&[(0, 0)]));
+        assert!(is_connected(&[(0, 0), (1, 0), (1, 1), (2, 1)]));
+        assert!(!is_connected(&[(0, 0), (1, 1)]));
+        assert!(!is_connected(&[(0, 0), (1, 0), (3, 0)]));
+    }
+
+    #[test]
+    fn disconnected_pieces_are_rejected() {
+        let (line, message) = invalid_line(
+            "[piece]\n\
+             cells = \"##\"\n\
+             [piece]\n\
+             cells = \"#./.#\"\n",
+        );
+
+        assert_eq!(line, 3);
+        assert_eq!(message, "the cells of the piece aren't all connected");
+    }
+
+    #[test]
+    fn bad_piece_sets_are_rejected() {
+        assert_eq!(invalid_line("cells = \"#\"\n").0, 1);
+        assert_eq!(invalid_line("[piece]\ncells = \"#\"\nspin = 1\n").0, 3);
+        assert_eq!(invalid_line("[piece]\ncells = \"#\"\ncolor = 12\n").0, 3);
+        assert_eq!(invalid_line("[piece]\ncells = \"#x\"\n").0, 2);
+        assert_eq!(invalid_line("[piece]\ncells = \"##########\"\n").0, 1);
+        assert_eq!(invalid_line("# Nothing in here.\n").0, 0);
+    }
+}
@@ -0,0 +1,586 @@
+// Puzzles: a board to start from, a list of pieces to play in order, and
+// something to do with them. The game comes with a few, and more can be put
+// into the "puzzles" folder of the data directory. A puzzle file looks a lot
+// like the config file, with the board at the end:
+//
+//     title = "Four at once"
+//     objective = "lines 4"
+//     pieces = "I"
+//     hold = "T"
+//
+//     [board]
+//     ZZJJJLLOO.
+//     XXXXXXXXX.
+//
+// The objective is one of "lines <n>" (clear that many lines), "tspin <n>"
+// (clear that many lines with a T-spin) or "perfect <n>" (clear the whole board
+// within that many pieces). The board is filled in from the bottom, with a dot
+// for every empty cell, X for garbage and the letter of a piece for the rest.
+// The hold piece can be left out.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::config::{self, Value, BOARD_WIDTH_RANGE};
+use crate::pieces;
+use crate::screen::Color;
+use crate::tetris::{Settings, GAME_HEIGHT, GARBAGE_COLOR};
+
+const PUZZLE_DIRECTORY: &str = "puzzles";
+const PUZZLE_EXTENSION: &str = "puzzle";
+// The names of the puzzles that have been solved, one on each line.
+const SOLVED_FILE: &str = "solved_puzzles";
+
+// The puzzles that come with the game, by name. These go first in the list.
+const BUILT_IN: [(&str, &str); 5] = [
+    (
+        "four-at-once",
+        "title = \"Four at once\"\n\
+         objective = \"lines 4\"\n\
+         pieces = \"I\"\n\
+         [board]\n\
+         ZZJJJLLOO.\n\
+         ZZSSJLLOO.\n\
+         IZZSSTTTL.\n\
+         IXXXXXTLL.\n",
+    ),
+    (
+        "skewed",
+        "title = \"Skewed\"\n\
+         objective = \"lines 2\"\n\
+         pieces = \"S\"\n\
+         [board]\n\
+         XXXXX.....\n\
+         XXXXXX..XX\n\
+         XXXXXXX.XX\n",
+    ),
+    (
+        "two-squares",
+        "title = \"Two squares\"\n\
+         objective = \"perfect 2\"\n\
+         pieces = \"OO\"\n\
+         [board]\n\
+         IJJJZZ....\n\
+         IJLZZL....\n",
+    ),
+    (
+        "flat-finish",
+        "title = \"Flat finish\"\n\
+         objective = \"perfect 3\"\n\
+         pieces = \"IOI\"\n\
+         [board]\n\
+         LLLT......\n\
+         LTTT......\n",
+    ),
+    (
+        "t-spin-double",
+        "title = \"T-spin double\"\n\
+         objective = \"tspin 2\"\n\
+         pieces = \"T\"\n\
+         [board]\n\
+         XXX.......\n\
+         XX...XXXXX\n\
+         XXX.XXXXXX\n",
+    ),
+];
+
+// What has to be done to solve a puzzle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Objective {
+    // Clear this many lines, in any way.
+    Lines(u32),
+    // Clear this many lines at once with a (full) T-spin.
+    TSpin(u32),
+    // Clear the whole board within this many pieces.
+    PerfectClear(u32),
+}
+
+impl Objective {
+    // What the objective is, for the player.
+    pub fn description(&self) -> String {
+        match self {
+            Objective::Lines(1) => String::from("Clear a line"),
+            Objective::Lines(lines) => format!("Clear {} lines", lines),
+            Objective::TSpin(lines) => {
+                let name = match lines {
+                    0 => "",
+                    1 => " single",
+                    2 => " double",
+                    _ => " triple",
+                };
+
+                format!("T-spin{}", name)
+            }
+            Objective::PerfectClear(pieces) => format!("Perfect clear in {}", pieces),
+        }
+    }
+
+    // How the objective is written in a puzzle file.
+    pub fn key(&self) -> String {
+        match self {
+            Objective::Lines(lines) => format!("lines {}", lines),
+            Objective::TSpin(lines) => format!("tspin {}", lines),
+            Objective::PerfectClear(pieces) => format!("perfect {}", pieces),
+        }
+    }
+
+    fn from_key(text: &str) -> Option<Objective> {
+        let (kind, amount) = text.split_once(' ')?;
+        let amount = amount.trim().parse().ok()?;
+
+        match kind {
+            "lines" if amount > 0 => Some(Objective::Lines(amount)),
+            "tspin" if amount <= 3 => Some(Objective::TSpin(amount)),
+            "perfect" if amount > 0 => Some(Objective::PerfectClear(amount)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Puzzle {
+    // The name of the file (or of the built in puzzle), which is what's used
+    // to remember that it has been solved.
+    pub name: String,
+    pub title: String,
+    pub objective: Objective,
+    // The letters of the pieces, in the order that they come out.
+    pub pieces: Vec<char>,
+    pub hold: Option<char>,
+    // The bottom of the board, from the top down. Every cell is the letter of
+    // a piece, or X for garbage.
+    pub board: Vec<Vec<Option<char>>>,
+}
+
+#[derive(Debug)]
+pub enum PuzzleError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for PuzzleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PuzzleError::Io(error) => write!(f, "{}", error),
+            PuzzleError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for PuzzleError {
+    fn from(error: io::Error) -> Self {
+        PuzzleError::Io(error)
+    }
+}
+
+pub fn is_piece_letter(letter: char) -> bool {
+    pieces::tetromino(letter).is_some()
+}
+
+// The color that a cell of the board gets drawn in.
+pub fn cell_color(cell: char) -> u8 {
+    match pieces::tetromino(cell).map(|shape| shape.fill_pixel.color) {
+        Some(Color::Basic(color)) => color,
+        _ => GARBAGE_COLOR,
+    }
+}
+
+impl Puzzle {
+    // An empty board to start a new puzzle from.
+    pub fn new(width: u32) -> Puzzle {
+        Puzzle {
+            name: String::new(),
+            title: String::new(),
+            objective: Objective::Lines(1),
+            pieces: Vec::new(),
+            hold: None,
+            board: vec![vec![None; width as usize]; GAME_HEIGHT as usize],
+        }
+    }
+
+    pub fn parse(name: &str, contents: &str) -> Result<Puzzle, PuzzleError> {
+        let mut puzzle = Puzzle {
+            name: name.to_string(),
+            title: name.to_string(),
+            objective: Objective::Lines(1),
+            pieces: Vec::new(),
+            hold: None,
+            board: Vec::new(),
+        };
+
+        let mut has_objective = false;
+        let mut is_in_board = false;
+
+        for (line_number, line) in contents.lines().enumerate() {
+            let line_number = line_number + 1;
+            let line = line.trim();
+
+            let error = |message: &str| PuzzleError::Parse {
+                line: line_number,
+                message: message.to_string(),
+            };
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line == "[board]" {
+                is_in_board = true;
+                continue;
+            }
+
+            if is_in_board {
+                let row = line
+                    .chars()
+                    .map(|cell| match cell {
+                        '.' => Ok(None),
+                        'X' => Ok(Some('X')),
+                        letter if is_piece_letter(letter) => Ok(Some(letter)),
+                        _ => Err(error(&format!("unexpected '{}' in the board", cell))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if puzzle
+                    .board
+                    .first()
+                    .is_some_and(|first| first.len() != row.len())
+                {
+                    return Err(error("all of the rows have to be the same width"));
+                }
+
+                puzzle.board.push(row);
+                continue;
+            }
+
+            let (key, value) = config::parse_line(line).map_err(|message| error(&message))?;
+            let value = match value {
+                Value::Number(text) | Value::Text(text) => text,
+            };
+
+            match key {
+                "title" => puzzle.title = value,
+                "objective" => {
+                    puzzle.objective = Objective::from_key(&value).ok_or_else(|| {
+                        error("expected \"lines <n>\", \"tspin <n>\" or \"perfect <n>\"")
+                    })?;
+                    has_objective = true;
+                }
+                "pieces" => {
+                    puzzle.pieces = value.chars().collect();
+
+                    if !puzzle.pieces.iter().all(|letter| is_piece_letter(*letter)) {
+                        return Err(error("expected the letters of pieces, like \"IOTSZJL\""));
+                    }
+                }
+                "hold" => {
+                    puzzle.hold = match value.chars().collect::<Vec<_>>()[..] {
+                        [letter] if is_piece_letter(letter) => Some(letter),
+                        _ => return Err(error("expected the letter of a piece")),
+                    }
+                }
+                _ => return Err(error(&format!("unknown key '{}'", key))),
+            }
+        }
+
+        let error = |message: &str| PuzzleError::Parse {
+            line: 0,
+            message: message.to_string(),
+        };
+
+        if !has_objective {
+            return Err(error("there's no objective"));
+        }
+
+        if puzzle.pieces.is_empty() && puzzle.hold.is_none() {
+            return Err(error("there are no pieces to play with"));
+        }
+
+        let (min_width, max_width) = BOARD_WIDTH_RANGE;
+        if !(min_width..=max_width).contains(&puzzle.width()) {
+            return Err(error(&format!(
+                "the board has to be between {} and {} wide",
+                min_width, max_width
+            )));
+        }
+
+        if puzzle.board.len() > GAME_HEIGHT as usize {
+            return Err(error(&format!(
+                "the board can't be more than {} rows high",
+                GAME_HEIGHT
+            )));
+        }
+
+        Ok(puzzle)
+    }
+
+    // Writes the puzzle out in the same format that parse() reads. The empty
+    // rows at the top of the board are left out, apart from the last one so
+    // that the width of the board is still known.
+    pub fn write(&self) -> String {
+        let title = self.title.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let mut contents = format!("title = \"{}\"\n", title);
+        contents += &format!("objective = \"{}\"\n", self.objective.key());
+        contents += &format!("pieces = \"{}\"\n", self.pieces.iter().collect::<String>());
+
+        if let Some(hold) = self.hold {
+            contents += &format!("hold = \"{}\"\n", hold);
+        }
+
+        contents += "\n[board]\n";
+
+        let first_row = self
+            .board
+            .iter()
+            .position(|row| row.iter().any(|cell| cell.is_some()))
+            .unwrap_or(self.board.len())
+            .min(self.board.len().saturating_sub(1));
+
+        for row in self.board.iter().skip(first_row) {
+            let row: String = row.iter().map(|cell| cell.unwrap_or('.')).collect();
+            contents += &row;
+            contents += "\n";
+        }
+
+        contents
+    }
+
+    // Saves the puzzle into the puzzle folder. The file is named after the
+    // title, which replaces any puzzle that was saved under it before.
+    pub fn save(&mut self) -> io::Result<()> {
+        let directory = match puzzle_dir() {
+            Some(directory) => directory,
+            None => return Ok(()),
+        };
+
+        fs::create_dir_all(&directory)?;
+
+        self.name = name_from_title(&self.title);
+        let path = directory.join(format!("{}.{}", self.name, PUZZLE_EXTENSION));
+
+        let mut temporary_path = path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(self.write().as_bytes())?;
+        file.sync_all()?;
+
+        fs::rename(&temporary_path, &path)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.board.first().map_or(0, |row| row.len() as u32)
+    }
+
+    // Puzzles are always played on a normal sized board with the normal pieces,
+    // whatever the settings say.
+    pub fn settings(&self, settings: &Settings) -> Settings {
+        Settings {
+            board_width: self.width(),
+            board_height: GAME_HEIGHT,
+            spawn_x: (self.width() / 2) as u16,
+            start_level: 1,
+            big_mode: false,
+            piece_set: String::from("standard"),
+            ..settings.clone()
+        }
+    }
+}
+
+// Turns a title like "T-spin double!" into "t-spin-double", for the file name.
+// Names of the built in puzzles get "-custom" on the end, so that an edited copy
+// of one doesn't get mixed up with the original.
+fn name_from_title(title: &str) -> String {
+    let name = title
+        .to_lowercase()
+        .chars()
+        .map(|character| {
+            if character.is_ascii_alphanumeric() {
+                character
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if name.is_empty() {
+        String::from("puzzle")
+    } else if is_built_in(&name) {
+        format!("{}-custom", name)
+    } else {
+        name
+    }
+}
+
+fn is_built_in(name: &str) -> bool {
+    BUILT_IN.iter().any(|(built_in, _)| *built_in == name)
+}
+
+fn puzzle_dir() -> Option<PathBuf> {
+    Some(crate::paths::data_dir()?.join(PUZZLE_DIRECTORY))
+}
+
+// Every puzzle there is, with the built in ones first. Puzzles that couldn't be
+// loaded are still there, along with why.
+pub fn load_all() -> Vec<(String, Result<Puzzle, PuzzleError>)> {
+    let mut puzzles: Vec<(String, Result<Puzzle, PuzzleError>)> = BUILT_IN
+        .iter()
+        .map(|(name, contents)| (name.to_string(), Puzzle::parse(name, contents)))
+        .collect();
+
+    let mut paths: Vec<PathBuf> = puzzle_dir()
+        .and_then(|directory| fs::read_dir(directory).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == PUZZLE_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        // Files with the name of a built in puzzle would show up twice, and
+        // share whether they've been solved.
+        let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(name) if !is_built_in(name) => name.to_string(),
+            _ => continue,
+        };
+
+        let puzzle = fs::read_to_string(&path)
+            .map_err(PuzzleError::from)
+            .and_then(|contents| Puzzle::parse(&name, &contents));
+
+        puzzles.push((name, puzzle));
+    }
+
+    puzzles
+}
+
+fn solved_path() -> Option<PathBuf> {
+    Some(crate::paths::data_dir()?.join(SOLVED_FILE))
+}
+
+// The names of the puzzles that have been solved so far.
+pub fn load_solved() -> HashSet<String> {
+    solved_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|contents| contents.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// Remembers that a puzzle has been solved.
+pub fn mark_solved(name: &str) -> io::Result<()> {
+    let path = match solved_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let mut solved = load_solved();
+    if !solved.insert(name.to_string()) {
+        return Ok(());
+    }
+
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let mut names: Vec<String> = solved.into_iter().collect();
+    names.sort();
+
+    let mut file = fs::File::create(path)?;
+    file.write_all((names.join("\n") + "\n").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_come_from_titles() {
+        assert_eq!(name_from_title("T-spin double!"), "t-spin-double-custom");
+        assert_eq!(name_from_title("Four at once"), "four-at-once-custom");
+        assert_eq!(name_from_title("My  first puzzle"), "my-first-puzzle");
+        assert_eq!(name_from_title("!!!"), "puzzle");
+    }
+
+    fn parse_error(contents: &str) -> (usize, String) {
+        match Puzzle::parse("test", contents) {
+            Err(PuzzleError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn built_in_puzzles_round_trip() {
+        for (name, contents) in BUILT_IN {
+            let puzzle = Puzzle::parse(name, contents).unwrap();
+            assert_eq!(Puzzle::parse(name, &puzzle.write()).unwrap(), puzzle);
+        }
+    }
+
+    #[test]
+    fn written_puzzles_read_back_in() {
+        let puzzle = Puzzle::parse(
+            "test",
+            "# Comments and blank lines get skipped.\n\
+             title = \"A \\\"quoted\\\" \\\\ title\"\n\
+             objective = \"perfect 3\"\n\
+             pieces = \"IOT\"\n\
+             hold = \"L\"\n\
+             \n\
+             [board]\n\
+             ZZ........\n\
+             XZZ.......\n",
+        )
+        .unwrap();
+
+        assert_eq!(puzzle.title, "A \"quoted\" \\ title");
+        assert_eq!(puzzle.objective, Objective::PerfectClear(3));
+        assert_eq!(puzzle.pieces, vec!['I', 'O', 'T']);
+        assert_eq!(puzzle.hold, Some('L'));
+        assert_eq!(puzzle.board.len(), 2);
+        assert_eq!(puzzle.width(), 10);
+
+        assert_eq!(Puzzle::parse("test", &puzzle.write()).unwrap(), puzzle);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let (line, message) = parse_error(
+            "title = \"Test\"\n\
+             objective = \"lines 1\"\n\
+             speed = \"fast\"\n\
+             pieces = \"I\"\n",
+        );
+
+        assert_eq!(line, 3);
+        assert_eq!(message, "unknown key 'speed'");
+    }
+
+    #[test]
+    fn bad_puzzles_are_rejected() {
+        let (line, _) = parse_error(
+            "objective = \"lines 1\"\n\
+             pieces = \"I\"\n\
+             [board]\n\
+             XXXXXXXXX.\n\
+             XXXXXXXX.\n",
+        );
+        assert_eq!(line, 5);
+
+        let (line, _) = parse_error("objective = \"lines 1\"\npieces = \"IQ\"\n");
+        assert_eq!(line, 2);
+
+        let (line, message) = parse_error("pieces = \"I\"\n[board]\nXXXXXXXXX.\n");
+        assert_eq!((line, message.as_str()), (0, "there's no objective"));
+    }
+}
@@ -0,0 +1,182 @@
+// The output side of the screen. The screen only keeps track of what every pixel
+// looks like, and a renderer is what actually shows the pixels somewhere, be it
+// a terminal or a string in memory.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::screen::{Color, Pixel, WIDE_FILLER};
+
+// A finished frame, as handed over to a renderer.
+pub struct Frame<'a> {
+    width: u32,
+    height: u32,
+    // These are stored column by column, the same as in the screen.
+    pixels: &'a [Pixel],
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(width: u32, height: u32, pixels: &'a [Pixel]) -> Frame<'a> {
+        Frame {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &Pixel {
+        &self.pixels[(x * self.height + y) as usize]
+    }
+
+    // The characters of one row, without any colors.
+    pub fn row_text(&self, y: u32) -> String {
+        (0..self.width)
+            .flat_map(|x| self.pixel(x, y).shape)
+            .filter(|character| *character != WIDE_FILLER)
+            .collect()
+    }
+
+    // The whole frame as plain text, one line for every row.
+    pub fn text(&self) -> String {
+        (0..self.height)
+            .map(|y| self.row_text(y))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+pub trait Renderer {
+    // Shows a finished frame.
+    fn present(&mut self, frame: &Frame);
+
+    // The screen changed size, so whatever was shown before has to go.
+    fn reset(&mut self) {}
+}
+
+// Goes back up to the top of the last frame, so that the next one gets drawn
+// over it.
+fn move_to_top(out: &mut String, frame: &Frame) {
+    *out += &format!("\x1B[{}D\x1B[{}A\n", frame.width(), frame.height() + 1);
+}
+
+fn clear_terminal() {
+    print!("\x1B[2J\x1B[H");
+    let _ = io::stdout().flush();
+}
+
+// Draws to a terminal that understands ANSI escape codes, with colors and all.
+#[derive(Default)]
+pub struct AnsiRenderer {
+    has_cursor_moved: bool,
+}
+
+impl Renderer for AnsiRenderer {
+    fn present(&mut self, frame: &Frame) {
+        let mut out = String::new();
+
+        if self.has_cursor_moved {
+            move_to_top(&mut out, frame);
+        }
+
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                let pixel = frame.pixel(x, y);
+
+                if let Some(styles) = pixel.styles {
+                    for (character, style) in pixel.shape.iter().zip(styles.iter()) {
+                        let codes = style.codes();
+
+                        match *character {
+                            WIDE_FILLER => (),
+                            character if codes.is_empty() => out.push(character),
+                            character => out += &format!("\x1B[{}m{}\x1B[0m", codes, character),
+                        }
+                    }
+
+                    continue;
+                }
+
+                match pixel.color {
+                    Color::Basic(code) => {
+                        out += &format!("\x1B[{}m{}{}\x1B[0m", code, pixel.shape[0], pixel.shape[1])
+                    }
+                    Color::Default => out.extend(pixel.shape),
+                }
+            }
+
+            out.push('\n');
+        }
+
+        print!("{}", out);
+        let _ = io::stdout().flush();
+
+        self.has_cursor_moved = true;
+    }
+
+    fn reset(&mut self) {
+        // The old frame might be bigger than the new one, so clear the terminal
+        // and start drawing from the top again.
+        clear_terminal();
+        self.has_cursor_moved = false;
+    }
+}
+
+// Draws to a terminal without any colors, for when they aren't wanted (like when
+// NO_COLOR is set). The cursor still gets moved around, since the game wouldn't
+// be very playable otherwise.
+#[derive(Default)]
+pub struct PlainRenderer {
+    has_cursor_moved: bool,
+}
+
+impl Renderer for PlainRenderer {
+    fn present(&mut self, frame: &Frame) {
+        let mut out = String::new();
+
+        if self.has_cursor_moved {
+            move_to_top(&mut out, frame);
+        }
+
+        out += &frame.text();
+        out.push('\n');
+
+        print!("{}", out);
+        let _ = io::stdout().flush();
+
+        self.has_cursor_moved = true;
+    }
+
+    fn reset(&mut self) {
+        clear_terminal();
+        self.has_cursor_moved = false;
+    }
+}
+
+// Keeps every frame as text instead of showing it, for when there's no terminal
+// to draw to. The frames are shared, so that they can still be looked at once
+// the screen has taken the renderer.
+#[derive(Default)]
+pub struct MemoryRenderer {
+    frames: Rc<RefCell<Vec<String>>>,
+}
+
+impl MemoryRenderer {
+    pub fn frames(&self) -> Rc<RefCell<Vec<String>>> {
+        Rc::clone(&self.frames)
+    }
+}
+
+impl Renderer for MemoryRenderer {
+    fn present(&mut self, frame: &Frame) {
+        self.frames.borrow_mut().push(frame.text());
+    }
+}
@@ -0,0 +1,616 @@
+// Recording and playing back games. Since the game is completely deterministic once
+// the seed is known, a replay is nothing more than the seed plus every input that
+// the player made, along with the frame on which it was made.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::screen::{
+    colors::basic::*, Blank, Color, Constraint, Label, ProgressBar, Screen, Split, Widget,
+};
+use crate::tetris::{Action, GameMode, Settings, Tetris};
+
+const REPLAY_HEADER: &str = "tetris-cli-replay";
+const REPLAY_VERSION: u32 = 6;
+
+// Replays from before this version were recorded with different gravity,
+// scoring and spawning, or without the line clear delay, so they can't be
+// played back anymore.
+const OLDEST_REPLAY_VERSION: u32 = 6;
+
+// Every finished game gets its replay saved in here, so that it can be watched
+// again from the replays menu.
+const LIBRARY_DIRECTORY: &str = "replays";
+const REPLAY_EXTENSION: &str = "replay";
+
+pub struct Replay {
+    pub seed: u64,
+    pub mode: GameMode,
+    pub settings: Settings,
+    pub inputs: Vec<(u32, Action)>,
+
+    // These are filled in once the game is over. They are what the playback gets
+    // checked against to make sure that it actually matches the original game.
+    pub frames: u32,
+    pub pieces: u32,
+    pub score: u32,
+    pub board_hash: u64,
+    // When the game was finished, as a UNIX timestamp.
+    pub date: u64,
+    // Whether the player kept going after reaching the goal of the mode.
+    pub endless: bool,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    // The file is fine, but the settings in it are not.
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ReplayError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, mode: GameMode, settings: &Settings) -> Replay {
+        Replay {
+            seed,
+            mode,
+            settings: settings.clone(),
+            inputs: Vec::new(),
+            frames: 0,
+            pieces: 0,
+            score: 0,
+            board_hash: 0,
+            date: 0,
+            endless: false,
+        }
+    }
+
+    pub fn record(&mut self, frame: u32, action: Action) {
+        self.inputs.push((frame, action));
+    }
+
+    // Stores the final state of the game so that playback can be verified.
+    pub fn finish(&mut self, game: &Tetris) {
+        self.frames = game.frame();
+        self.pieces = game.pieces_spawned();
+        self.score = game.score();
+        self.board_hash = game.board_hash();
+        self.date = crate::highscores::now();
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        fs::write(path, self.write())?;
+        Ok(())
+    }
+
+    // Writes the replay out in the same format that parse() reads.
+    fn write(&self) -> String {
+        let mut contents = format!("{} {}\n", REPLAY_HEADER, REPLAY_VERSION);
+
+        contents += &format!("seed {}\n", self.seed);
+        contents += &format!("mode {}\n", self.mode.key());
+        self.settings.write_fields(&mut contents);
+        contents += &format!("frames {}\n", self.frames);
+        contents += &format!("pieces {}\n", self.pieces);
+        contents += &format!("score {}\n", self.score);
+        contents += &format!("board {:016x}\n", self.board_hash);
+        contents += &format!("date {}\n", self.date);
+        contents += &format!("endless {}\n", self.endless);
+
+        for (frame, action) in self.inputs.iter() {
+            contents += &format!("{} {}\n", frame, action.name());
+        }
+
+        contents
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        Replay::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(contents: &str) -> Result<Replay, ReplayError> {
+        let mut lines = contents.lines().enumerate();
+
+        let error = |line: usize, message: &str| ReplayError::Parse {
+            line: line + 1,
+            message: message.to_string(),
+        };
+
+        // Versions that are still supported might not have all of the keys.
+        // Anything that's missing stays at its default value.
+        let version = match lines.next().and_then(|(_, header)| header.split_once(' ')) {
+            Some((REPLAY_HEADER, version)) => version.parse::<u32>().ok(),
+            _ => None,
+        };
+
+        match version {
+            Some(version) if (OLDEST_REPLAY_VERSION..=REPLAY_VERSION).contains(&version) => (),
+            Some(_) => {
+                return Err(error(
+                    0,
+                    "the replay is from an incompatible version of the game",
+                ))
+            }
+            None => return Err(error(0, "not a replay file")),
+        }
+
+        let mut replay = Replay::new(0, GameMode::Marathon, &Settings::default());
+
+        for (line_number, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| error(line_number, "expected a key and a value"))?;
+
+            let bad_number = || error(line_number, &format!("invalid number '{}'", value));
+
+            // Inputs start with the frame number, while everything else starts
+            // with a name.
+            if let Ok(frame) = key.parse::<u32>() {
+                let action = Action::from_name(value)
+                    .ok_or_else(|| error(line_number, &format!("unknown action '{}'", value)))?;
+
+                if let Some((previous_frame, _)) = replay.inputs.last() {
+                    if frame <= *previous_frame {
+                        return Err(error(line_number, "inputs are not in order"));
+                    }
+                }
+
+                replay.inputs.push((frame, action));
+                continue;
+            }
+
+            match replay.settings.read_field(key, value) {
+                Ok(true) => continue,
+                Ok(false) => (),
+                Err(()) => return Err(bad_number()),
+            }
+
+            match key {
+                // Only the remainder makes any difference to the game, and a
+                // seed that's any bigger could overflow in the generator.
+                "seed" => {
+                    replay.seed = value.parse::<u64>().map_err(|_| bad_number())?
+                        % crate::tetris::SEED_MODULUS
+                }
+                "mode" => {
+                    replay.mode = GameMode::from_key(value).ok_or_else(|| {
+                        error(line_number, &format!("unknown game mode '{}'", value))
+                    })?
+                }
+                "frames" => replay.frames = value.parse().map_err(|_| bad_number())?,
+                "pieces" => replay.pieces = value.parse().map_err(|_| bad_number())?,
+                "score" => replay.score = value.parse().map_err(|_| bad_number())?,
+                "board" => {
+                    replay.board_hash = u64::from_str_radix(value, 16).map_err(|_| bad_number())?
+                }
+                "date" => replay.date = value.parse().map_err(|_| bad_number())?,
+                "endless" => {
+                    replay.endless = value
+                        .parse()
+                        .map_err(|_| error(line_number, "expected true or false"))?
+                }
+                _ => return Err(error(line_number, &format!("unknown key '{}'", key))),
+            }
+        }
+
+        // A replay with a broken board would crash the game, so this gets checked
+        // the same way as the config file.
+        crate::config::validate_settings(&replay.settings).map_err(|(key, message)| {
+            ReplayError::Invalid(format!("invalid value for '{}': {}", key, message))
+        })?;
+
+        Ok(replay)
+    }
+
+    // Saves the replay into the library, named after the date and the game mode.
+    pub fn save_to_library(&self) -> Result<(), ReplayError> {
+        let directory = match library_dir() {
+            Some(directory) => directory,
+            None => return Ok(()),
+        };
+
+        fs::create_dir_all(&directory)?;
+
+        let name = format!("{}-{}.{}", self.date, self.mode.key(), REPLAY_EXTENSION);
+        self.save(&directory.join(name))
+    }
+
+    // How long the game went on for.
+    pub fn time_ms(&self) -> u64 {
+        self.frames as u64 * 1000 / self.settings.frame_rate as u64
+    }
+}
+
+fn library_dir() -> Option<PathBuf> {
+    Some(crate::paths::data_dir()?.join(LIBRARY_DIRECTORY))
+}
+
+// All of the replays in the library, newest first. The file names start with the
+// date, so sorting them by name is enough.
+pub fn list_library() -> Vec<PathBuf> {
+    let entries = match library_dir().map(fs::read_dir) {
+        Some(Ok(entries)) => entries,
+        _ => return Vec::new(),
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == REPLAY_EXTENSION)
+        })
+        .collect();
+
+    paths.sort();
+    paths.reverse();
+    paths
+}
+
+// Sets up the game that a replay starts from. Games that went on past their goal
+// are endless from the start, since that's the only difference it makes.
+fn start_game(replay: &Replay) -> Tetris {
+    let mut game = Tetris::new(replay.seed, replay.mode, &replay.settings);
+
+    if replay.endless {
+        game.keep_going();
+    }
+
+    game
+}
+
+// The playback speeds that can be cycled through with + and -.
+const SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+
+// What we found out after playing back the whole replay.
+#[derive(Debug)]
+pub enum Verification {
+    Matched,
+    Desync {
+        expected_score: u32,
+        actual_score: u32,
+        expected_board_hash: u64,
+        actual_board_hash: u64,
+    },
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verification::Matched => write!(f, "replay verified, final score and board match"),
+            Verification::Desync {
+                expected_score,
+                actual_score,
+                expected_board_hash,
+                actual_board_hash,
+            } => write!(
+                f,
+                "replay desynced: expected score {} and board {:016x}, got score {} and board {:016x}",
+                expected_score, expected_board_hash, actual_score, actual_board_hash
+            ),
+        }
+    }
+}
+
+// Drives a game from the inputs of a replay, with the usual video player controls.
+pub struct Player {
+    replay: Replay,
+    game: Tetris,
+    next_input: usize,
+
+    is_running: bool,
+    paused: bool,
+    speed: usize,
+
+    // How many frames we owe the game. At speeds below 1x, this takes several
+    // real frames to reach one, and at higher speeds several game frames are
+    // played in a single real frame.
+    frame_progress: f32,
+
+    // The piece number that is being typed in, if the viewer is about to jump
+    // to a piece.
+    piece_prompt: Option<String>,
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> Player {
+        Player {
+            game: start_game(&replay),
+            replay,
+            next_input: 0,
+
+            is_running: true,
+            paused: false,
+            speed: NORMAL_SPEED,
+
+            frame_progress: 0.0,
+            piece_prompt: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    fn is_finished(&self) -> bool {
+        !self.game.is_running() || self.game.frame() >= self.replay.frames
+    }
+
+    // Plays exactly one frame of the game.
+    fn step(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+
+        let input = match self.replay.inputs.get(self.next_input) {
+            Some((frame, action)) if *frame == self.game.frame() => {
+                self.next_input += 1;
+                Some(*action)
+            }
+            _ => None,
+        };
+
+        self.game.update(input);
+    }
+
+    fn restart(&mut self) {
+        self.game = start_game(&self.replay);
+        self.next_input = 0;
+        self.frame_progress = 0.0;
+    }
+
+    // Seeking is done by playing the game from the start up until the moment
+    // that the piece spawned, which is plenty fast since nothing gets drawn.
+    fn jump_to_piece(&mut self, piece: u32) {
+        let piece = piece.clamp(1, self.replay.pieces.max(1));
+
+        if piece <= self.game.pieces_spawned() {
+            self.restart();
+        }
+
+        while self.game.pieces_spawned() < piece && !self.is_finished() {
+            self.step();
+        }
+    }
+
+    // Once the replay has been played all the way through, this tells whether
+    // we ended up in the same place as the original game.
+    pub fn verify(&self) -> Option<Verification> {
+        if !self.is_finished() {
+            return None;
+        }
+
+        let actual_score = self.game.score();
+        let actual_board_hash = self.game.board_hash();
+
+        if actual_score == self.replay.score && actual_board_hash == self.replay.board_hash {
+            Some(Verification::Matched)
+        } else {
+            Some(Verification::Desync {
+                expected_score: self.replay.score,
+                actual_score,
+                expected_board_hash: self.replay.board_hash,
+                actual_board_hash,
+            })
+        }
+    }
+
+    pub fn handle_key(&mut self, key: char) {
+        // While typing in a piece number, everything goes to the prompt.
+        if let Some(prompt) = self.piece_prompt.as_mut() {
+            match key {
+                '0'..='9' => prompt.push(key),
+                '\x7F' | '\x08' => {
+                    prompt.pop();
+                }
+                '\n' | '\r' => {
+                    if let Ok(piece) = prompt.parse() {
+                        self.jump_to_piece(piece);
+                    }
+                    self.piece_prompt = None;
+                }
+                '\x1B' => self.piece_prompt = None,
+                _ => (),
+            }
+
+            return;
+        }
+
+        match key {
+            ' ' => self.paused = !self.paused,
+            '+' | '=' => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            '-' => self.speed = self.speed.saturating_sub(1),
+            '.' => {
+                self.paused = true;
+                self.step();
+            }
+            'n' => self.jump_to_piece(self.game.pieces_spawned() + 1),
+            'p' => self.jump_to_piece(self.game.pieces_spawned().saturating_sub(1)),
+            'g' => self.piece_prompt = Some(String::new()),
+            'r' => self.restart(),
+            'q' => self.is_running = false,
+            _ => (),
+        }
+    }
+
+    pub fn update(&mut self) {
+        if self.paused {
+            return;
+        }
+
+        self.frame_progress += SPEEDS[self.speed];
+
+        while self.frame_progress >= 1.0 {
+            self.frame_progress -= 1.0;
+            self.step();
+        }
+    }
+
+    pub fn render(&mut self, screen: &mut Screen) {
+        self.game.render(screen);
+
+        // The replay stuff goes where the controls usually are.
+        let area = self.game.panel_areas(screen).controls;
+
+        let status = if let Some(prompt) = self.piece_prompt.as_ref() {
+            format!("Go to piece: {}_", prompt)
+        } else {
+            match self.verify() {
+                Some(Verification::Matched) => String::from("Finished, verified"),
+                Some(Verification::Desync { .. }) => String::from("Finished, DESYNC!"),
+                None => String::new(),
+            }
+        };
+
+        let progress = if self.replay.frames == 0 {
+            1.0
+        } else {
+            self.game.frame() as f32 / self.replay.frames as f32
+        };
+
+        let lines =
+            |lines: Vec<String>| -> Box<dyn Widget> { Box::new(Label::new(lines.join("\n"))) };
+
+        Split::vertical(vec![
+            (
+                Constraint::Length(4),
+                lines(vec![
+                    String::from(if self.paused {
+                        "REPLAY (PAUSED)"
+                    } else {
+                        "REPLAY"
+                    }),
+                    format!("Speed: {}x", SPEEDS[self.speed]),
+                    format!("Frame {}/{}", self.game.frame(), self.replay.frames),
+                    format!(
+                        "Piece {}/{}",
+                        self.game.pieces_spawned(),
+                        self.replay.pieces
+                    ),
+                ]),
+            ),
+            (
+                Constraint::Length(1),
+                Box::new(Split::horizontal(vec![
+                    (
+                        Constraint::Fill,
+                        Box::new(ProgressBar {
+                            progress,
+                            color: Color::Basic(GREEN),
+                        }),
+                    ),
+                    (Constraint::Length(1), Box::new(Blank)),
+                ])),
+            ),
+            (Constraint::Length(1), lines(vec![status])),
+            (
+                Constraint::Fill,
+                lines(vec![
+                    String::from("SPACE pause  +/- speed . step"),
+                    String::from("n/p next/prev piece  g go to"),
+                    String::from("r restart  q quit"),
+                ]),
+            ),
+        ])
+        .draw(screen, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error_line(contents: &str) -> usize {
+        match Replay::parse(contents) {
+            Err(ReplayError::Parse { line, .. }) => line,
+            Err(error) => panic!("expected a parse error, got {}", error),
+            Ok(_) => panic!("expected a parse error, but it loaded"),
+        }
+    }
+
+    #[test]
+    fn replays_round_trip() {
+        let mut replay = Replay::new(42, GameMode::Sprint, &Settings::default());
+        replay.record(3, Action::MoveLeft);
+        replay.record(4, Action::RotateRight);
+        replay.record(20, Action::HardDrop);
+        replay.frames = 25;
+        replay.pieces = 2;
+        replay.score = 30;
+        replay.board_hash = 0x0123456789abcdef;
+        replay.date = 1700000000;
+        replay.endless = true;
+
+        let contents = replay.write();
+        let loaded = Replay::parse(&contents).unwrap();
+
+        assert_eq!(loaded.seed, replay.seed);
+        assert_eq!(loaded.mode, replay.mode);
+        assert_eq!(loaded.inputs, replay.inputs);
+        assert_eq!(loaded.board_hash, replay.board_hash);
+        assert!(loaded.endless);
+        assert_eq!(loaded.write(), contents);
+    }
+
+    #[test]
+    fn bad_replays_are_rejected() {
+        assert_eq!(parse_error_line("tetris-cli-save 9\nseed 1\n"), 1);
+        assert_eq!(parse_error_line("tetris-cli-replay 5\nseed 1\n"), 1);
+
+        let inputs_out_of_order = "tetris-cli-replay 6\n\
+                                   seed 1\n\
+                                   mode marathon\n\
+                                   5 move_left\n\
+                                   5 hard_drop\n";
+        assert_eq!(parse_error_line(inputs_out_of_order), 5);
+
+        let unknown_action = "tetris-cli-replay 6\n\
+                              seed 1\n\
+                              mode marathon\n\
+                              5 teleport\n";
+        assert_eq!(parse_error_line(unknown_action), 4);
+    }
+
+    #[test]
+    fn huge_seeds_play_back() {
+        let replay = Replay::parse(
+            "tetris-cli-replay 6\n\
+             seed 18446744073709551615\n\
+             mode marathon\n\
+             frames 3\n\
+             1 hard_drop\n\
+             2 hard_drop\n",
+        )
+        .unwrap();
+        assert!(replay.seed < crate::tetris::SEED_MODULUS);
+
+        let mut player = Player::new(replay);
+        while player.verify().is_none() {
+            player.update();
+        }
+    }
+}
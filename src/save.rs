@@ -0,0 +1,359 @@
+// Suspending a game to disk so that it can be continued the next time the game is
+// started. The game gets saved whenever the player quits in the middle of it, or
+// when the program is asked to stop (with SIGTERM or by the terminal closing).
+//
+// The save file has the same "key value" layout as replays, split into sections:
+//
+//     tetris-cli-save 9
+//     mode marathon
+//     seed 1234
+//     [settings]
+//     ...
+//     [state]
+//     ...
+//     [inputs]
+//     ...
+//
+// The inputs are the replay so far, so that a game that gets continued can still
+// be recorded from the very start.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::replay::Replay;
+use crate::tetris::{Action, GameMode, Settings, Tetris};
+
+const SAVE_HEADER: &str = "tetris-cli-save";
+const SAVE_FILE: &str = "save";
+
+// This has to be bumped whenever the format of the state changes, so that old
+// saves get turned away instead of being loaded wrong.
+const SAVE_VERSION: u32 = 9;
+
+pub struct SavedGame {
+    pub game: Tetris,
+    // The mode, seed, settings and inputs of the game up until it was saved.
+    pub replay: Replay,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    // The save is from a different version of the game.
+    Incompatible(u32),
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "{}", error),
+            SaveError::Incompatible(version) => {
+                write!(f, "save version {} isn't supported", version)
+            }
+            SaveError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+fn save_path() -> Option<PathBuf> {
+    Some(crate::paths::data_dir()?.join(SAVE_FILE))
+}
+
+impl SavedGame {
+    // Saves the game, replacing whatever was saved before. Like everything else,
+    // this goes through a temporary file so that a broken save can't be left
+    // behind if we get killed halfway through.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match save_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let contents = self.write();
+
+        let mut temporary_path = path.clone().into_os_string();
+        temporary_path.push(".tmp");
+
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        fs::rename(&temporary_path, &path)
+    }
+
+    // Writes the game out in the same format that parse() reads.
+    fn write(&self) -> String {
+        let mut contents = format!("{} {}\n", SAVE_HEADER, SAVE_VERSION);
+        contents += &format!("mode {}\n", self.replay.mode.key());
+        contents += &format!("seed {}\n", self.replay.seed);
+
+        contents += "[settings]\n";
+        self.replay.settings.write_fields(&mut contents);
+
+        contents += "[state]\n";
+        self.game.write_state(&mut contents);
+
+        contents += "[inputs]\n";
+        for (frame, action) in self.replay.inputs.iter() {
+            contents += &format!("{} {}\n", frame, action.name());
+        }
+
+        contents
+    }
+
+    // Loads the saved game, if there is one.
+    pub fn load() -> Result<Option<SavedGame>, SaveError> {
+        let path = match save_path() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        match fs::read_to_string(path) {
+            Ok(contents) => SavedGame::parse(&contents).map(Some),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn parse(contents: &str) -> Result<SavedGame, SaveError> {
+        let invalid = |line: usize, message: &str| SaveError::Invalid {
+            line: line + 1,
+            message: message.to_string(),
+        };
+
+        let mut lines = contents.lines().enumerate();
+
+        // Check the version before anything else, since the rest of the file
+        // could mean something completely different in another version.
+        let version = match lines.next().and_then(|(_, header)| header.split_once(' ')) {
+            Some((SAVE_HEADER, version)) => version
+                .parse::<u32>()
+                .map_err(|_| invalid(0, "invalid version"))?,
+            _ => return Err(invalid(0, "not a saved game")),
+        };
+
+        if version != SAVE_VERSION {
+            return Err(SaveError::Incompatible(version));
+        }
+
+        let mut mode = None;
+        let mut replay = Replay::new(0, GameMode::Marathon, &Settings::default());
+        let mut state_fields = Vec::new();
+        let mut section = "";
+
+        for (line_number, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                section = match name {
+                    "settings" | "state" | "inputs" => name,
+                    _ => return Err(invalid(line_number, "unknown section")),
+                };
+
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(line_number, "expected a key and a value"))?;
+
+            match section {
+                "settings" => match replay.settings.read_field(key, value) {
+                    Ok(true) => (),
+                    _ => return Err(invalid(line_number, &format!("invalid setting '{}'", key))),
+                },
+                "state" => state_fields.push((key, value)),
+                "inputs" => {
+                    let frame = key
+                        .parse()
+                        .map_err(|_| invalid(line_number, "invalid frame"))?;
+                    let action = Action::from_name(value)
+                        .ok_or_else(|| invalid(line_number, "unknown action"))?;
+
+                    // The same as in replays, the frames only ever go up.
+                    if let Some((previous_frame, _)) = replay.inputs.last() {
+                        if frame <= *previous_frame {
+                            return Err(invalid(line_number, "inputs are not in order"));
+                        }
+                    }
+
+                    replay.record(frame, action);
+                }
+                _ => match key {
+                    "mode" => mode = GameMode::from_key(value),
+                    "seed" => {
+                        replay.seed = value
+                            .parse()
+                            .map_err(|_| invalid(line_number, "invalid seed"))?
+                    }
+                    _ => return Err(invalid(line_number, &format!("unknown key '{}'", key))),
+                },
+            }
+        }
+
+        replay.mode = mode.ok_or_else(|| invalid(0, "missing or unknown game mode"))?;
+
+        crate::config::validate_settings(&replay.settings).map_err(|(key, message)| {
+            invalid(0, &format!("invalid value for '{}': {}", key, message))
+        })?;
+
+        let game = Tetris::read_state(replay.mode, &replay.settings, &state_fields)
+            .map_err(|message| invalid(0, &message))?;
+
+        replay.endless = game.is_endless();
+
+        Ok(SavedGame { game, replay })
+    }
+
+    // Gets rid of the saved game, once it has been continued and finished.
+    pub fn delete() {
+        if let Some(path) = save_path() {
+            if let Err(error) = fs::remove_file(path) {
+                if error.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to delete the saved game: {}", error);
+                }
+            }
+        }
+    }
+}
+
+// Set by the signal handler when we get asked to stop.
+static TERMINATION_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_family = "unix")]
+extern "C" fn handle_termination(_signal: std::os::raw::c_int) {
+    // Storing to an atomic is about the only thing that's safe to do in here.
+    TERMINATION_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// While a game is being played, SIGTERM and SIGHUP don't kill the program right
+// away. Instead, the game notices (with termination_requested()), saves itself
+// and then exits.
+#[cfg(target_family = "unix")]
+pub fn catch_termination() {
+    use crate::system::signal;
+
+    unsafe {
+        signal::signal(signal::SIGTERM as i32, Some(handle_termination));
+        signal::signal(signal::SIGHUP as i32, Some(handle_termination));
+    }
+}
+
+// Puts the signals back to normal once the game is over, so that the menus can
+// still be killed like any other program.
+#[cfg(target_family = "unix")]
+pub fn release_termination() {
+    use crate::system::signal;
+
+    unsafe {
+        signal::signal(signal::SIGTERM as i32, signal::SIG_DFL);
+        signal::signal(signal::SIGHUP as i32, signal::SIG_DFL);
+    }
+}
+
+// Windows doesn't have any of these signals.
+#[cfg(target_family = "windows")]
+pub fn catch_termination() {}
+
+#[cfg(target_family = "windows")]
+pub fn release_termination() {}
+
+pub fn termination_requested() -> bool {
+    TERMINATION_REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_game() -> SavedGame {
+        let settings = Settings::default();
+        let mut game = Tetris::new(42, GameMode::Marathon, &settings);
+        let mut replay = Replay::new(42, GameMode::Marathon, &settings);
+
+        let inputs = [
+            Action::MoveLeft,
+            Action::RotateRight,
+            Action::HardDrop,
+            Action::Hold,
+            Action::HardDrop,
+        ];
+
+        for action in inputs {
+            replay.record(game.frame(), action);
+            game.update(Some(action));
+
+            for _ in 0..5 {
+                game.update(None);
+            }
+        }
+
+        SavedGame { game, replay }
+    }
+
+    fn invalid_line(contents: &str) -> usize {
+        match SavedGame::parse(contents) {
+            Err(SaveError::Invalid { line, .. }) => line,
+            Err(error) => panic!("expected an invalid save, got {}", error),
+            Ok(_) => panic!("expected an invalid save, but it loaded"),
+        }
+    }
+
+    #[test]
+    fn saves_round_trip() {
+        let saved_game = saved_game();
+        let contents = saved_game.write();
+        let loaded = SavedGame::parse(&contents).unwrap();
+
+        assert_eq!(loaded.replay.inputs, saved_game.replay.inputs);
+        assert_eq!(loaded.write(), contents);
+    }
+
+    #[test]
+    fn inputs_out_of_order_are_rejected() {
+        let contents = saved_game().write();
+        let (head, inputs) = contents.split_once("[inputs]\n").unwrap();
+
+        // Swap the first two inputs around.
+        let mut lines: Vec<&str> = inputs.lines().collect();
+        lines.swap(0, 1);
+        let contents = format!("{}[inputs]\n{}\n", head, lines.join("\n"));
+
+        // The second input is the one that goes backwards.
+        assert_eq!(invalid_line(&contents), head.lines().count() + 3);
+    }
+
+    #[test]
+    fn other_versions_are_turned_away() {
+        let contents = saved_game().write().replacen(
+            &format!("{} {}", SAVE_HEADER, SAVE_VERSION),
+            &format!("{} {}", SAVE_HEADER, SAVE_VERSION - 1),
+            1,
+        );
+
+        assert!(matches!(
+            SavedGame::parse(&contents),
+            Err(SaveError::Incompatible(version)) if version == SAVE_VERSION - 1
+        ));
+        assert_eq!(invalid_line("tetris-cli-replay 6\n"), 1);
+    }
+}
@@ -9,6 +9,8 @@ use std::thread;
 use super::system::{termios as term, unistd};
 use std::ops::{Index, IndexMut};
 
+use crate::renderer::{AnsiRenderer, Frame, PlainRenderer, Renderer};
+
 // A basic representation of a "pixel"
 #[derive(Clone, PartialEq, Debug)]
 pub struct Pixel {
@@ -19,6 +21,11 @@ pub struct Pixel {
 
     // The color of the pixel
     pub color: Color,
+
+    // Text doesn't have to line up with the pixels, so each of the two
+    // characters can have its own style. Graphics leave this as None, and the
+    // color goes for both characters.
+    pub styles: Option<[Style; 2]>,
 }
 
 impl Default for Pixel {
@@ -26,12 +33,18 @@ impl Default for Pixel {
         Pixel {
             shape: [' ', ' '],
             color: Color::Default,
+            styles: None,
         }
     }
 }
 
+// The second column of a wide character. It goes in the pixel right after the
+// wide character and doesn't get printed, since the terminal already moved
+// over by two columns.
+pub const WIDE_FILLER: char = '\0';
+
 // Support for RGB
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Color {
     Default,
     Basic(u8), // Basic color support. Use for maximum compatibility. Only have 16 colors available.
@@ -50,29 +63,172 @@ pub mod colors {
 
         // Bright colors.
         pub const BRIGHT_BLACK: u8 = 90;
-        pub const _BRIGHT_RED: u8 = 91;
-        pub const _BRIGHT_GREEN: u8 = 92;
+        pub const BRIGHT_RED: u8 = 91;
+        pub const BRIGHT_GREEN: u8 = 92;
         pub const BRIGHT_YELLOW: u8 = 93;
-        pub const _BRIGHT_BLUE: u8 = 94;
-        pub const _BRIGHT_MAGENTA: u8 = 95;
-        pub const _BRIGHT_CYAN: u8 = 96;
-        pub const _BRIGHT_WHITE: u8 = 97;
+        pub const BRIGHT_BLUE: u8 = 94;
+        pub const BRIGHT_MAGENTA: u8 = 95;
+        pub const BRIGHT_CYAN: u8 = 96;
+        pub const BRIGHT_WHITE: u8 = 97;
+    }
+}
+
+// How a character of text looks.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Style {
+    pub color: Color,
+    pub background: Color,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            color: Color::Default,
+            background: Color::Default,
+            bold: false,
+            underline: false,
+        }
+    }
+}
+
+impl Style {
+    pub fn colored(color: Color) -> Style {
+        Style {
+            color,
+            ..Style::default()
+        }
+    }
+
+    // The escape codes that turn this style on, separated by semicolons.
+    pub fn codes(&self) -> String {
+        let mut codes = Vec::new();
+
+        if let Color::Basic(code) = self.color {
+            codes.push(code.to_string());
+        }
+
+        // The background colors are the same as the foreground ones, just 10
+        // further along.
+        if let Color::Basic(code) = self.background {
+            codes.push((code + 10).to_string());
+        }
+
+        if self.bold {
+            codes.push(String::from("1"));
+        }
+
+        if self.underline {
+            codes.push(String::from("4"));
+        }
+
+        codes.join(";")
     }
 }
 
+// Where text goes within the space that it's given.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+// Somewhere on the screen for text to go. Unlike everything else, this is
+// measured in columns of the terminal rather than pixels, so that text can
+// start halfway through a pixel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextArea {
+    pub column: u32,
+    pub row: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TextOptions {
+    pub style: Style,
+    pub align: Align,
+    // Without wrapping, lines that are too long get cut off.
+    pub wrap: bool,
+}
+
+// Breaks text up into lines that are at most `width` columns wide, splitting
+// between words where possible. Words that are too long for a line of their
+// own get split wherever they run out of room.
+pub fn wrap_text(text: &str, width: u32) -> Vec<String> {
+    use crate::unicode::{char_width, text_width};
+
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            let line_width = text_width(&line);
+            let word_width = text_width(word);
+
+            if line.is_empty() && word_width <= width {
+                line += word;
+                continue;
+            }
+
+            if line_width + 1 + word_width <= width {
+                line.push(' ');
+                line += word;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            for character in word.chars() {
+                if text_width(&line) + char_width(character) > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+
+                line.push(character);
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Cuts text off once it gets wider than `width` columns.
+fn clip_text(text: &str, width: u32) -> String {
+    let mut used = 0;
+
+    text.chars()
+        .take_while(|character| {
+            used += crate::unicode::char_width(*character);
+            used <= width
+        })
+        .collect()
+}
+
 // A basic abstraction of a screen that makes it easier to render bitmap graphics
 // on the terminal
 pub struct Screen {
     width: u32,
     height: u32,
 
-    has_cursor_moved: bool,
+    // Screens that aren't on the terminal don't read any input, and don't have
+    // to put the terminal back the way it was at the end.
+    is_terminal: bool,
 
     event_reciever: Receiver<char>,
 
     // Used a single-dimensional vector instead of a vector of vectors to improve
     // performance.
     pixels: Vec<Pixel>,
+
+    renderer: Box<dyn Renderer>,
 }
 //
 // Basically, read whatever key the user has pressed from the terminal
@@ -141,24 +297,52 @@ impl Screen {
         // And, yes, the thread runs until the program itself stops.
         // That's probably not a good idea but it's the best we've got.
 
+        // Colors can be turned off the usual way.
+        let renderer: Box<dyn Renderer> = if std::env::var_os("NO_COLOR").is_some() {
+            Box::new(PlainRenderer::default())
+        } else {
+            Box::new(AnsiRenderer::default())
+        };
+
+        Ok(Screen {
+            width,
+            height,
+            is_terminal: true,
+            event_reciever,
+            pixels: vec![Pixel::default(); (width * height).try_into()?],
+            renderer,
+        })
+    }
+
+    // Creates a screen that isn't hooked up to the terminal at all, and just
+    // hands its frames to the renderer. There's never any input.
+    pub fn with_renderer(
+        width: u32,
+        height: u32,
+        renderer: Box<dyn Renderer>,
+    ) -> Result<Screen, std::num::TryFromIntError> {
+        // Nothing ever gets sent, since the sender is gone straight away.
+        let (_, event_reciever) = channel();
+
         Ok(Screen {
             width,
             height,
+            is_terminal: false,
             event_reciever,
-            has_cursor_moved: false,
             pixels: vec![Pixel::default(); (width * height).try_into()?],
+            renderer,
         })
     }
 
     // Returns the width of the screen. This can be used by clients to ensure
     // that they don't try to write to pixels that are out of bounds, which
     // can cause the program to panic.
-    pub fn _width(&self) -> u32 {
+    pub fn width(&self) -> u32 {
         self.width
     }
 
     // Returns the height of the screen. Same use case as the width() function
-    pub fn _height(&self) -> u32 {
+    pub fn height(&self) -> u32 {
         self.height
     }
 
@@ -176,14 +360,19 @@ impl Screen {
     // anyways for completeness's sake. And with the hope that, in the future, I might
     // be able to copy and paste this file for another project.
     //
+    // Turns out that it does get used, for when the board size gets changed in
+    // the settings.
+    //
     // By the way, after resizing the screen would be blank so...
-    pub fn _resize(&mut self, new_width: u32, new_height: u32) {
+    pub fn resize(&mut self, new_width: u32, new_height: u32) {
         self.width = new_width;
         self.height = new_height;
         self.pixels.resize(
             (new_width * new_height).try_into().unwrap(),
             Pixel::default(),
         );
+
+        self.renderer.reset();
     }
 
     // Takes the first event from the event channel and return it if it exists. If there
@@ -192,44 +381,22 @@ impl Screen {
         self.event_reciever.try_recv()
     }
 
-    // Finally, the function that you've all been waiting for. This guy does all of the
-    // hard work of going through the pixels and drawing them on the terminal.
+    // Finally, the function that you've all been waiting for. This guy hands the
+    // pixels over to the renderer, which does all of the hard work of actually
+    // showing them.
     pub fn present(&mut self) {
-        if self.has_cursor_moved {
-            println!("\x1B[{}D\x1B[{}A", self.width, self.height + 1);
-        }
-
-        // Move to the start of the screen before printing.
-        // print!("\x1B[H");
-
-        for i in 0..self.height {
-            for j in 0..self.width {
-                let pixel: &Pixel = &self[j][i as usize];
-
-                match pixel.color {
-                    Color::Basic(code) => {
-                        print!("\x1B[{}m{}{}\x1B[0m", code, pixel.shape[0], pixel.shape[1])
-                    }
-                    Color::Default => print!("{}{}", pixel.shape[0], pixel.shape[1]),
-                }
-            }
-
-            println!("");
-        }
-
-        self.has_cursor_moved = true;
+        let frame = Frame::new(self.width, self.height, &self.pixels);
+        self.renderer.present(&frame);
     }
 }
 
-// In Tetris, all shapes are made up of only 4 pixels.
-const SHAPE_PIXEL_COUNT: usize = 4;
-
 // A struct for a shape.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Shape {
     // The squares that are taken up by the shape, relative to the
-    // shape itself.
-    pub pixels: [(i16, i16); SHAPE_PIXEL_COUNT],
+    // shape itself. In Tetris there are always 4 of them, but other
+    // piece sets can have anywhere from 1 to 9.
+    pub pixels: Vec<(i16, i16)>,
     // The pixel to fill the shape with.
     pub fill_pixel: Pixel,
 }
@@ -253,6 +420,7 @@ impl Shape {
         })
     }
 
+    #[allow(dead_code)]
     pub fn flip(&mut self, horizontally: bool) {
         self.pixels.iter_mut().for_each(|coordinates| {
             let (old_x, old_y) = *coordinates;
@@ -266,9 +434,13 @@ impl Shape {
         })
     }
 
-    pub fn is_within_bounds(&self, x: u16, y: u16) -> (bool, bool) {
-        use crate::tetris::{GAME_HEIGHT, GAME_WIDTH};
-
+    pub fn is_within_bounds(
+        &self,
+        x: u16,
+        y: u16,
+        board_width: u32,
+        board_height: u32,
+    ) -> (bool, bool) {
         let mut within_x_bounds = true;
         let mut within_y_bounds = true;
 
@@ -276,11 +448,11 @@ impl Shape {
             let block_x: i16 = block_x + <u16 as TryInto<i16>>::try_into(x).unwrap();
             let block_y: i16 = block_y + <u16 as TryInto<i16>>::try_into(y).unwrap();
 
-            if block_y >= GAME_HEIGHT as i16 || block_y <= 0 {
+            if block_y >= board_height as i16 || block_y < 0 {
                 within_y_bounds = false;
             }
 
-            if block_x > GAME_WIDTH as i16 || block_x <= 0 {
+            if block_x > board_width as i16 || block_x <= 0 {
                 within_x_bounds = false;
             }
         });
@@ -336,6 +508,7 @@ impl Screen {
     }
 
     // Fills an area of the screen with a specific color.
+    #[allow(dead_code)]
     pub fn fill_area_with_pixel(
         &mut self,
         pixel: &Pixel,
@@ -381,11 +554,13 @@ impl Screen {
             self[i.into()][<u16 as Into<usize>>::into(top)] = Pixel {
                 shape: [BOX_DRAWINGS_LIGHT_HORIZONTAL, BOX_DRAWINGS_LIGHT_HORIZONTAL],
                 color: Color::Default,
+                styles: None,
             };
 
             self[i.into()][<u16 as Into<usize>>::into(bottom)] = Pixel {
                 shape: [BOX_DRAWINGS_LIGHT_HORIZONTAL, BOX_DRAWINGS_LIGHT_HORIZONTAL],
                 color: Color::Default,
+                styles: None,
             };
         }
 
@@ -396,11 +571,13 @@ impl Screen {
             self[left.into()][<u16 as Into<usize>>::into(i)] = Pixel {
                 shape: [' ', BOX_DRAWINGS_LIGHT_VERTICAL],
                 color: Color::Default,
+                styles: None,
             };
 
             self[right.into()][<u16 as Into<usize>>::into(i)] = Pixel {
                 shape: [BOX_DRAWINGS_LIGHT_VERTICAL, ' '],
                 color: Color::Default,
+                styles: None,
             };
         }
 
@@ -414,57 +591,514 @@ impl Screen {
         self[left.into()][<u16 as Into<usize>>::into(top)] = Pixel {
             shape: [' ', BOX_DRAWINGS_LIGHT_DOWN_AND_RIGHT],
             color: Color::Default,
+            styles: None,
         };
 
         // top right
         self[right.into()][<u16 as Into<usize>>::into(top)] = Pixel {
             shape: [BOX_DRAWINGS_LIGHT_DOWN_AND_LEFT, ' '],
             color: Color::Default,
+            styles: None,
         };
 
         // bottom left
         self[left.into()][<u16 as Into<usize>>::into(bottom)] = Pixel {
             shape: [' ', BOX_DRAWINGS_LIGHT_UP_AND_RIGHT],
             color: Color::Default,
+            styles: None,
         };
 
         // bottom right
         self[right.into()][<u16 as Into<usize>>::into(bottom)] = Pixel {
             shape: [BOX_DRAWINGS_LIGHT_UP_AND_LEFT, ' '],
             color: Color::Default,
+            styles: None,
         };
 
         Ok(())
     }
 
     pub fn draw_text(&mut self, x: u32, y: u32, text: &str) {
-        if x >= self.width || y >= self.height {
+        let width = self.width.saturating_sub(x);
+        self.draw_aligned_text(x, y, width, text, Color::Default, Align::Left);
+    }
+
+    pub fn draw_colored_text(&mut self, x: u32, y: u32, text: &str, color: Color) {
+        let width = self.width.saturating_sub(x);
+        self.draw_aligned_text(x, y, width, text, color, Align::Left);
+    }
+
+    // Draws text somewhere within a span of pixels, cutting off whatever doesn't
+    // fit.
+    pub fn draw_aligned_text(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        text: &str,
+        color: Color,
+        align: Align,
+    ) {
+        let area = TextArea {
+            column: x * 2,
+            row: y,
+            width: width * 2,
+            height: 1,
+        };
+
+        let options = TextOptions {
+            style: Style::colored(color),
+            align,
+            wrap: false,
+        };
+
+        self.draw_text_in(area, text, &options);
+    }
+
+    // Draws text into an area of the screen, one line after another. Returns
+    // how many lines it took up, which can be more than the area has room for
+    // if the text got cut off.
+    pub fn draw_text_in(&mut self, area: TextArea, text: &str, options: &TextOptions) -> u32 {
+        use crate::unicode::{char_width, text_width};
+
+        let lines = if options.wrap {
+            wrap_text(text, area.width)
+        } else {
+            text.split('\n').map(String::from).collect()
+        };
+
+        for (i, line) in lines.iter().take(area.height as usize).enumerate() {
+            let line = clip_text(line, area.width);
+            let line_width = text_width(&line);
+
+            let mut column = area.column
+                + match options.align {
+                    Align::Left => 0,
+                    Align::Center => (area.width - line_width) / 2,
+                    Align::Right => area.width - line_width,
+                };
+
+            for character in line.chars() {
+                let width = char_width(character);
+
+                if width > 0 {
+                    self.put_character(column, area.row + i as u32, character, options.style);
+                    column += width;
+                }
+            }
+        }
+
+        lines.len() as u32
+    }
+
+    // Puts a single character on the screen at a column of the terminal. Wide
+    // characters take up the next column as well.
+    fn put_character(&mut self, column: u32, row: u32, character: char, style: Style) {
+        let width = crate::unicode::char_width(character);
+
+        if row >= self.height || column + width > self.width * 2 {
+            return;
+        }
+
+        // Whatever wide characters are there get cut in half, so they have to
+        // go completely.
+        for column in column..column + width {
+            match self.character_at(column, row) {
+                WIDE_FILLER if column > 0 => self.set_character(column - 1, row, ' ', None),
+                old if crate::unicode::char_width(old) == 2 && column + 1 < self.width * 2 => {
+                    self.set_character(column + 1, row, ' ', None)
+                }
+                _ => (),
+            }
+        }
+
+        self.set_character(column, row, character, Some(style));
+
+        if width == 2 {
+            self.set_character(column + 1, row, WIDE_FILLER, Some(style));
+        }
+    }
+
+    fn character_at(&self, column: u32, row: u32) -> char {
+        self[column / 2][row as usize].shape[(column % 2) as usize]
+    }
+
+    // Changes one of the two characters of a pixel, along with its style if
+    // one is given.
+    fn set_character(&mut self, column: u32, row: u32, character: char, style: Option<Style>) {
+        let half = (column % 2) as usize;
+        let pixel = &mut self[column / 2][row as usize];
+        let color = pixel.color;
+
+        let styles = pixel.styles.get_or_insert([Style::colored(color); 2]);
+
+        if let Some(style) = style {
+            styles[half] = style;
+        }
+
+        pixel.shape[half] = character;
+    }
+}
+
+// Everything from here on is for laying things out on the screen, so that the
+// HUD and the menus can be put together out of pieces instead of working out
+// where everything goes by hand.
+
+// A rectangle of the screen, measured in pixels.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Which way a rectangle gets split up.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    // Side by side.
+    Horizontal,
+    // One on top of the other.
+    Vertical,
+}
+
+// How big one part of a split should be.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Constraint {
+    // Exactly this many pixels.
+    Length(u32),
+    // This much of the whole thing.
+    Percentage(u32),
+    // Whatever is left over, shared between all of the parts that want it.
+    Fill,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // The inside of a border drawn around this rectangle.
+    pub fn inner(&self) -> Rect {
+        Rect {
+            x: self.x + 1,
+            y: self.y + 1,
+            width: self.width.saturating_sub(2),
+            height: self.height.saturating_sub(2),
+        }
+    }
+
+    // A smaller rectangle in the middle of this one.
+    pub fn centered(&self, width: u32, height: u32) -> Rect {
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+
+        Rect {
+            x: self.x + (self.width - width) / 2,
+            y: self.y + (self.height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    // Cuts the rectangle up into parts, in order. If the constraints ask for
+    // more than there is, the parts at the end get squashed.
+    pub fn split(&self, direction: Direction, constraints: &[Constraint]) -> Vec<Rect> {
+        let total = match direction {
+            Direction::Horizontal => self.width,
+            Direction::Vertical => self.height,
+        };
+
+        let fixed = |constraint: &Constraint| match *constraint {
+            Constraint::Length(length) => Some(length),
+            Constraint::Percentage(percentage) => Some(total * percentage / 100),
+            Constraint::Fill => None,
+        };
+
+        let fixed_total: u32 = constraints.iter().filter_map(fixed).sum();
+        let fill_count = constraints.iter().filter(|c| fixed(c).is_none()).count() as u32;
+        let left_over = total.saturating_sub(fixed_total);
+
+        let mut start = 0;
+        let mut fills_seen = 0;
+
+        constraints
+            .iter()
+            .map(|constraint| {
+                let size = fixed(constraint).unwrap_or_else(|| {
+                    fills_seen += 1;
+
+                    // The last one gets whatever doesn't divide evenly.
+                    if fills_seen == fill_count {
+                        left_over - left_over / fill_count * (fill_count - 1)
+                    } else {
+                        left_over / fill_count
+                    }
+                });
+
+                let size = size.min(total - start);
+                let part = match direction {
+                    Direction::Horizontal => Rect::new(self.x + start, self.y, size, self.height),
+                    Direction::Vertical => Rect::new(self.x, self.y + start, self.width, size),
+                };
+
+                start += size;
+                part
+            })
+            .collect()
+    }
+
+    // The same space as a place for text.
+    pub fn text_area(&self) -> TextArea {
+        TextArea {
+            column: self.x * 2,
+            row: self.y,
+            width: self.width * 2,
+            height: self.height,
+        }
+    }
+}
+
+impl Screen {
+    // All of the screen.
+    pub fn area(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+}
+
+// Anything that can be drawn into a rectangle of the screen.
+pub trait Widget {
+    fn draw(&self, screen: &mut Screen, area: Rect);
+}
+
+// Closures work as widgets too, for anything that only gets drawn in one place.
+impl<F: Fn(&mut Screen, Rect)> Widget for F {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        self(screen, area)
+    }
+}
+
+// Takes up space without drawing anything.
+pub struct Blank;
+
+impl Widget for Blank {
+    fn draw(&self, _screen: &mut Screen, _area: Rect) {}
+}
+
+// Lays out other widgets next to each other, or one on top of the other.
+pub struct Split<'a> {
+    pub direction: Direction,
+    pub children: Vec<(Constraint, Box<dyn Widget + 'a>)>,
+}
+
+impl<'a> Split<'a> {
+    pub fn vertical(children: Vec<(Constraint, Box<dyn Widget + 'a>)>) -> Split<'a> {
+        Split {
+            direction: Direction::Vertical,
+            children,
+        }
+    }
+
+    pub fn horizontal(children: Vec<(Constraint, Box<dyn Widget + 'a>)>) -> Split<'a> {
+        Split {
+            direction: Direction::Horizontal,
+            children,
+        }
+    }
+}
+
+impl Widget for Split<'_> {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        let constraints: Vec<Constraint> = self.children.iter().map(|(c, _)| *c).collect();
+        let areas = area.split(self.direction, &constraints);
+
+        for ((_, child), area) in self.children.iter().zip(areas) {
+            child.draw(screen, area);
+        }
+    }
+}
+
+// A box around another widget, with a title in the top border.
+pub struct Panel<'a> {
+    pub title: Option<String>,
+    pub content: Box<dyn Widget + 'a>,
+}
+
+impl Widget for Panel<'_> {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        if area.width < 2 || area.height < 2 {
             return;
         }
 
-        text.chars()
-            .collect::<Vec<char>>()
-            .chunks(2)
+        // The box goes from one corner to the other, so it's one smaller.
+        let _ = screen.draw_box(
+            area.x as u16,
+            area.y as u16,
+            (area.width - 1) as u16,
+            (area.height - 1) as u16,
+        );
+
+        if let Some(title) = self.title.as_ref() {
+            // The border starts halfway through the first pixel, and the title
+            // leaves a bit of it showing on either side.
+            let title_area = TextArea {
+                column: area.x * 2 + 2,
+                row: area.y,
+                width: (area.width * 2).saturating_sub(4),
+                height: 1,
+            };
+
+            screen.draw_text_in(title_area, &format!(" {} ", title), &TextOptions::default());
+        }
+
+        self.content.draw(screen, area.inner());
+    }
+}
+
+// A line or more of text.
+pub struct Label {
+    pub text: String,
+    pub options: TextOptions,
+}
+
+impl Label {
+    pub fn new(text: impl Into<String>) -> Label {
+        Label {
+            text: text.into(),
+            options: TextOptions::default(),
+        }
+    }
+
+    pub fn aligned(text: impl Into<String>, align: Align) -> Label {
+        Label {
+            text: text.into(),
+            options: TextOptions {
+                align,
+                ..TextOptions::default()
+            },
+        }
+    }
+}
+
+impl Widget for Label {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        screen.draw_text_in(area.text_area(), &self.text, &self.options);
+    }
+}
+
+// Lines of text, one of which can be selected. When there are more than fit,
+// the list scrolls along with the selection. The list has to be kept around
+// between frames for that, since it remembers how far it has scrolled.
+pub struct List {
+    pub items: Vec<String>,
+    pub selected: Option<usize>,
+    scroll: std::cell::Cell<usize>,
+}
+
+impl List {
+    pub fn new(items: Vec<String>) -> List {
+        List {
+            items,
+            selected: None,
+            scroll: std::cell::Cell::new(0),
+        }
+    }
+}
+
+impl Widget for List {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        let visible_rows = area.height as usize;
+        let mut scroll = self.scroll.get();
+
+        // Keep the selection on the screen.
+        if let Some(selected) = self.selected {
+            if selected < scroll {
+                scroll = selected;
+            } else if selected >= scroll + visible_rows {
+                scroll = selected + 1 - visible_rows;
+            }
+        }
+
+        self.scroll.set(scroll);
+
+        self.items
+            .iter()
             .enumerate()
-            .for_each(|(index, characters)| {
-                let pixel_x = x + index as u32;
-                let pixel_y: usize = y.try_into().unwrap();
+            .skip(scroll)
+            .take(visible_rows)
+            .for_each(|(i, item)| {
+                let marker = match self.selected {
+                    Some(selected) if selected == i => "> ",
+                    Some(_) => "  ",
+                    None => "",
+                };
+
+                let row = Rect::new(area.x, area.y + (i - scroll) as u32, area.width, 1);
+                Label::new(format!("{}{}", marker, item)).draw(screen, row);
+            });
+    }
+}
+
+// A bar that fills up from the left. It goes by columns of the terminal rather
+// than pixels, so that it moves a bit more smoothly.
+pub struct ProgressBar {
+    // From 0 to 1.
+    pub progress: f32,
+    pub color: Color,
+}
 
-                let second_char = if let Some(second_char) = characters.get(1) {
-                    *second_char
+impl Widget for ProgressBar {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        use crate::unicode::{FULL_BLOCK, LIGHT_SHADE};
+        use colors::basic::BRIGHT_BLACK;
+
+        let columns = area.width * 2;
+        let filled = (self.progress.clamp(0.0, 1.0) * columns as f32).round() as u32;
+
+        for row in area.y..area.y + area.height {
+            for column in 0..columns {
+                let (character, color) = if column < filled {
+                    (FULL_BLOCK, self.color)
                 } else {
-                    ' '
+                    (LIGHT_SHADE, Color::Basic(BRIGHT_BLACK))
                 };
 
-                if pixel_x < self.width {
-                    let pixel = Pixel {
-                        shape: [characters[0], second_char],
-                        color: Color::Default,
-                    };
+                screen.put_character(area.x * 2 + column, row, character, Style::colored(color));
+            }
+        }
+    }
+}
 
-                    self[pixel_x][pixel_y] = pixel;
-                }
-            });
+// A column of choices in the middle of the area, with arrows around the one
+// that's selected.
+pub struct Menu {
+    pub items: Vec<String>,
+    pub selected: usize,
+    // How many rows apart the choices are.
+    pub spacing: u32,
+}
+
+impl Widget for Menu {
+    fn draw(&self, screen: &mut Screen, area: Rect) {
+        self.items.iter().enumerate().for_each(|(i, item)| {
+            let label = if i == self.selected {
+                format!("> {} <", item)
+            } else {
+                item.to_string()
+            };
+
+            let y = area.y + i as u32 * self.spacing;
+
+            if y < area.y + area.height {
+                Label::aligned(label, Align::Center)
+                    .draw(screen, Rect::new(area.x, y, area.width, 1));
+            }
+        });
     }
 }
 
@@ -507,6 +1141,10 @@ impl IndexMut<u32> for Screen {
 #[cfg(target_family = "unix")]
 impl Drop for Screen {
     fn drop(&mut self) {
+        if !self.is_terminal {
+            return;
+        }
+
         unsafe {
             let mut terminal_settings = term::termios::default();
             term::tcgetattr(unistd::STDIN_FILENO as i32, &mut terminal_settings);
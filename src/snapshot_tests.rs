@@ -0,0 +1,194 @@
+// Snapshot tests of what the game looks like. Each case starts a game with a
+// fixed seed, plays a script of inputs and compares the frames it rendered
+// against the ones stored in snapshots/. When a change to the drawing is on
+// purpose, run `cargo update-snapshots` to write the new frames out, and look
+// over the diff before committing it.
+//
+// A script is a list of words separated by whitespace:
+//   an action name, like "hard_drop", is one frame with that input
+//   "wait:N" is N frames without any input
+//   "snap" draws the game and keeps the frame
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::puzzle::Puzzle;
+use crate::renderer::MemoryRenderer;
+use crate::screen::Screen;
+use crate::tetris::{Action, GameMode, Settings, Tetris};
+
+struct Case {
+    name: &'static str,
+    start: fn() -> (Tetris, Settings),
+    script: &'static str,
+}
+
+const CASES: [Case; 4] = [
+    Case {
+        name: "marathon_start",
+        start: marathon,
+        script: "snap move_left move_left rotate_right snap hard_drop wait:2 snap \
+                 hold wait:2 snap",
+    },
+    Case {
+        name: "sprint_drops",
+        start: sprint,
+        script: "hard_drop wait:2 move_right move_right move_right hard_drop wait:2 \
+                 move_left move_left move_left rotate_left hard_drop wait:2 snap \
+                 wait:60 snap",
+    },
+    Case {
+        name: "four_at_once",
+        start: four_at_once,
+        script: "snap move_right move_right move_right move_right move_right \
+                 snap hard_drop snap",
+    },
+    Case {
+        name: "zen_undo",
+        start: zen,
+        script: "hard_drop wait:2 hard_drop wait:2 snap undo wait:2 snap redo wait:2 snap",
+    },
+];
+
+fn marathon() -> (Tetris, Settings) {
+    let settings = Settings::default();
+    (Tetris::new(1, GameMode::Marathon, &settings), settings)
+}
+
+fn sprint() -> (Tetris, Settings) {
+    let settings = Settings::default();
+    (Tetris::new(2, GameMode::Sprint, &settings), settings)
+}
+
+fn zen() -> (Tetris, Settings) {
+    let settings = Settings::default();
+    (Tetris::new(3, GameMode::Zen, &settings), settings)
+}
+
+// The same board as the built in puzzle, written out here so that changes to
+// the puzzles that come with the game don't break the snapshots.
+fn four_at_once() -> (Tetris, Settings) {
+    let puzzle = Puzzle::parse(
+        "four-at-once",
+        "title = \"Four at once\"\n\
+         objective = \"lines 4\"\n\
+         pieces = \"I\"\n\
+         [board]\n\
+         ZZJJJLLOO.\n\
+         ZZSSJLLOO.\n\
+         IZZSSTTTL.\n\
+         IXXXXXTLL.\n",
+    )
+    .expect("The snapshot puzzle should parse");
+
+    let settings = puzzle.settings(&Settings::default());
+    (Tetris::from_puzzle(&puzzle, &settings), settings)
+}
+
+// Plays through a case and returns every frame it took, one after another.
+fn play(case: &Case) -> String {
+    let (mut game, settings) = (case.start)();
+    let controls = Config::default().controls;
+
+    let renderer = MemoryRenderer::default();
+    let frames = renderer.frames();
+
+    let (width, height) = settings.screen_size();
+    let mut screen = Screen::with_renderer(width, height, Box::new(renderer)).expect("Uh oh");
+
+    for word in case.script.split_whitespace() {
+        if word == "snap" {
+            game.render(&mut screen);
+            game.render_controls(&mut screen, &controls);
+            screen.present();
+        } else if let Some(count) = word.strip_prefix("wait:") {
+            let count: u32 = count.parse().expect("Bad wait in a snapshot script");
+            for _ in 0..count {
+                game.update(None);
+            }
+        } else {
+            let action = Action::from_name(word)
+                .unwrap_or_else(|| panic!("Unknown action {:?} in a snapshot script", word));
+            game.update(Some(action));
+        }
+    }
+
+    let frames = frames.borrow();
+    let mut out = String::new();
+    for (index, frame) in frames.iter().enumerate() {
+        out += &format!("=== frame {} ===\n", index + 1);
+        // Trailing spaces are left off, so that editors don't mess with them.
+        for line in frame.lines() {
+            out += line.trim_end();
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn snapshot_path(case: &Case) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("snapshots")
+        .join(format!("{}.txt", case.name))
+}
+
+#[test]
+fn snapshots_match() {
+    let mut failures = Vec::new();
+
+    for case in CASES.iter() {
+        let path = snapshot_path(case);
+        let actual = play(case);
+
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(error) => {
+                failures.push(format!(
+                    "{}: couldn't read {:?}: {}",
+                    case.name, path, error
+                ));
+                continue;
+            }
+        };
+
+        if actual == expected {
+            continue;
+        }
+
+        // Point at the first line that's different, since the whole thing is
+        // far too long to read.
+        let line = actual
+            .lines()
+            .zip(expected.lines())
+            .position(|(actual, expected)| actual != expected)
+            .unwrap_or_else(|| actual.lines().count().min(expected.lines().count()));
+
+        failures.push(format!(
+            "{}: differs at line {}\n  expected: {:?}\n  actual:   {:?}",
+            case.name,
+            line + 1,
+            expected.lines().nth(line).unwrap_or(""),
+            actual.lines().nth(line).unwrap_or(""),
+        ));
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nIf the change is on purpose, run `cargo update-snapshots`.",
+        failures.join("\n")
+    );
+}
+
+// Writes the snapshots out again. This is ignored so that it only runs when
+// it's asked for, through `cargo update-snapshots`.
+#[test]
+#[ignore]
+fn update_snapshots() {
+    for case in CASES.iter() {
+        let path = snapshot_path(case);
+        fs::create_dir_all(path.parent().unwrap()).expect("Couldn't make the snapshot directory");
+        fs::write(&path, play(case)).expect("Couldn't write a snapshot");
+    }
+}
@@ -6,6 +6,8 @@
 
 // A binding to system-specific functionalities.
 #[cfg(target_family = "unix")]
+pub mod signal;
+#[cfg(target_family = "unix")]
 pub mod termios;
 #[cfg(target_family = "unix")]
 pub mod unistd;
@@ -0,0 +1,16 @@
+/* The parts of signal.h that are needed to catch SIGTERM and friends. Written by
+ * hand in the same style as the generated bindings, since the full header drags
+ * in way too much. */
+
+pub const SIGHUP: u32 = 1;
+pub const SIGINT: u32 = 2;
+pub const SIGTERM: u32 = 15;
+
+pub type __sighandler_t = ::std::option::Option<unsafe extern "C" fn(arg1: ::std::os::raw::c_int)>;
+
+// SIG_DFL is defined as ((__sighandler_t) 0), which is None on the Rust side.
+pub const SIG_DFL: __sighandler_t = None;
+
+extern "C" {
+    pub fn signal(__sig: ::std::os::raw::c_int, __handler: __sighandler_t) -> __sighandler_t;
+}
@@ -1,519 +1,3855 @@
 // This file contains all the logic that is related to the actual Tetris game itself.
 // This includes the game mechanics, the game abstractions, etc.
 
-use std::num::TryFromIntError;
-
-use crate::screen::{self, Pixel, Screen, Shape};
+use crate::config::Controls;
+use crate::fumen::{Page, Placement, FIELD_HEIGHT, FIELD_WIDTH};
+use crate::puzzle::{Objective, Puzzle};
+use crate::screen::{Constraint, Pixel, Rect, Screen, Shape};
 
 pub const GAME_WIDTH: u32 = 10;
 pub const GAME_HEIGHT: u32 = 20;
 
+// The screen has to be at least this big so that the menus fit.
 pub const SCREEN_HEIGHT: u32 = 25;
 pub const SCREEN_WIDTH: u32 = 30;
 
-pub const PLAYER_STARTING_X: u16 = 5;
-pub const PLAYER_STARTING_Y: u16 = 3;
+// The space to the right of the board, where the score and such go.
+pub const SIDE_PANEL_WIDTH: u32 = 18;
+
+pub const PLAYER_STARTING_X: u16 = 5;
+
+// How many rows there are above the top of the board. Pieces spawn in here, and
+// can be moved around in it, but it's only drawn for as far as there's room.
+pub const BUFFER_HEIGHT: u32 = 20;
+
+// How many rows of the screen there are above the board, so that pieces that
+// are still in the buffer can be seen poking out of the top.
+const BOARD_TOP: u32 = 2;
+
+// The piece generator works with numbers below this, so this is as many
+// different seeds as there really are.
+pub const SEED_MODULUS: u64 = 101;
+
+// The level stops going up here, since that's where the gravity table ends.
+pub const MAX_LEVEL: u32 = 15;
+
+// How many seconds it takes a piece to fall one row on each level. This is the
+// table from the guideline, which is (0.8 - (level - 1) * 0.007) ^ (level - 1)
+// worked out ahead of time so that it comes out the same on every machine.
+const SECONDS_PER_ROW: [f32; MAX_LEVEL as usize] = [
+    1.0, 0.793, 0.6178, 0.4727, 0.3552, 0.2621, 0.1897, 0.1347, 0.0939, 0.0642, 0.043, 0.0282,
+    0.0182, 0.0114, 0.0071,
+];
+
+// The settings that change how the game plays. These come from the config file,
+// and get stored in replays so that they can be played back the same way.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub frame_rate: u8,
+    // How much faster (or slower) than the guideline the pieces fall.
+    pub fall_speed: f32,
+    pub board_width: u32,
+    pub board_height: u32,
+    pub spawn_x: u16,
+    // The level that the game starts on, in modes that let the player pick it.
+    pub start_level: u32,
+    // The level whose gravity is used in practice modes, or 0 for no gravity.
+    pub practice_gravity: u32,
+    // How many rows of garbage the board starts with in modes that have it.
+    pub garbage_rows: u32,
+    // The chance (in percent) of the hole moving to another column from one
+    // row of garbage to the next.
+    pub garbage_messiness: u32,
+    // Whether every mino takes up 2x2 cells of the board.
+    pub big_mode: bool,
+    // The name of the set of pieces that the game is played with.
+    pub piece_set: String,
+    // How long full lines stay on the board before they get cleared, in
+    // milliseconds. Modes with arcade timing have their own delays.
+    pub line_clear_delay: u32,
+    // Whether lines flash before they get cleared, pieces flash when they lock
+    // and so on. Without animations, lines get cleared right away.
+    pub animations: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            frame_rate: crate::FRAME_RATE,
+            fall_speed: 1.0,
+            board_width: GAME_WIDTH,
+            board_height: GAME_HEIGHT,
+            spawn_x: PLAYER_STARTING_X,
+            start_level: 1,
+            practice_gravity: 0,
+            garbage_rows: 10,
+            garbage_messiness: 20,
+            big_mode: false,
+            piece_set: String::from("standard"),
+            line_clear_delay: 250,
+            animations: true,
+        }
+    }
+}
+
+impl Settings {
+    // How big the screen has to be to fit a board of this size.
+    pub fn screen_size(&self) -> (u32, u32) {
+        (
+            (self.board_width + 2 + SIDE_PANEL_WIDTH).max(SCREEN_WIDTH),
+            (self.board_height + BOARD_TOP + 3).max(SCREEN_HEIGHT),
+        )
+    }
+
+    // How many cells of the board every mino takes up in each direction.
+    fn scale(&self) -> u32 {
+        if self.big_mode {
+            2
+        } else {
+            1
+        }
+    }
+
+    // The settings as far as the game itself is concerned. Big mode is the
+    // same as playing on a board half the size and drawing everything twice
+    // as big, so the game only ever sees the smaller board.
+    fn playfield(&self) -> Settings {
+        let scale = self.scale();
+
+        Settings {
+            board_width: self.board_width / scale,
+            board_height: self.board_height / scale,
+            spawn_x: self.spawn_x.div_ceil(scale as u16),
+            ..self.clone()
+        }
+    }
+
+    // How many rows the board has, counting the buffer above it.
+    fn total_height(&self) -> u32 {
+        self.board_height + BUFFER_HEIGHT
+    }
+
+    // Writes the settings out as "key value" lines. This is what replays and
+    // saved games use.
+    pub fn write_fields(&self, out: &mut String) {
+        *out += &format!("frame_rate {}\n", self.frame_rate);
+        *out += &format!("fall_speed {}\n", self.fall_speed);
+        *out += &format!("board_width {}\n", self.board_width);
+        *out += &format!("board_height {}\n", self.board_height);
+        *out += &format!("spawn_x {}\n", self.spawn_x);
+        *out += &format!("start_level {}\n", self.start_level);
+        *out += &format!("practice_gravity {}\n", self.practice_gravity);
+        *out += &format!("garbage_rows {}\n", self.garbage_rows);
+        *out += &format!("garbage_messiness {}\n", self.garbage_messiness);
+        *out += &format!("big_mode {}\n", self.big_mode);
+        *out += &format!("piece_set {}\n", self.piece_set);
+        *out += &format!("line_clear_delay {}\n", self.line_clear_delay);
+        *out += &format!("animations {}\n", self.animations);
+    }
+
+    // Reads back one of the lines that write_fields() wrote. Returns Ok(false)
+    // if the key isn't a setting at all, and Err(()) if the value is garbage.
+    pub fn read_field(&mut self, key: &str, value: &str) -> Result<bool, ()> {
+        fn parse<T: std::str::FromStr>(value: &str, setting: &mut T) -> Result<bool, ()> {
+            *setting = value.parse().map_err(|_| ())?;
+            Ok(true)
+        }
+
+        match key {
+            "frame_rate" => parse(value, &mut self.frame_rate),
+            "fall_speed" => parse(value, &mut self.fall_speed),
+            "board_width" => parse(value, &mut self.board_width),
+            "board_height" => parse(value, &mut self.board_height),
+            "spawn_x" => parse(value, &mut self.spawn_x),
+            "start_level" => parse(value, &mut self.start_level),
+            "practice_gravity" => parse(value, &mut self.practice_gravity),
+            "garbage_rows" => parse(value, &mut self.garbage_rows),
+            "garbage_messiness" => parse(value, &mut self.garbage_messiness),
+            "big_mode" => parse(value, &mut self.big_mode),
+            "piece_set" => parse(value, &mut self.piece_set),
+            "line_clear_delay" => parse(value, &mut self.line_clear_delay),
+            "animations" => parse(value, &mut self.animations),
+            _ => Ok(false),
+        }
+    }
+}
+
+// The different ways that the game can be played. Each one of them gets its own
+// high score table.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameMode {
+    Marathon,
+    Sprint,
+    Ultra,
+    Zen,
+    Dig,
+    Master,
+    Fading,
+    Invisible,
+    // Puzzles aren't picked from the list of modes, since they need a puzzle
+    // to go with them (see puzzle.rs).
+    Puzzle,
+}
+
+// How the high score table of a mode is sorted.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ranking {
+    // The highest score wins.
+    Score,
+    // The fastest time wins, and only games that were actually finished count.
+    Time,
+    // There's no high score table at all.
+    Unranked,
+}
+
+// How much of the stack the player gets to see.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Visibility {
+    Visible,
+    // Blocks fade away over a few seconds after they lock.
+    Fading,
+    // Blocks disappear as soon as they lock.
+    Invisible,
+}
+
+// Everything that makes one game mode different from another.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rules {
+    // The game is finished once this many lines have been cleared.
+    pub line_goal: Option<u32>,
+    // How many lines there are between split times, if the mode keeps them.
+    pub split_interval: Option<u32>,
+    // The game is over once this much time has gone by.
+    pub time_limit_ms: Option<u64>,
+    // Whether the player can keep playing after reaching the line goal.
+    pub endless_after_goal: bool,
+    // Whether the player gets to pick the level that the game starts on.
+    pub selectable_start_level: bool,
+    // Whether the gravity comes from the practice gravity setting instead of
+    // the level.
+    pub practice_gravity: bool,
+    // Whether placing pieces can be undone.
+    pub undo: bool,
+    // Whether the board can be exported as fumen.
+    pub export: bool,
+    // Whether the game is over when the board fills up. If it isn't, the top
+    // of the board gets cleared out instead.
+    pub top_out: bool,
+    // Whether the board starts out with rows of garbage. The game is finished
+    // once all of them have been cleared.
+    pub garbage: bool,
+    // Whether the game runs on arcade timing: pieces drop at 20G, there are
+    // delays between pieces, the level goes up with every piece up to 999, and
+    // the player gets a grade at the end.
+    pub arcade: bool,
+    // The stack always shows up for a moment when lines get cleared, and for
+    // good once the game is over.
+    pub visibility: Visibility,
+    pub ranking: Ranking,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 8] = [
+        GameMode::Marathon,
+        GameMode::Sprint,
+        GameMode::Ultra,
+        GameMode::Dig,
+        GameMode::Master,
+        GameMode::Fading,
+        GameMode::Invisible,
+        GameMode::Zen,
+    ];
+
+    // The name that is shown to the player.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+            GameMode::Zen => "Zen",
+            GameMode::Dig => "Dig",
+            GameMode::Master => "Master",
+            GameMode::Fading => "Fading",
+            GameMode::Invisible => "Invisible",
+            GameMode::Puzzle => "Puzzle",
+        }
+    }
+
+    // A short explanation that is shown on the mode select screen.
+    pub fn description(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Clear 150 lines, starting on any level",
+            GameMode::Sprint => "Clear 40 lines as fast as possible",
+            GameMode::Ultra => "Score as much as possible in 2 minutes",
+            GameMode::Zen => "Practice at your own pace, with undo",
+            GameMode::Dig => "Dig through the garbage as fast as possible",
+            GameMode::Master => "Reach level 999 at 20G and earn a grade",
+            GameMode::Fading => "Clear 40 lines as the stack fades away",
+            GameMode::Invisible => "Clear 40 lines with an invisible stack",
+            GameMode::Puzzle => "Solve a puzzle with the pieces you're given",
+        }
+    }
+
+    // The name that is used for this mode in files.
+    pub fn key(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "marathon",
+            GameMode::Sprint => "sprint",
+            GameMode::Ultra => "ultra",
+            GameMode::Zen => "zen",
+            GameMode::Dig => "dig",
+            GameMode::Master => "master",
+            GameMode::Fading => "fading",
+            GameMode::Invisible => "invisible",
+            GameMode::Puzzle => "puzzle",
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        match self {
+            GameMode::Marathon => Rules {
+                line_goal: Some(150),
+                split_interval: None,
+                time_limit_ms: None,
+                endless_after_goal: true,
+                selectable_start_level: true,
+                practice_gravity: false,
+                undo: false,
+                export: false,
+                top_out: true,
+                garbage: false,
+                arcade: false,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Score,
+            },
+            GameMode::Sprint => Rules {
+                line_goal: Some(40),
+                split_interval: Some(10),
+                time_limit_ms: None,
+                endless_after_goal: false,
+                selectable_start_level: false,
+                practice_gravity: false,
+                undo: false,
+                export: false,
+                top_out: true,
+                garbage: false,
+                arcade: false,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Time,
+            },
+            GameMode::Ultra => Rules {
+                line_goal: None,
+                split_interval: None,
+                time_limit_ms: Some(120_000),
+                endless_after_goal: false,
+                selectable_start_level: false,
+                practice_gravity: false,
+                undo: false,
+                export: false,
+                top_out: true,
+                garbage: false,
+                arcade: false,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Score,
+            },
+            GameMode::Zen => Rules {
+                line_goal: None,
+                split_interval: None,
+                time_limit_ms: None,
+                endless_after_goal: false,
+                selectable_start_level: false,
+                practice_gravity: true,
+                undo: true,
+                export: true,
+                top_out: false,
+                garbage: false,
+                arcade: false,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Unranked,
+            },
+            GameMode::Dig => Rules {
+                line_goal: None,
+                split_interval: None,
+                time_limit_ms: None,
+                endless_after_goal: false,
+                selectable_start_level: false,
+                practice_gravity: false,
+                undo: false,
+                export: false,
+                top_out: true,
+                garbage: true,
+                arcade: false,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Time,
+            },
+            GameMode::Master => Rules {
+                line_goal: None,
+                // With arcade timing, the splits are every 100 levels instead.
+                split_interval: Some(100),
+                time_limit_ms: None,
+                endless_after_goal: false,
+                selectable_start_level: false,
+                practice_gravity: false,
+                undo: false,
+                export: false,
+                top_out: true,
+                garbage: false,
+                arcade: true,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Score,
+            },
+            GameMode::Puzzle => Rules {
+                line_goal: None,
+                split_interval: None,
+                time_limit_ms: None,
+                endless_after_goal: false,
+                selectable_start_level: false,
+                practice_gravity: false,
+                undo: false,
+                export: false,
+                top_out: true,
+                garbage: false,
+                arcade: false,
+                visibility: Visibility::Visible,
+                ranking: Ranking::Unranked,
+            },
+            // These are Sprint, but harder to see.
+            GameMode::Fading | GameMode::Invisible => Rules {
+                visibility: if *self == GameMode::Fading {
+                    Visibility::Fading
+                } else {
+                    Visibility::Invisible
+                },
+                ..GameMode::Sprint.rules()
+            },
+        }
+    }
+
+    // Puzzle mode isn't in ALL, since it doesn't get picked from the mode
+    // select screen, so it has to be looked for on its own.
+    pub fn from_key(key: &str) -> Option<GameMode> {
+        GameMode::ALL
+            .iter()
+            .chain([GameMode::Puzzle].iter())
+            .find(|mode| mode.key() == key)
+            .copied()
+    }
+}
+
+// A Pseudorandom number generator, used to decide what piece to use next.
+struct RandomGenerator {
+    modulus: u64,
+    multiplier: u64,
+    increment: u64,
+    seed: u64,
+}
+
+impl RandomGenerator {
+    // The seed is passed in from the outside so that a game can be played back
+    // exactly the same way later on (see replay.rs).
+    fn new(modulus: u64, multiplier: u64, increment: u64, seed: u64) -> RandomGenerator {
+        let mut generator = RandomGenerator {
+            modulus,
+            multiplier,
+            increment,
+            seed: seed % modulus,
+        };
+
+        // A seed that the generator turns back into itself would give the same
+        // number forever (67, with the numbers the game uses). Nothing else ever
+        // leads to it, so moving off of it at the start is enough.
+        if generator.is_stuck(generator.seed) {
+            generator.seed = (generator.seed + 1) % modulus;
+        }
+
+        generator
+    }
+
+    // Whether the generator would give this seed straight back.
+    fn is_stuck(&self, seed: u64) -> bool {
+        (self.multiplier * seed + self.increment) % self.modulus == seed
+    }
+
+    // Whether the generator could have gotten to this seed by itself, which is
+    // what the seeds in saved games have to be.
+    fn is_valid_seed(&self, seed: u64) -> bool {
+        seed < self.modulus && !self.is_stuck(seed)
+    }
+
+    fn generate(&mut self) -> u64 {
+        let result = self
+            .multiplier
+            .wrapping_mul(self.seed)
+            .wrapping_add(self.increment)
+            % self.modulus;
+        self.seed = result;
+        result
+    }
+}
+
+// Generates a seed from the current time. Used whenever we're not replaying
+// a previously recorded game.
+pub fn random_seed() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now();
+    now.duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+// Everything that the player can do to the game. The keyboard gets translated
+// into these so that the game itself doesn't have to care about where the
+// inputs are coming from (the keyboard or a replay file).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    RotateRight,
+    RotateLeft,
+    RotateLeft180,
+    RotateRight180,
+    Hold,
+    HardDrop,
+    Undo,
+    Redo,
+    Export,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 12] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::RotateRight,
+        Action::RotateLeft,
+        Action::RotateLeft180,
+        Action::RotateRight180,
+        Action::Hold,
+        Action::HardDrop,
+        Action::Undo,
+        Action::Redo,
+        Action::Export,
+        Action::Quit,
+    ];
+
+    // What the action is called in the list of controls.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::RotateRight => "Rotate Right",
+            Action::RotateLeft => "Rotate Left",
+            Action::RotateLeft180 => "Rotate left 180 degrees",
+            Action::RotateRight180 => "Rotate right 180 degrees",
+            Action::Hold => "Hold",
+            Action::HardDrop => "Drop",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::Export => "Export board",
+            Action::Quit => "Quit",
+        }
+    }
+
+    // The name that is used for this action in files (such as replays).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::RotateRight => "rotate_right",
+            Action::RotateLeft => "rotate_left",
+            Action::RotateLeft180 => "rotate_left_180",
+            Action::RotateRight180 => "rotate_right_180",
+            Action::Hold => "hold",
+            Action::HardDrop => "hard_drop",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Export => "export",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .find(|action| action.name() == name)
+            .copied()
+    }
+}
+
+// Anything that isn't drawn with full blocks has its two characters tacked on
+// to the end, like "93@[]".
+fn glyphs_to_string(glyphs: [char; 2]) -> String {
+    use crate::unicode::FULL_BLOCK;
+
+    if glyphs == [FULL_BLOCK, FULL_BLOCK] {
+        String::new()
+    } else {
+        format!("@{}{}", glyphs[0], glyphs[1])
+    }
+}
+
+fn glyphs_from_string(text: Option<&str>) -> Result<[char; 2], ()> {
+    use crate::unicode::FULL_BLOCK;
+
+    let text = match text {
+        Some(text) => text,
+        None => return Ok([FULL_BLOCK, FULL_BLOCK]),
+    };
+
+    let mut glyphs = text.chars();
+
+    match (glyphs.next(), glyphs.next(), glyphs.next()) {
+        (Some(left), Some(right), None) => Ok([left, right]),
+        _ => Err(()),
+    }
+}
+
+// How shapes are written in saved games: the color followed by the position of
+// every pixel, like "93 0,0 1,0 1,1 0,1". Shapes that don't exist are "none".
+fn shape_to_string(shape: &Option<Shape>) -> String {
+    let shape = match shape {
+        Some(shape) => shape,
+        None => return String::from("none"),
+    };
+
+    let color = match shape.fill_pixel.color {
+        crate::screen::Color::Basic(color) => color.to_string(),
+        crate::screen::Color::Default => String::from("default"),
+    } + &glyphs_to_string(shape.fill_pixel.shape);
+
+    let pixels: Vec<String> = shape
+        .pixels
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect();
+
+    format!("{} {}", color, pixels.join(" "))
+}
+
+fn shape_from_string(text: &str) -> Result<Option<Shape>, ()> {
+    use crate::screen::Color;
+
+    if text == "none" {
+        return Ok(None);
+    }
+
+    let mut parts = text.split(' ');
+    let mut fill = parts.next().ok_or(())?.splitn(2, '@');
+
+    let color = match fill.next().ok_or(())? {
+        "default" => Color::Default,
+        color => Color::Basic(color.parse().map_err(|_| ())?),
+    };
+
+    let pixels = parts
+        .map(|pixel| {
+            let (x, y) = pixel.split_once(',').ok_or(())?;
+            Ok((x.parse().map_err(|_| ())?, y.parse().map_err(|_| ())?))
+        })
+        .collect::<Result<Vec<_>, ()>>()?;
+
+    if pixels.is_empty() || pixels.len() > crate::pieces::MAX_PIECE_SIZE {
+        return Err(());
+    }
+
+    Ok(Some(Shape {
+        pixels,
+        fill_pixel: Pixel {
+            shape: glyphs_from_string(fill.next())?,
+            color,
+            styles: None,
+        },
+    }))
+}
+
+// The two kinds of T-spin. A mini T-spin is one where only one of the corners
+// in front of the T is filled.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum TSpin {
+    Mini,
+    Full,
+}
+
+// Formats a time as m:ss.mmm
+pub fn format_time_ms(time_ms: u64) -> String {
+    format!(
+        "{}:{:02}.{:03}",
+        time_ms / 60000,
+        time_ms / 1000 % 60,
+        time_ms % 1000
+    )
+}
+
+// Formats how far ahead (negative) or behind (positive) a time is, like -1.234
+pub fn format_time_difference(difference_ms: i64) -> String {
+    let sign = if difference_ms <= 0 { '-' } else { '+' };
+    let difference_ms = difference_ms.unsigned_abs();
+
+    format!(
+        "{}{}.{:03}",
+        sign,
+        difference_ms / 1000,
+        difference_ms % 1000
+    )
+}
+
+// Lists of times are written as comma separated milliseconds, or "-" if empty.
+pub fn times_to_string(times: &[u64]) -> String {
+    if times.is_empty() {
+        return String::from("-");
+    }
+
+    let times: Vec<String> = times.iter().map(|time| time.to_string()).collect();
+    times.join(",")
+}
+
+pub fn times_from_string(text: &str) -> Result<Vec<u64>, ()> {
+    if text == "-" {
+        return Ok(Vec::new());
+    }
+
+    text.split(',')
+        .map(|time| time.parse().map_err(|_| ()))
+        .collect()
+}
+
+// The level that finishes a game with arcade timing.
+const ARCADE_LAST_LEVEL: u32 = 999;
+
+// The delays of arcade timing, in frames at 60 frames per second.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Delays {
+    // Between a piece locking and the next one coming out (ARE).
+    spawn: u32,
+    // The same, but after lines have been cleared.
+    line_spawn: u32,
+    // How long a piece can sit on the ground before it locks.
+    lock: u32,
+    // How long full lines stay on the board before they are cleared.
+    clear: u32,
+}
+
+// The delays for every section of 100 levels. These roughly follow the arcade
+// games, except that the lock delay starts getting shorter a bit earlier.
+const ARCADE_DELAYS: [Delays; 10] = [
+    Delays {
+        spawn: 25,
+        line_spawn: 25,
+        lock: 30,
+        clear: 40,
+    },
+    Delays {
+        spawn: 25,
+        line_spawn: 25,
+        lock: 30,
+        clear: 40,
+    },
+    Delays {
+        spawn: 25,
+        line_spawn: 25,
+        lock: 30,
+        clear: 40,
+    },
+    Delays {
+        spawn: 25,
+        line_spawn: 25,
+        lock: 30,
+        clear: 40,
+    },
+    Delays {
+        spawn: 25,
+        line_spawn: 25,
+        lock: 30,
+        clear: 40,
+    },
+    Delays {
+        spawn: 25,
+        line_spawn: 25,
+        lock: 28,
+        clear: 25,
+    },
+    Delays {
+        spawn: 25,
+        line_spawn: 16,
+        lock: 26,
+        clear: 16,
+    },
+    Delays {
+        spawn: 16,
+        line_spawn: 12,
+        lock: 24,
+        clear: 12,
+    },
+    Delays {
+        spawn: 12,
+        line_spawn: 6,
+        lock: 20,
+        clear: 6,
+    },
+    Delays {
+        spawn: 12,
+        line_spawn: 6,
+        lock: 17,
+        clear: 6,
+    },
+];
+
+// The grades, and the score that it takes to get each of them.
+const GRADES: [(u32, &str); 18] = [
+    (0, "9"),
+    (400, "8"),
+    (800, "7"),
+    (1400, "6"),
+    (2000, "5"),
+    (3500, "4"),
+    (5500, "3"),
+    (8000, "2"),
+    (12000, "1"),
+    (16000, "S1"),
+    (22000, "S2"),
+    (30000, "S3"),
+    (40000, "S4"),
+    (52000, "S5"),
+    (66000, "S6"),
+    (82000, "S7"),
+    (100000, "S8"),
+    (120000, "S9"),
+];
+
+// What it takes to become a Grand Master: a high enough score by a few levels,
+// and getting there fast enough. The level, the score and the time.
+const GRAND_MASTER_CHECKS: [(u32, u32, u64); 2] = [(300, 12000, 255_000), (500, 40000, 450_000)];
+const GRAND_MASTER_SCORE: u32 = 126000;
+const GRAND_MASTER_TIME_MS: u64 = 810_000;
+
+// What the game is doing at the moment. Most modes are always Active, since the
+// next piece comes out as soon as the last one locks, but arcade timing waits
+// in between. The numbers are how many frames are left.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Phase {
+    // Waiting for the next piece to come out.
+    Spawning(u32),
+    // The piece is falling, and can be moved around.
+    Active,
+    // The piece is on the ground, and locks once the time runs out.
+    Locking(u32),
+    // Full lines are about to be cleared.
+    Clearing(u32),
+}
+
+// How phases are written in saved games, like "locking 12".
+fn phase_to_string(phase: Phase) -> String {
+    match phase {
+        Phase::Spawning(frames) => format!("spawning {}", frames),
+        Phase::Active => String::from("active"),
+        Phase::Locking(frames) => format!("locking {}", frames),
+        Phase::Clearing(frames) => format!("clearing {}", frames),
+    }
+}
+
+fn phase_from_string(text: &str) -> Result<Phase, ()> {
+    if text == "active" {
+        return Ok(Phase::Active);
+    }
+
+    let (name, frames) = text.split_once(' ').ok_or(())?;
+    let frames: u32 = frames.parse().map_err(|_| ())?;
+
+    // Every wait is at least a frame long.
+    if frames == 0 {
+        return Err(());
+    }
+
+    match name {
+        "spawning" => Ok(Phase::Spawning(frames)),
+        "locking" => Ok(Phase::Locking(frames)),
+        "clearing" => Ok(Phase::Clearing(frames)),
+        _ => Err(()),
+    }
+}
+
+// How long it takes blocks to fade away completely in modes where they do, and
+// how long the stack shows up for when lines are cleared.
+const FADE_MS: u64 = 3000;
+const REVEAL_MS: u64 = 500;
+
+// How long pieces flash for when they lock, and how long the trail behind a
+// hard dropped piece stays around.
+const LOCK_FLASH_MS: u64 = 100;
+const TRAIL_MS: u64 = 150;
+
+// How wide the hold box is, in pixels, counting the border.
+const HOLD_BOX_WIDTH: u32 = 8;
+
+// How long the text that pops up after a clear stays on the board.
+const POPUP_MS: u64 = 1500;
+
+// Garbage is grey, which no piece is, so that it can be told apart from
+// everything else on the board.
+pub const GARBAGE_COLOR: u8 = crate::screen::colors::basic::BRIGHT_BLACK;
+
+// How many placements can be undone in modes that allow it.
+const MAX_UNDO: usize = 100;
+
+// Everything that undoing a placement puts back, as it was when a piece was
+// spawned. The random generator is in here too, since it decides which pieces
+// come next.
+#[derive(Clone)]
+struct Snapshot {
+    blocks: Vec<Vec<Option<Block>>>,
+    rng: u64,
+    current_shape: Option<Shape>,
+    held_shape: Option<Shape>,
+    previous_shape: Option<Shape>,
+    can_hold_shape: bool,
+    score: u32,
+    lines_cleared: u32,
+    back_to_back: bool,
+    combo: u32,
+    pieces_spawned: u32,
+    pieces_locked: u32,
+    // The pieces that are left in a fixed queue. This isn't written out, since
+    // the games that have one (puzzles and fumen boards) never get saved.
+    queue: Option<Vec<Shape>>,
+}
+
+impl Snapshot {
+    // Snapshots are written out on a single line, with the fields separated by
+    // semicolons and the rows of the board separated by slashes.
+    fn write(&self) -> String {
+        let rows: Vec<String> = self.blocks.iter().map(|row| row_to_string(row)).collect();
+
+        [
+            self.rng.to_string(),
+            shape_to_string(&self.current_shape),
+            shape_to_string(&self.held_shape),
+            shape_to_string(&self.previous_shape),
+            self.can_hold_shape.to_string(),
+            self.score.to_string(),
+            self.lines_cleared.to_string(),
+            self.back_to_back.to_string(),
+            self.combo.to_string(),
+            self.pieces_spawned.to_string(),
+            self.pieces_locked.to_string(),
+            rows.join("/"),
+        ]
+        .join(";")
+    }
+
+    fn read(text: &str, settings: &Settings) -> Result<Snapshot, ()> {
+        fn parse<T: std::str::FromStr>(field: Option<&str>) -> Result<T, ()> {
+            field.ok_or(())?.parse().map_err(|_| ())
+        }
+
+        let mut fields = text.split(';');
+
+        let snapshot = Snapshot {
+            rng: parse(fields.next())?,
+            current_shape: shape_from_string(fields.next().ok_or(())?)?,
+            held_shape: shape_from_string(fields.next().ok_or(())?)?,
+            previous_shape: shape_from_string(fields.next().ok_or(())?)?,
+            can_hold_shape: parse(fields.next())?,
+            score: parse(fields.next())?,
+            lines_cleared: parse(fields.next())?,
+            back_to_back: parse(fields.next())?,
+            combo: parse(fields.next())?,
+            pieces_spawned: parse(fields.next())?,
+            pieces_locked: parse(fields.next())?,
+            queue: None,
+            blocks: fields
+                .next()
+                .ok_or(())?
+                .split('/')
+                .map(|row| row_from_string(row, settings.board_width))
+                .collect::<Result<_, ()>>()?,
+        };
+
+        if fields.next().is_some() || snapshot.blocks.len() != settings.total_height() as usize {
+            return Err(());
+        }
+
+        Ok(snapshot)
+    }
+}
+
+// Something that's only there to look at. Effects get drawn for a little while
+// and then go away, and have nothing to do with the game itself, so they don't
+// get saved either.
+#[derive(Clone, PartialEq, Debug)]
+struct Effect {
+    kind: EffectKind,
+    // The cells of the board that the effect is drawn on, as (column, row).
+    cells: Vec<(usize, usize)>,
+    color: u8,
+    started_at: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum EffectKind {
+    // A piece that just locked flashes white.
+    LockFlash,
+    // The path that a hard dropped piece took, which shrinks down towards it.
+    Trail,
+}
+
+impl EffectKind {
+    fn duration_ms(self) -> u64 {
+        match self {
+            EffectKind::LockFlash => LOCK_FLASH_MS,
+            EffectKind::Trail => TRAIL_MS,
+        }
+    }
+}
+
+// The parts of the side panel, from top to bottom.
+pub struct PanelAreas {
+    pub status: Rect,
+    pub progress: Rect,
+    pub controls: Rect,
+    pub hold: Rect,
+    // Whatever's left at the bottom, which has the splits in it.
+    pub below: Rect,
+}
+
+// Text that pops up on the board after a clear, saying what the clear was and
+// how many points it was worth. It slowly fades out, and gets replaced by the
+// next one.
+#[derive(Clone, PartialEq, Debug)]
+struct Popup {
+    // Every line of the popup has its own color.
+    lines: Vec<(String, u8)>,
+    // The row of the board that the popup is shown at, which is wherever the
+    // piece locked.
+    row: usize,
+    started_at: u32,
+}
+
+// A block that has been locked into the board.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Block {
+    color: u8,
+    // What the block is drawn with, which is a full block unless the piece set
+    // says otherwise.
+    glyphs: [char; 2],
+    // The frame that the block got locked on, which is what makes it fade away
+    // in some modes.
+    locked_at: u32,
+}
+
+// Rows of the board are written with a dot for every empty block, and the
+// color and lock frame of the others, like "93@120".
+fn row_to_string(row: &[Option<Block>]) -> String {
+    let row: Vec<String> = row
+        .iter()
+        .map(|block| match block {
+            Some(block) => format!(
+                "{}@{}{}",
+                block.color,
+                block.locked_at,
+                glyphs_to_string(block.glyphs)
+            ),
+            None => String::from("."),
+        })
+        .collect();
+
+    row.join(" ")
+}
+
+fn row_from_string(text: &str, board_width: u32) -> Result<Vec<Option<Block>>, ()> {
+    let row: Vec<Option<Block>> = text
+        .split(' ')
+        .map(|block| {
+            if block == "." {
+                return Ok(None);
+            }
+
+            let mut parts = block.splitn(3, '@');
+
+            Ok(Some(Block {
+                color: parts.next().ok_or(())?.parse().map_err(|_| ())?,
+                locked_at: parts.next().ok_or(())?.parse().map_err(|_| ())?,
+                glyphs: glyphs_from_string(parts.next())?,
+            }))
+        })
+        .collect::<Result<_, ()>>()?;
+
+    if row.len() != board_width as usize {
+        return Err(());
+    }
+
+    Ok(row)
+}
+
+pub struct Tetris {
+    rules: Rules,
+    settings: Settings,
+
+    is_running: bool,
+    // Whether the game ended because the board filled up, as opposed to the
+    // player quitting.
+    is_game_over: bool,
+    // Whether the game ended because the player reached the goal of the mode.
+    is_cleared: bool,
+    // Set once the player decides to keep going after the goal.
+    is_endless: bool,
+
+    random_generator: RandomGenerator,
+    // The shapes that can come out, from the piece set in the settings.
+    pieces: Vec<Shape>,
+
+    // How far the current piece has fallen since it last moved down a whole row.
+    // The gravity gets added to this every frame, and every time it gets past
+    // one the piece falls one row.
+    fall_progress: f32,
+
+    player_x: u16,
+    player_y: u16,
+    // How many times the current shape has been turned to the right since it
+    // was spawned, from 0 to 3.
+    rotation: u8,
+
+    score: u32,
+    lines_cleared: u32,
+    // Whether the last line clear was a tetris or a T-spin, which makes the
+    // next one of those worth more.
+    back_to_back: bool,
+    // How many pieces in a row have cleared lines. With arcade timing, this is
+    // what the score of a line clear gets multiplied by instead.
+    combo: u32,
+    // T-spins only count if the piece got into place by rotating.
+    last_move_was_rotation: bool,
+
+    blocks: Vec<Vec<Option<Block>>>,
+    // The stack is shown in full until this frame, in modes where it's hard
+    // to see.
+    reveal_until: u32,
+
+    phase: Phase,
+    // The level with arcade timing, which goes up with every piece and line.
+    arcade_level: u32,
+    // Whether the player has kept up with everything that it takes to become
+    // a Grand Master so far.
+    can_become_grand_master: bool,
+
+    previous_shape: Option<Shape>,
+    current_shape: Option<Shape>,
+    held_shape: Option<Shape>,
+
+    can_hold_shape: bool,
+
+    // The number of times update() has been called. Replays use this to know
+    // when each input is supposed to happen.
+    frame: u32,
+    pieces_spawned: u32,
+    pieces_locked: u32,
+
+    // The time (in milliseconds) at which every split was reached, and the
+    // times of the personal best that they get compared against.
+    splits: Vec<u64>,
+    personal_best_splits: Vec<u64>,
+
+    // How many moves and rotations the player has used on the current piece,
+    // and how many more than necessary they have used on all of the pieces so
+    // far.
+    piece_inputs: u32,
+    finesse_faults: u32,
+
+    // The state at the moment the current piece was spawned, which is what
+    // gets put back if its placement is undone. Only kept in modes with undo.
+    spawn_snapshot: Option<Snapshot>,
+    undo_history: Vec<Snapshot>,
+    redo_history: Vec<Snapshot>,
+
+    // The pieces that come out before the random ones, in games that start
+    // from a puzzle or a fumen.
+    queue: Option<Vec<Shape>>,
+    // What has to be done in a puzzle. Puzzles only have the pieces in the
+    // queue, so they're over once those run out.
+    objective: Option<Objective>,
+    // The boards that have been exported as fumen so far.
+    exports: Vec<String>,
+
+    effects: Vec<Effect>,
+    popup: Option<Popup>,
+}
+
+impl Tetris {
+    pub fn new(seed: u64, mode: GameMode, settings: &Settings) -> Tetris {
+        let settings = &settings.playfield();
+
+        let mut game = Tetris {
+            rules: mode.rules(),
+            settings: settings.clone(),
+
+            is_running: true,
+            is_game_over: false,
+            is_cleared: false,
+            is_endless: false,
+
+            random_generator: RandomGenerator::new(SEED_MODULUS, 4, 1, seed),
+            // The settings have already been checked by now, so the piece set
+            // should always load. If the file went away since then, the game
+            // can still be played with the normal pieces.
+            pieces: crate::pieces::load(&settings.piece_set)
+                .unwrap_or_else(|_| crate::pieces::standard()),
+
+            fall_progress: 0.0,
+
+            player_x: settings.spawn_x,
+            player_y: 0,
+            rotation: 0,
+
+            score: 0,
+            lines_cleared: 0,
+            back_to_back: false,
+            combo: 0,
+            last_move_was_rotation: false,
+
+            blocks: vec![
+                vec![None; settings.board_width as usize];
+                settings.total_height() as usize
+            ],
+
+            reveal_until: 0,
+
+            phase: Phase::Active,
+            arcade_level: 0,
+            can_become_grand_master: true,
+
+            previous_shape: None,
+            current_shape: None, // TODO: Select random shape
+            held_shape: None,
+            can_hold_shape: true,
+
+            frame: 0,
+            pieces_spawned: 0,
+            pieces_locked: 0,
+
+            splits: Vec::new(),
+            personal_best_splits: Vec::new(),
+
+            piece_inputs: 0,
+            finesse_faults: 0,
+
+            spawn_snapshot: None,
+            undo_history: Vec::new(),
+            redo_history: Vec::new(),
+
+            queue: None,
+            objective: None,
+            exports: Vec::new(),
+
+            effects: Vec::new(),
+            popup: None,
+        };
+
+        if game.rules.garbage {
+            game.add_garbage();
+        }
+
+        game.spawn_next_shape();
+        game
+    }
+
+    // Sets up a game of a puzzle. The settings should come from the puzzle, so
+    // that the board is the right size.
+    pub fn from_puzzle(puzzle: &Puzzle, settings: &Settings) -> Tetris {
+        let mut game = Tetris::new(0, GameMode::Puzzle, settings);
+
+        game.fill_board(&puzzle.board);
+        game.objective = Some(puzzle.objective);
+        game.held_shape = puzzle.hold.and_then(crate::pieces::tetromino);
+        game.start_queue(&puzzle.pieces);
+        game
+    }
+
+    // Sets up a Zen game from the first page of a fumen. The pieces that get
+    // placed over all of the pages come out first, and then it's random pieces
+    // like always. The board has to be 10 wide, like it is in fumen.
+    pub fn from_fumen(pages: &[Page], seed: u64, settings: &Settings) -> Tetris {
+        let mut game = Tetris::new(seed, GameMode::Zen, settings);
+
+        if let Some(page) = pages.first() {
+            game.fill_board(&page.field);
+        }
+
+        let letters: Vec<char> = pages
+            .iter()
+            .filter_map(|page| Some(page.piece?.letter))
+            .collect();
+
+        game.start_queue(&letters);
+        game
+    }
+
+    // Puts a board (from the top down, with letters for the colors) at the
+    // bottom of the board. Whatever doesn't fit gets cut off at the top.
+    fn fill_board(&mut self, board: &[Vec<Option<char>>]) {
+        let to_block = |cell: &Option<char>| {
+            Some(Block {
+                color: crate::puzzle::cell_color((*cell)?),
+                glyphs: [crate::unicode::FULL_BLOCK, crate::unicode::FULL_BLOCK],
+                locked_at: 0,
+            })
+        };
+
+        let width = self.settings.board_width as usize;
+        let rows = board.len().min(self.blocks.len());
+        let top = self.blocks.len() - rows;
+
+        for (i, row) in board[board.len() - rows..].iter().enumerate() {
+            let mut row: Vec<Option<Block>> = row.iter().map(to_block).collect();
+            row.resize(width, None);
+
+            self.blocks[top + i] = row;
+        }
+    }
+
+    // Replaces the random piece that the game started with by the pieces in
+    // the queue.
+    fn start_queue(&mut self, letters: &[char]) {
+        self.queue = Some(
+            letters
+                .iter()
+                .filter_map(|letter| crate::pieces::tetromino(*letter))
+                .collect(),
+        );
+
+        // The snapshot of the random piece would bring it back on an undo.
+        self.current_shape = None;
+        self.spawn_snapshot = None;
+        self.pieces_spawned = 0;
+        self.spawn_next_shape();
+    }
+
+    // The board as a page of fumen, along with the piece that's being played.
+    // Fumen only knows about the standard pieces, so anything else turns into
+    // garbage.
+    pub fn fumen_page(&self) -> Page {
+        let to_cell = |block: &Option<Block>| {
+            let color = block.as_ref()?.color;
+
+            Some(
+                "IOTSZJL"
+                    .chars()
+                    .find(|letter| crate::puzzle::cell_color(*letter) == color)
+                    .unwrap_or('X'),
+            )
+        };
+
+        // The board goes from the bottom up in fumen, so rows are lined up
+        // with the bottom of ours.
+        let height = self.blocks.len() as i32;
+        let mut field = vec![vec![None; FIELD_WIDTH]; FIELD_HEIGHT];
+        for (i, row) in field.iter_mut().enumerate() {
+            let y = height - FIELD_HEIGHT as i32 + i as i32;
+
+            if let Some(blocks) = usize::try_from(y).ok().and_then(|y| self.blocks.get(y)) {
+                for (cell, block) in row.iter_mut().zip(blocks.iter()) {
+                    *cell = to_cell(block);
+                }
+            }
+        }
+
+        let mut page = Page::new(field);
+
+        page.piece = self.current_shape.as_ref().and_then(|shape| {
+            let letter = match &shape.fill_pixel.color {
+                crate::screen::Color::Basic(color) => to_cell(&Some(Block {
+                    color: *color,
+                    glyphs: shape.fill_pixel.shape,
+                    locked_at: 0,
+                }))?,
+                crate::screen::Color::Default => return None,
+            };
+
+            let cells: Vec<(i32, i32)> = shape
+                .pixels
+                .iter()
+                .map(|(x, y)| {
+                    (
+                        *x as i32 + self.player_x as i32 - 1,
+                        height - 1 - (*y as i32 + self.player_y as i32),
+                    )
+                })
+                .collect();
+
+            Placement::from_cells(letter, &cells)
+                .filter(|piece| piece.cells().iter().all(|(_, y)| *y < FIELD_HEIGHT as i32))
+        });
+
+        page
+    }
+
+    // Hands over the boards that were exported during the game.
+    pub fn take_exports(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.exports)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
+    pub fn is_cleared(&self) -> bool {
+        self.is_cleared
+    }
+
+    // Whether the game is over for good, one way or another.
+    pub fn has_ended(&self) -> bool {
+        self.is_game_over || self.is_cleared
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn lines_cleared(&self) -> u32 {
+        self.lines_cleared
+    }
+
+    // How many rows on the board still have garbage in them.
+    pub fn garbage_remaining(&self) -> u32 {
+        self.blocks
+            .iter()
+            .filter(|row| {
+                row.iter()
+                    .any(|block| block.map(|block| block.color) == Some(GARBAGE_COLOR))
+            })
+            .count() as u32
+    }
+
+    // Every ten lines is a new level.
+    // With arcade timing, the level is counted separately.
+    pub fn level(&self) -> u32 {
+        if self.rules.arcade {
+            return self.arcade_level;
+        }
+
+        (self.settings.start_level + self.lines_cleared / 10).min(MAX_LEVEL)
+    }
+
+    // The grade that the score is worth, in modes with arcade timing.
+    pub fn grade(&self) -> Option<&'static str> {
+        if !self.rules.arcade {
+            return None;
+        }
+
+        if self.is_cleared
+            && self.can_become_grand_master
+            && self.score >= GRAND_MASTER_SCORE
+            && self.time_ms() <= GRAND_MASTER_TIME_MS
+        {
+            return Some("GM");
+        }
+
+        GRADES
+            .iter()
+            .rev()
+            .find(|(score, _)| self.score >= *score)
+            .map(|(_, grade)| *grade)
+    }
+
+    // The delays of the current section, in frames at the actual frame rate.
+    fn delays(&self) -> Delays {
+        let delays = ARCADE_DELAYS[(self.arcade_level / 100).min(9) as usize];
+        let frames = |frames: u32| (frames * self.settings.frame_rate as u32 / 60).max(1);
+
+        Delays {
+            spawn: frames(delays.spawn),
+            line_spawn: frames(delays.line_spawn),
+            lock: frames(delays.lock),
+            clear: frames(delays.clear),
+        }
+    }
+
+    // How many rows the piece falls every frame.
+    fn gravity(&self) -> f32 {
+        let level = if self.rules.practice_gravity {
+            self.settings.practice_gravity
+        } else {
+            self.level()
+        };
+
+        if level == 0 {
+            return 0.0;
+        }
+
+        let seconds_per_row = SECONDS_PER_ROW[level as usize - 1];
+        self.settings.fall_speed / (seconds_per_row * self.settings.frame_rate as f32)
+    }
+
+    pub fn is_endless(&self) -> bool {
+        self.is_endless
+    }
+
+    // Lets the game carry on past the goal of the mode, for as long as the
+    // player manages to survive. If the goal has already been reached, the game
+    // picks up right where it stopped.
+    pub fn keep_going(&mut self) {
+        self.is_endless = true;
+
+        if self.is_cleared {
+            self.is_cleared = false;
+            self.is_running = true;
+            self.spawn_next_shape();
+        }
+    }
+
+    // How long the game has been going on for, in milliseconds. This is based
+    // on the number of frames rather than the wall clock so that it's the same
+    // when watching a replay.
+    pub fn time_ms(&self) -> u64 {
+        self.frame as u64 * 1000 / self.settings.frame_rate as u64
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn pieces_spawned(&self) -> u32 {
+        self.pieces_spawned
+    }
+
+    pub fn pieces_per_second(&self) -> f64 {
+        match self.time_ms() {
+            0 => 0.0,
+            time_ms => self.pieces_locked as f64 * 1000.0 / time_ms as f64,
+        }
+    }
+
+    pub fn finesse_faults(&self) -> u32 {
+        self.finesse_faults
+    }
+
+    pub fn splits(&self) -> &[u64] {
+        &self.splits
+    }
+
+    // Sets the split times that are shown next to the player's own, so that
+    // they can see whether they are ahead or behind.
+    pub fn compare_splits_with(&mut self, splits: Vec<u64>) {
+        self.personal_best_splits = splits;
+    }
+
+    // A hash of the fossilized blocks, used to check that a replay ended up
+    // with the exact same board as the original game. This is FNV-1a, which
+    // is more than good enough for this.
+    pub fn board_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+
+        self.blocks.iter().flatten().for_each(|block| {
+            // Only the colors count, so that this stays the same as it was
+            // before blocks knew when they were locked.
+            let value = match block {
+                Some(block) => block.color as u64 + 1,
+                None => 0,
+            };
+
+            hash ^= value;
+            hash = hash.wrapping_mul(0x100000001b3);
+        });
+
+        hash
+    }
+
+    // Writes out everything about the game as "key value" lines, so that it can
+    // be picked back up later on. The settings aren't included, since whoever
+    // is saving the game should store those themselves (see save.rs).
+    pub fn write_state(&self, out: &mut String) {
+        *out += &format!("rng {}\n", self.random_generator.seed);
+        *out += &format!("frame {}\n", self.frame);
+        *out += &format!("pieces_spawned {}\n", self.pieces_spawned);
+        *out += &format!("pieces_locked {}\n", self.pieces_locked);
+        *out += &format!("piece_inputs {}\n", self.piece_inputs);
+        *out += &format!("finesse_faults {}\n", self.finesse_faults);
+        *out += &format!("splits {}\n", times_to_string(&self.splits));
+        *out += &format!("fall_progress {}\n", self.fall_progress);
+        *out += &format!("endless {}\n", self.is_endless);
+        *out += &format!("reveal_until {}\n", self.reveal_until);
+        *out += &format!("phase {}\n", phase_to_string(self.phase));
+        *out += &format!("arcade_level {}\n", self.arcade_level);
+        *out += &format!("can_become_grand_master {}\n", self.can_become_grand_master);
+        *out += &format!("player_x {}\n", self.player_x);
+        *out += &format!("player_y {}\n", self.player_y);
+        *out += &format!("rotation {}\n", self.rotation);
+        *out += &format!("score {}\n", self.score);
+        *out += &format!("lines_cleared {}\n", self.lines_cleared);
+        *out += &format!("back_to_back {}\n", self.back_to_back);
+        *out += &format!("combo {}\n", self.combo);
+        *out += &format!("last_move_was_rotation {}\n", self.last_move_was_rotation);
+        *out += &format!("can_hold_shape {}\n", self.can_hold_shape);
+        *out += &format!("current_shape {}\n", shape_to_string(&self.current_shape));
+        *out += &format!("held_shape {}\n", shape_to_string(&self.held_shape));
+        *out += &format!("previous_shape {}\n", shape_to_string(&self.previous_shape));
+
+        if let Some(snapshot) = self.spawn_snapshot.as_ref() {
+            *out += &format!("spawn_snapshot {}\n", snapshot.write());
+        }
+
+        for snapshot in self.undo_history.iter() {
+            *out += &format!("undo {}\n", snapshot.write());
+        }
+
+        for snapshot in self.redo_history.iter() {
+            *out += &format!("redo {}\n", snapshot.write());
+        }
+
+        // The board goes from top to bottom.
+        for row in self.blocks.iter() {
+            *out += &format!("row {}\n", row_to_string(row));
+        }
+    }
+
+    // The opposite of write_state(). Takes the "key value" pairs that it wrote
+    // and turns them back into a game, or says which key is wrong.
+    pub fn read_state(
+        mode: GameMode,
+        settings: &Settings,
+        fields: &[(&str, &str)],
+    ) -> Result<Tetris, String> {
+        let mut game = Tetris::new(0, mode, settings);
+        game.blocks.clear();
+
+        // The board in the state is the one that the game sees, which is
+        // smaller than the settings say in big mode.
+        let settings = &game.settings.clone();
+
+        for (key, value) in fields.iter() {
+            let invalid = || format!("invalid value for '{}'", key);
+
+            fn parse<T: std::str::FromStr>(value: &str, field: &mut T) -> Result<(), ()> {
+                *field = value.parse().map_err(|_| ())?;
+                Ok(())
+            }
+
+            match *key {
+                "rng" => parse(value, &mut game.random_generator.seed),
+                "frame" => parse(value, &mut game.frame),
+                "pieces_spawned" => parse(value, &mut game.pieces_spawned),
+                "pieces_locked" => parse(value, &mut game.pieces_locked),
+                "piece_inputs" => parse(value, &mut game.piece_inputs),
+                "finesse_faults" => parse(value, &mut game.finesse_faults),
+                "splits" => times_from_string(value).map(|splits| game.splits = splits),
+                "fall_progress" => parse(value, &mut game.fall_progress),
+                "endless" => parse(value, &mut game.is_endless),
+                "reveal_until" => parse(value, &mut game.reveal_until),
+                "phase" => phase_from_string(value).map(|phase| game.phase = phase),
+                "arcade_level" => parse(value, &mut game.arcade_level),
+                "can_become_grand_master" => parse(value, &mut game.can_become_grand_master),
+                "player_x" => parse(value, &mut game.player_x),
+                "player_y" => parse(value, &mut game.player_y),
+                "rotation" => parse(value, &mut game.rotation),
+                "score" => parse(value, &mut game.score),
+                "lines_cleared" => parse(value, &mut game.lines_cleared),
+                "back_to_back" => parse(value, &mut game.back_to_back),
+                "combo" => parse(value, &mut game.combo),
+                "last_move_was_rotation" => parse(value, &mut game.last_move_was_rotation),
+                "can_hold_shape" => parse(value, &mut game.can_hold_shape),
+                "current_shape" => shape_from_string(value).map(|shape| game.current_shape = shape),
+                "held_shape" => shape_from_string(value).map(|shape| game.held_shape = shape),
+                "previous_shape" => {
+                    shape_from_string(value).map(|shape| game.previous_shape = shape)
+                }
+                "spawn_snapshot" => Snapshot::read(value, settings)
+                    .map(|snapshot| game.spawn_snapshot = Some(snapshot)),
+                "undo" => {
+                    Snapshot::read(value, settings).map(|snapshot| game.undo_history.push(snapshot))
+                }
+                "redo" => {
+                    Snapshot::read(value, settings).map(|snapshot| game.redo_history.push(snapshot))
+                }
+                "row" => {
+                    row_from_string(value, settings.board_width).map(|row| game.blocks.push(row))
+                }
+                _ => return Err(format!("unknown key '{}'", key)),
+            }
+            .map_err(|_| invalid())?;
+        }
+
+        if game.blocks.len() != settings.total_height() as usize {
+            return Err(String::from("the board is the wrong size"));
+        }
+
+        if game.rotation > 3 {
+            return Err(String::from("invalid value for 'rotation'"));
+        }
+
+        // The position gets worked with as signed numbers, which a number from
+        // way off the board wouldn't fit in.
+        if game.player_x > settings.board_width as u16 {
+            return Err(String::from("invalid value for 'player_x'"));
+        }
+
+        if game.player_y >= settings.total_height() as u16 {
+            return Err(String::from("invalid value for 'player_y'"));
+        }
+
+        // A seed that's too big would overflow when the next number gets
+        // worked out. The snapshots get checked as well, since undoing puts
+        // their seeds back.
+        if !game
+            .random_generator
+            .is_valid_seed(game.random_generator.seed)
+        {
+            return Err(String::from("invalid value for 'rng'"));
+        }
+
+        let snapshots = game
+            .spawn_snapshot
+            .iter()
+            .chain(game.undo_history.iter())
+            .chain(game.redo_history.iter());
+
+        for snapshot in snapshots {
+            if !game.random_generator.is_valid_seed(snapshot.rng) {
+                return Err(String::from("invalid seed in the undo history"));
+            }
+        }
+
+        // The game has to be in a state where it can keep going, otherwise
+        // there's no point in continuing it. There's no piece at all while
+        // waiting for the next one, though.
+        let is_waiting = matches!(game.phase, Phase::Spawning(_) | Phase::Clearing(_));
+        let (within_x_bounds, within_y_bounds) = game.is_shape_in_bounds();
+        if !is_waiting && (!within_x_bounds || !within_y_bounds) {
+            return Err(String::from("the current shape doesn't fit on the board"));
+        }
+
+        game.is_running = true;
+        game.is_game_over = false;
+
+        Ok(game)
+    }
+
+    // Checks if the current shape is within the bounds of the game.
+    fn is_shape_in_bounds(&self) -> (bool, bool) {
+        if let Some(current_shape) = self.current_shape.as_ref() {
+            let mut within_x_bounds = true;
+            let mut within_y_bounds = true;
+
+            current_shape.pixels.iter().for_each(|(block_x, block_y)| {
+                let block_x: i16 =
+                    block_x + <u16 as TryInto<i16>>::try_into(self.player_x).unwrap();
+                let block_y: i16 =
+                    block_y + <u16 as TryInto<i16>>::try_into(self.player_y).unwrap();
+
+                // Both directions get checked for every block, the same as in
+                // Shape::is_within_bounds.
+                let is_row_on_board = block_y < self.blocks.len() as i16 && block_y >= 0;
+                let is_column_on_board = block_x <= self.settings.board_width as i16 && block_x > 0;
+
+                if !is_row_on_board {
+                    within_y_bounds = false;
+                }
+
+                if !is_column_on_board {
+                    within_x_bounds = false;
+                }
+
+                if !is_row_on_board || !is_column_on_board {
+                    return;
+                }
+
+                // Check that it is not colliding with fossilized blocks.
+
+                if self.blocks[<i16 as TryInto<usize>>::try_into(block_y).unwrap()]
+                    [<i16 as TryInto<usize>>::try_into(block_x - 1).unwrap()]
+                .is_some()
+                {
+                    within_x_bounds = false;
+                    within_y_bounds = false;
+                }
+            });
+
+            (within_x_bounds, within_y_bounds)
+        } else {
+            (false, false)
+        }
+    }
+
+    fn fossilize_current_piece(&mut self) {
+        if let Some(snapshot) = self.spawn_snapshot.take() {
+            self.undo_history.push(snapshot);
+            self.redo_history.clear();
+
+            if self.undo_history.len() > MAX_UNDO {
+                self.undo_history.remove(0);
+            }
+        }
+
+        if self.current_shape.is_some() {
+            self.finesse_faults += self
+                .piece_inputs
+                .saturating_sub(self.fewest_inputs_for_placement());
+            self.piece_inputs = 0;
+            self.pieces_locked += 1;
+        }
+
+        // This has to be checked before the rows get cleared.
+        let t_spin = self.t_spin();
+        let score_before = self.score;
+        let was_back_to_back = self.back_to_back;
+
+        // A piece that locks without any of it making it down onto the board is
+        // a lock out, which ends the game just like not having room to spawn.
+        let is_lock_out = self.current_shape.as_ref().is_some_and(|shape| {
+            shape
+                .pixels
+                .iter()
+                .all(|(_, y)| y + (self.player_y as i16) < BUFFER_HEIGHT as i16)
+        });
+
+        if let Some(shape) = self.current_shape.as_ref() {
+            shape.pixels.iter().for_each(|(component_x, component_y)| {
+                self.blocks[<i16 as TryInto<usize>>::try_into(
+                    *component_y + <u16 as TryInto<i16>>::try_into(self.player_y).unwrap(),
+                )
+                .unwrap()][<i16 as TryInto<usize>>::try_into(
+                    *component_x + <u16 as TryInto<i16>>::try_into(self.player_x - 1).unwrap(),
+                )
+                .unwrap()] = if let crate::screen::Color::Basic(color) = shape.fill_pixel.color {
+                    Some(Block {
+                        color,
+                        glyphs: shape.fill_pixel.shape,
+                        locked_at: self.frame,
+                    })
+                } else {
+                    None
+                };
+            });
+
+            let cells = self.piece_cells(shape);
+            let bottom_row = cells.iter().map(|(_, row)| *row).max().unwrap_or(0);
+
+            if self.settings.animations {
+                self.add_effect(EffectKind::LockFlash, cells, 0);
+            }
+
+            self.previous_shape = self.current_shape.take();
+
+            if is_lock_out && self.rules.top_out {
+                self.is_running = false;
+                self.is_game_over = true;
+                return;
+            }
+
+            // With arcade timing, the full rows stay on the board for a bit
+            // before they get cleared.
+            let rows_cleared = if self.rules.arcade {
+                let rows_cleared = self.full_rows();
+                let delays = self.delays();
+
+                self.award_arcade_points(rows_cleared);
+                self.phase = if rows_cleared > 0 {
+                    Phase::Clearing(delays.clear)
+                } else {
+                    Phase::Spawning(delays.spawn)
+                };
+
+                rows_cleared
+            } else {
+                // The full rows stay put for the line clear delay, if there
+                // is one, so that they can be seen going away.
+                let clear_frames = self.line_clear_frames();
+                let rows_cleared = if clear_frames > 0 {
+                    let rows_cleared = self.full_rows();
+
+                    if rows_cleared > 0 {
+                        self.phase = Phase::Clearing(clear_frames);
+                    }
+
+                    rows_cleared
+                } else {
+                    self.clear_full_rows()
+                };
+
+                self.award_points(rows_cleared, t_spin);
+                rows_cleared
+            };
+
+            self.lines_cleared += rows_cleared;
+
+            // Back to backs only count for the guideline scoring.
+            let is_back_to_back =
+                !self.rules.arcade && was_back_to_back && self.back_to_back && rows_cleared > 0;
+            self.add_popup(
+                rows_cleared,
+                t_spin,
+                is_back_to_back,
+                self.score - score_before,
+                bottom_row,
+            );
+
+            if rows_cleared > 0 {
+                let reveal_frames = REVEAL_MS * self.settings.frame_rate as u64 / 1000;
+                self.reveal_until = self.frame + reveal_frames as u32;
+            }
+
+            if self.rules.arcade {
+                self.advance_arcade_level(rows_cleared, true);
+            }
+
+            self.update_splits();
+
+            if let Some(goal) = self.rules.line_goal {
+                if self.lines_cleared >= goal && !self.is_endless {
+                    self.is_running = false;
+                    self.is_cleared = true;
+                }
+            }
+
+            if self.rules.garbage && self.garbage_remaining() == 0 {
+                self.is_running = false;
+                self.is_cleared = true;
+            }
+
+            if let Some(objective) = self.objective {
+                self.check_objective(objective, rows_cleared, t_spin);
+            }
+        }
+
+        self.can_hold_shape = true;
+    }
+
+    // Ends a puzzle once its objective has been met, or once it can't be met
+    // anymore.
+    fn check_objective(&mut self, objective: Objective, rows_cleared: u32, t_spin: Option<TSpin>) {
+        let is_solved = match objective {
+            Objective::Lines(lines) => self.lines_cleared >= lines,
+            Objective::TSpin(lines) => t_spin == Some(TSpin::Full) && rows_cleared == lines,
+            Objective::PerfectClear(pieces) => {
+                // The full rows might still be waiting to be cleared.
+                rows_cleared > 0
+                    && self.pieces_locked <= pieces
+                    && self.blocks.iter().all(|row| {
+                        row.iter().all(|block| block.is_none())
+                            || row.iter().all(|block| block.is_some())
+                    })
+            }
+        };
+
+        if is_solved {
+            self.is_running = false;
+            self.is_cleared = true;
+        } else if matches!(objective, Objective::PerfectClear(pieces) if self.pieces_locked >= pieces)
+        {
+            self.is_running = false;
+            self.is_game_over = true;
+        }
+    }
+
+    // How many frames full lines stay on the board for, outside of arcade
+    // timing.
+    fn line_clear_frames(&self) -> u32 {
+        if !self.settings.animations {
+            return 0;
+        }
+
+        (self.settings.line_clear_delay as u64 * self.settings.frame_rate as u64 / 1000) as u32
+    }
+
+    // How far along the line clear is, from 0 to 1, while full lines are
+    // waiting to be cleared.
+    fn clear_progress(&self) -> Option<f32> {
+        let frames_left = match self.phase {
+            Phase::Clearing(frames) if self.settings.animations => frames,
+            _ => return None,
+        };
+
+        let total_frames = if self.rules.arcade {
+            self.delays().clear
+        } else {
+            self.line_clear_frames()
+        };
+
+        Some(1.0 - frames_left as f32 / total_frames.max(1) as f32)
+    }
+
+    // The cells of the board that a piece takes up where it is now.
+    fn piece_cells(&self, shape: &Shape) -> Vec<(usize, usize)> {
+        shape
+            .pixels
+            .iter()
+            .map(|(x, y)| {
+                (
+                    (*x + self.player_x as i16 - 1) as usize,
+                    (*y + self.player_y as i16) as usize,
+                )
+            })
+            .collect()
+    }
+
+    fn add_effect(&mut self, kind: EffectKind, cells: Vec<(usize, usize)>, color: u8) {
+        self.effects.push(Effect {
+            kind,
+            cells,
+            color,
+            started_at: self.frame,
+        });
+    }
+
+    // Leaves a trail behind the current piece, from where it was dropped down
+    // to where it landed.
+    fn add_trail(&mut self, start_y: u16) {
+        let shape = match self.current_shape.as_ref() {
+            Some(shape) => shape,
+            None => return,
+        };
+
+        let color = match shape.fill_pixel.color {
+            crate::screen::Color::Basic(color) => color,
+            crate::screen::Color::Default => return,
+        };
+
+        let landed = self.piece_cells(shape);
+        let distance = (self.player_y - start_y) as usize;
+
+        let mut cells: Vec<(usize, usize)> = landed
+            .iter()
+            .flat_map(|&(column, row)| (1..=distance).map(move |up| (column, row.wrapping_sub(up))))
+            .filter(|cell| cell.1 < self.blocks.len() && !landed.contains(cell))
+            .collect();
+        cells.sort();
+        cells.dedup();
+
+        self.add_effect(EffectKind::Trail, cells, color);
+    }
+
+    // Pops up some text about the piece that just locked, if it did anything
+    // worth mentioning.
+    fn add_popup(
+        &mut self,
+        rows_cleared: u32,
+        t_spin: Option<TSpin>,
+        is_back_to_back: bool,
+        points: u32,
+        row: usize,
+    ) {
+        use crate::screen::colors::basic::*;
+
+        let count = match rows_cleared {
+            0 => String::new(),
+            1 => String::from("SINGLE"),
+            2 => String::from("DOUBLE"),
+            3 => String::from("TRIPLE"),
+            4 => String::from("TETRIS"),
+            lines => format!("{} LINES", lines),
+        };
+
+        let name = match t_spin {
+            Some(TSpin::Full) => format!("T-SPIN {}", count),
+            Some(TSpin::Mini) => format!("MINI T-SPIN {}", count),
+            None => count,
+        };
+
+        let mut lines = Vec::new();
+
+        if is_back_to_back {
+            lines.push((String::from("BACK-TO-BACK"), BRIGHT_MAGENTA));
+        }
+
+        if !name.is_empty() {
+            let color = if t_spin.is_some() || rows_cleared >= 4 {
+                BRIGHT_YELLOW
+            } else {
+                BRIGHT_WHITE
+            };
+
+            lines.push((name.trim().to_string(), color));
+        }
+
+        // The full rows might still be on the board at this point.
+        let is_perfect_clear = rows_cleared > 0
+            && self.blocks.iter().all(|row| {
+                row.iter().all(|block| block.is_none()) || row.iter().all(|block| block.is_some())
+            });
+
+        if is_perfect_clear {
+            lines.push((String::from("PERFECT CLEAR"), BRIGHT_CYAN));
+        }
+
+        if lines.is_empty() {
+            return;
+        }
+
+        if points > 0 {
+            lines.push((format!("+{}", points), BRIGHT_GREEN));
+        }
+
+        self.popup = Some(Popup {
+            lines,
+            row,
+            started_at: self.frame,
+        });
+    }
+
+    // How many rows of the board are full.
+    fn full_rows(&self) -> u32 {
+        self.blocks
+            .iter()
+            .filter(|row| row.iter().all(|block| block.is_some()))
+            .count() as u32
+    }
+
+    // Clears the full rows, moving everything above them down. Returns how
+    // many rows got cleared.
+    fn clear_full_rows(&mut self) -> u32 {
+        let mut rows_cleared = 0;
+
+        // Now, iterate through the rows and clear the ones that are full.
+        let mut i = 0;
+        while i < self.blocks.len() {
+            let is_row_full = self.blocks[i].iter().all(|block| block.is_some());
+
+            if is_row_full {
+                self.blocks.remove(i);
+                self.blocks
+                    .insert(0, vec![None; self.settings.board_width as usize]);
+                rows_cleared += 1;
+
+                // Effects move down along with everything else.
+                for effect in self.effects.iter_mut() {
+                    effect.cells.retain(|(_, row)| *row != i);
+                    effect
+                        .cells
+                        .iter_mut()
+                        .filter(|(_, row)| *row < i)
+                        .for_each(|(_, row)| *row += 1);
+                }
+
+                continue;
+            }
+
+            i += 1;
+        }
+
+        rows_cleared
+    }
+
+    // Records the time of every split that has been reached since the last
+    // time this was called. Splits are every so many lines, or every so many
+    // levels with arcade timing. The goal always counts as a split, even if
+    // it's not a round number (like level 999).
+    fn update_splits(&mut self) {
+        let interval = match self.rules.split_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        let (progress, goal) = self.progress();
+        let split_count = match goal {
+            Some(goal) if progress >= goal => goal.div_ceil(interval),
+            _ => progress / interval,
+        };
+
+        while (self.splits.len() as u32) < split_count {
+            self.splits.push(self.time_ms());
+
+            let level = self.splits.len() as u32 * interval;
+            let time_ms = self.time_ms();
+
+            // Falling behind at any of the checks rules out becoming a Grand
+            // Master for the rest of the game.
+            if self.rules.arcade {
+                for (check_level, score, check_time_ms) in GRAND_MASTER_CHECKS {
+                    if level == check_level && (self.score < score || time_ms > check_time_ms) {
+                        self.can_become_grand_master = false;
+                    }
+                }
+            }
+        }
+    }
+
+    // How far the game has gotten, and where the goal is. This is counted in
+    // lines, except with arcade timing where it's counted in levels.
+    fn progress(&self) -> (u32, Option<u32>) {
+        if self.rules.arcade {
+            (self.arcade_level, Some(ARCADE_LAST_LEVEL))
+        } else {
+            (self.lines_cleared, self.rules.line_goal)
+        }
+    }
+
+    // Arcade levels go up by one for every piece that comes out and by one for
+    // every line cleared. Only lines can take the level past the end of a
+    // section (like 199) or to the very end, though.
+    fn advance_arcade_level(&mut self, amount: u32, by_lines: bool) {
+        let level = self.arcade_level;
+
+        if !by_lines && (level % 100 == 99 || level == ARCADE_LAST_LEVEL - 1) {
+            return;
+        }
+
+        self.arcade_level = (level + amount).min(ARCADE_LAST_LEVEL);
+        self.update_splits();
+
+        if self.arcade_level == ARCADE_LAST_LEVEL {
+            self.is_running = false;
+            self.is_cleared = true;
+        }
+    }
+
+    // Scoring with arcade timing. Clearing lines is worth more on higher
+    // levels, more when several clears come one after another, and four times
+    // as much if it empties the whole board.
+    fn award_arcade_points(&mut self, lines: u32) {
+        if lines == 0 {
+            self.combo = 0;
+            return;
+        }
+
+        self.combo = self.combo.max(1) + 2 * lines - 2;
+
+        // The full rows are still on the board at this point.
+        let is_bravo = self.blocks.iter().all(|row| {
+            row.iter().all(|block| block.is_some()) || row.iter().all(|block| block.is_none())
+        });
+        let bravo = if is_bravo { 4 } else { 1 };
+
+        self.score += (self.arcade_level + lines).div_ceil(4) * lines * self.combo * bravo;
+    }
+
+    // With arcade timing, pieces drop straight to the bottom, and lock once
+    // they have been sitting there for the lock delay. The delay starts over
+    // whenever the piece gets any lower, but not when it only moves sideways.
+    fn update_lock(&mut self) {
+        if self.current_shape.is_none() {
+            return;
+        }
+
+        let start_y = self.player_y;
+        self.fall_until_hit();
+
+        match self.phase {
+            Phase::Locking(frames) if self.player_y == start_y => {
+                if frames > 1 {
+                    self.phase = Phase::Locking(frames - 1);
+                } else {
+                    self.fossilize_current_piece();
+                }
+            }
+            _ => self.phase = Phase::Locking(self.delays().lock),
+        }
+    }
+
+    // Counts down the wait between pieces, and moves on once it's over.
+    fn wait(&mut self) {
+        self.phase = match self.phase {
+            Phase::Spawning(1) => {
+                self.advance_arcade_level(1, false);
+
+                if self.is_running {
+                    self.spawn_next_shape();
+                }
+
+                Phase::Active
+            }
+            Phase::Clearing(1) if !self.rules.arcade => {
+                self.clear_full_rows();
+
+                if self.is_running {
+                    self.spawn_next_shape();
+                }
+
+                Phase::Active
+            }
+            Phase::Clearing(1) => {
+                self.clear_full_rows();
+                Phase::Spawning(self.delays().line_spawn)
+            }
+            Phase::Spawning(frames) => Phase::Spawning(frames - 1),
+            Phase::Clearing(frames) => Phase::Clearing(frames - 1),
+            phase => phase,
+        };
+    }
+
+    // Fills the bottom of the board with rows of garbage, each with a single hole
+    // in it. The garbage never takes up more than half of the board, so that
+    // there's always room to play.
+    fn add_garbage(&mut self) {
+        use crate::unicode::FULL_BLOCK;
+
+        let width = self.settings.board_width as usize;
+        let rows = self
+            .settings
+            .garbage_rows
+            .min(self.settings.board_height / 2) as usize;
+        let mut hole = self.random_generator.generate() as usize % width;
+
+        for i in 0..rows {
+            // The hole moves by at least one column, so that a messiness of
+            // 100 really does mean that no two holes line up.
+            if i > 0
+                && self.random_generator.generate() % 100 < self.settings.garbage_messiness as u64
+            {
+                hole = (hole + 1 + self.random_generator.generate() as usize % (width - 1)) % width;
+            }
+
+            let row = self.blocks.len() - 1 - i;
+            self.blocks[row] = (0..width)
+                .map(|x| {
+                    (x != hole).then_some(Block {
+                        color: GARBAGE_COLOR,
+                        glyphs: [FULL_BLOCK, FULL_BLOCK],
+                        locked_at: 0,
+                    })
+                })
+                .collect();
+        }
+    }
+
+    // Takes a snapshot of the game as it is now, with the current piece back in
+    // the way that it spawned.
+    fn snapshot(&self) -> Snapshot {
+        let mut current_shape = self.current_shape.clone();
+
+        if let Some(shape) = current_shape.as_mut() {
+            (0..self.rotation).for_each(|_| shape.rotate(true));
+        }
+
+        Snapshot {
+            blocks: self.blocks.clone(),
+            rng: self.random_generator.seed,
+            current_shape,
+            held_shape: self.held_shape.clone(),
+            previous_shape: self.previous_shape.clone(),
+            can_hold_shape: self.can_hold_shape,
+            score: self.score,
+            lines_cleared: self.lines_cleared,
+            back_to_back: self.back_to_back,
+            combo: self.combo,
+            pieces_spawned: self.pieces_spawned,
+            pieces_locked: self.pieces_locked,
+            queue: self.queue.clone(),
+        }
+    }
+
+    // Puts the game back the way it was in a snapshot, with the current piece
+    // back where it spawned.
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.blocks = snapshot.blocks.clone();
+        self.random_generator.seed = snapshot.rng;
+        self.current_shape = snapshot.current_shape.clone();
+        self.held_shape = snapshot.held_shape.clone();
+        self.previous_shape = snapshot.previous_shape.clone();
+        self.can_hold_shape = snapshot.can_hold_shape;
+        self.score = snapshot.score;
+        self.lines_cleared = snapshot.lines_cleared;
+        self.back_to_back = snapshot.back_to_back;
+        self.combo = snapshot.combo;
+        self.pieces_spawned = snapshot.pieces_spawned;
+        self.pieces_locked = snapshot.pieces_locked;
+        self.queue = snapshot.queue.clone();
+
+        self.move_to_spawn();
+        self.rotation = 0;
+        self.fall_progress = 0.0;
+        self.piece_inputs = 0;
+        self.last_move_was_rotation = false;
+
+        self.spawn_snapshot = Some(snapshot);
+    }
+
+    // Takes back the last piece that was placed.
+    fn undo(&mut self) {
+        if let Some(snapshot) = self.undo_history.pop() {
+            self.redo_history.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    // Puts back a placement that was undone.
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.redo_history.pop() {
+            self.undo_history.push(self.snapshot());
+            self.restore(snapshot);
+        }
+    }
+
+    // Clears rows from the top of the board until the current piece fits. This
+    // is what happens instead of a game over in modes without one.
+    fn clear_top_rows(&mut self) {
+        while self.is_shape_in_bounds() != (true, true) {
+            match self
+                .blocks
+                .iter()
+                .position(|row| row.iter().any(Option::is_some))
+            {
+                Some(row) => self.blocks[row].iter_mut().for_each(|block| *block = None),
+                None => break,
+            }
+        }
+    }
+
+    // Checks if the current piece is a T that has been spun into place. This uses
+    // the usual three corner rule: at least three of the blocks diagonal to the
+    // middle of the T have to be filled (or be outside of the board).
+    fn t_spin(&self) -> Option<TSpin> {
+        let shape = self.current_shape.as_ref()?;
+
+        if !self.last_move_was_rotation {
+            return None;
+        }
+
+        let mut spawned_shape = shape.clone();
+        (0..self.rotation).for_each(|_| spawned_shape.rotate(true));
+
+        if spawned_shape.pixels != crate::pieces::TEE {
+            return None;
+        }
+
+        // The middle of the T is always at 0,0, and the block that sticks out
+        // is the one that's the only one on its side.
+        let (nub_x, nub_y) = *shape
+            .pixels
+            .iter()
+            .find(|(x, y)| !shape.pixels.contains(&(-x, -y)))?;
+
+        let is_filled = |x: i16, y: i16| {
+            let x = self.player_x as i16 + x - 1;
+            let y = self.player_y as i16 + y;
+
+            x < 0
+                || y < 0
+                || x >= self.settings.board_width as i16
+                || y >= self.blocks.len() as i16
+                || self.blocks[y as usize][x as usize].is_some()
+        };
+
+        let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+        let filled_corners = corners.iter().filter(|(x, y)| is_filled(*x, *y)).count();
+
+        // The front corners are the ones on the same side as the block that
+        // sticks out.
+        let filled_front_corners = corners
+            .iter()
+            .filter(|(x, y)| x * nub_x + y * nub_y > 0 && is_filled(*x, *y))
+            .count();
+
+        match (filled_corners, filled_front_corners) {
+            (0..=2, _) => None,
+            (_, 2) => Some(TSpin::Full),
+            _ => Some(TSpin::Mini),
+        }
+    }
+
+    // Guideline scoring. Line clears are worth more at higher levels, tetrises
+    // and T-spins in a row get a 50% bonus, and clearing lines with several
+    // pieces in a row adds a combo bonus.
+    fn award_points(&mut self, lines: u32, t_spin: Option<TSpin>) {
+        let level = self.level();
+
+        let points = match (t_spin, lines) {
+            (None, 0) => 0,
+            (None, 1) => 100,
+            (None, 2) => 300,
+            (None, 3) => 500,
+            (None, _) => 800,
+            (Some(TSpin::Mini), 0) => 100,
+            (Some(TSpin::Mini), 1) => 200,
+            (Some(TSpin::Mini), _) => 400,
+            (Some(TSpin::Full), 0) => 400,
+            (Some(TSpin::Full), 1) => 800,
+            (Some(TSpin::Full), 2) => 1200,
+            (Some(TSpin::Full), _) => 1600,
+        };
+
+        if lines == 0 {
+            self.score += points * level;
+            self.combo = 0;
+            return;
+        }
+
+        let is_difficult = lines >= 4 || t_spin.is_some();
+
+        self.score += if is_difficult && self.back_to_back {
+            points * level * 3 / 2
+        } else {
+            points * level
+        };
+
+        self.back_to_back = is_difficult;
+
+        if self.combo > 0 {
+            self.score += 50 * self.combo * level;
+        }
+
+        self.combo += 1;
+    }
+
+    // The fewest moves and rotations that could have gotten the current piece
+    // to where it is now from where it spawned. Every rotation is a single
+    // input (since there are 180 degree rotations), and every move is one
+    // input because there's no auto-repeat. Pieces that look the same in
+    // different rotations (like the square) can take whichever is closest.
+    fn fewest_inputs_for_placement(&self) -> u32 {
+        let shape = match self.current_shape.as_ref() {
+            Some(shape) => shape,
+            None => return 0,
+        };
+
+        // The pixels of a shape, moved so that the top left one is at 0,0, and
+        // how far that moved them horizontally.
+        let normalize = |shape: &Shape| {
+            let min_x = shape.pixels.iter().map(|(x, _)| *x).min().unwrap_or(0);
+            let min_y = shape.pixels.iter().map(|(_, y)| *y).min().unwrap_or(0);
+
+            let mut pixels: Vec<(i16, i16)> = shape
+                .pixels
+                .iter()
+                .map(|(x, y)| (x - min_x, y - min_y))
+                .collect();
+            pixels.sort();
+
+            (pixels, min_x)
+        };
+
+        let (target, target_min_x) = normalize(shape);
+        let target_x = self.player_x as i32 + target_min_x as i32;
+
+        let mut spawned_shape = shape.clone();
+        (0..self.rotation).for_each(|_| spawned_shape.rotate(true));
+
+        (0..4)
+            .filter_map(|turns| {
+                let mut candidate = spawned_shape.clone();
+                (0..turns).for_each(|_| candidate.rotate(false));
+
+                let (pixels, min_x) = normalize(&candidate);
+                if pixels != target {
+                    return None;
+                }
+
+                let rotations = if turns == 0 { 0 } else { 1 };
+                let moves =
+                    (target_x - (self.settings.spawn_x as i32 + min_x as i32)).unsigned_abs();
+
+                Some(rotations + moves)
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn fall_until_hit(&mut self) {
+        loop {
+            let (_, not_at_bottom) = self.is_shape_in_bounds();
+            if not_at_bottom {
+                self.player_y += 1;
+            } else {
+                self.player_y -= 1;
+                break;
+            }
+        }
+    }
+
+    // Rotates the current shape by some number of quarter turns to the right (or
+    // to the left if it's negative), unless that would take it out of bounds.
+    fn rotate_current_shape(&mut self, quarter_turns: i32) {
+        // false -> right
+        // true -> left
+        let rotate = |shape: &mut Shape, quarter_turns: i32| {
+            (0..quarter_turns.abs()).for_each(|_| shape.rotate(quarter_turns < 0));
+        };
+
+        let current_shape = match self.current_shape.as_mut() {
+            Some(current_shape) => current_shape,
+            None => return,
+        };
+        rotate(current_shape, quarter_turns);
+
+        // This is to prevent rotating the shape out of bounds.
+        let (within_x_bounds, within_y_bounds) = current_shape.is_within_bounds(
+            self.player_x,
+            self.player_y,
+            self.settings.board_width,
+            self.settings.total_height(),
+        );
+
+        // The shape only knows about the edges of the board, so whether it ended
+        // up inside the stack has to be checked as well.
+        let is_in_stack = self.is_shape_in_bounds() != (true, true);
+
+        if !within_x_bounds || !within_y_bounds || is_in_stack {
+            // Undo the rotation if it results in the shape going out of bounds.
+            if let Some(current_shape) = self.current_shape.as_mut() {
+                rotate(current_shape, -quarter_turns);
+            }
+        } else {
+            self.rotation = (self.rotation as i32 + quarter_turns).rem_euclid(4) as u8;
+            self.last_move_was_rotation = true;
+        }
+    }
+
+    // Picks the next piece out of the piece set.
+    fn random_shape(&mut self) -> Shape {
+        loop {
+            let generated_shape =
+                self.pieces[<u64 as TryInto<usize>>::try_into(self.random_generator.generate())
+                    .unwrap()
+                    % self.pieces.len()]
+                .clone();
+
+            // With only one or two pieces in the set, not having repeats
+            // would make the order completely predictable.
+            if self.pieces.len() <= 2 {
+                break generated_shape;
+            }
+
+            if let Some(previous_shape) = self.previous_shape.as_ref() {
+                if generated_shape != *previous_shape {
+                    break generated_shape;
+                }
+            } else {
+                break generated_shape;
+            }
+        }
+    }
+
+    // Puts the current piece where new pieces come in, which is with its bottom
+    // in the row right above the board. Like in the guideline, it then drops a
+    // row straight away if there's room for it.
+    fn move_to_spawn(&mut self) {
+        let bottom = self.current_shape.as_ref().map_or(0, |shape| {
+            shape.pixels.iter().map(|(_, y)| *y).max().unwrap_or(0)
+        });
+
+        self.player_x = self.settings.spawn_x;
+        self.player_y = (BUFFER_HEIGHT as i16 - 1 - bottom) as u16;
+
+        if let (true, true) = self.is_shape_in_bounds() {
+            self.player_y += 1;
+
+            if self.is_shape_in_bounds() != (true, true) {
+                self.player_y -= 1;
+            }
+        }
+    }
+
+    // If the current shape is out of bounds as soon as it's spawned, then it's
+    // likely because the player has lost. Modes that can't be lost make room
+    // for it instead.
+    fn check_spawn_room(&mut self) {
+        let (within_x_bounds, within_y_bounds) = self.is_shape_in_bounds();
+        if !within_x_bounds || !within_y_bounds {
+            if self.rules.top_out {
+                self.is_running = false;
+                self.is_game_over = true;
+            } else {
+                self.clear_top_rows();
+            }
+        }
+    }
+
+    // Picks a new shape and puts it at the top of the board. If it doesn't fit
+    // in there, then the game is over.
+    fn spawn_next_shape(&mut self) {
+        self.rotation = 0;
+        self.piece_inputs = 0;
+        self.last_move_was_rotation = false;
+        self.fall_progress = 0.0;
+
+        // Puzzles only have the pieces in their queue. Once those run out, the
+        // held piece is the only one left, and after that the puzzle has been
+        // failed.
+        self.current_shape = match self.queue.as_mut() {
+            Some(queue) if !queue.is_empty() => Some(queue.remove(0)),
+            Some(_) if self.objective.is_some() => self.held_shape.take(),
+            _ => Some(self.random_shape()),
+        };
+
+        if self.current_shape.is_none() {
+            self.is_running = false;
+            self.is_game_over = true;
+            return;
+        }
+
+        self.pieces_spawned += 1;
+        self.move_to_spawn();
+        self.check_spawn_room();
+
+        // A piece that comes out because of a hold keeps the snapshot from
+        // before the hold, so that undoing goes back to before it.
+        if self.rules.undo && self.spawn_snapshot.is_none() {
+            self.spawn_snapshot = Some(self.snapshot());
+        }
+    }
+
+    // Advances the game by one frame. The input is whatever the player did
+    // during this frame, if anything.
+    pub fn update(&mut self, input: Option<Action>) {
+        if !self.is_running {
+            return;
+        }
+
+        // Nothing can be done while waiting for the next piece, apart from
+        // quitting.
+        match self.phase {
+            Phase::Spawning(_) | Phase::Clearing(_) if input == Some(Action::Quit) => {
+                self.is_running = false
+            }
+            Phase::Spawning(_) | Phase::Clearing(_) => self.wait(),
+            Phase::Active | Phase::Locking(_) => self.update_piece(input),
+        }
+
+        self.frame += 1;
+
+        let frame_rate = self.settings.frame_rate as u64;
+        let frame = self.frame;
+        self.effects.retain(|effect| {
+            ((frame - effect.started_at) as u64) * 1000 < effect.kind.duration_ms() * frame_rate
+        });
+
+        if let Some(popup) = self.popup.as_ref() {
+            if ((frame - popup.started_at) as u64) * 1000 >= POPUP_MS * frame_rate {
+                self.popup = None;
+            }
+        }
+
+        if let Some(time_limit_ms) = self.rules.time_limit_ms {
+            if self.is_running && self.time_ms() >= time_limit_ms {
+                self.is_running = false;
+                self.is_cleared = true;
+            }
+        }
+    }
+
+    // Moves the current piece around, and makes it fall.
+    fn update_piece(&mut self, input: Option<Action>) {
+        // Arcade timing has its own gravity, which happens after the inputs.
+        if !self.rules.arcade {
+            self.fall_progress += self.gravity();
+        }
+
+        // On the higher levels, pieces can fall more than one row per frame.
+        while self.fall_progress >= 1.0 && self.current_shape.is_some() {
+            self.fall_progress -= 1.0;
+
+            // If the piece can't go any lower, then it gets locked where it is.
+            self.player_y += 1;
+
+            if let (true, true) = self.is_shape_in_bounds() {
+                self.last_move_was_rotation = false;
+            } else {
+                self.player_y -= 1;
+                self.fossilize_current_piece();
+            }
+        }
+
+        // If the piece locked and some lines are being cleared, then it's too
+        // late for the input.
+        if matches!(self.phase, Phase::Clearing(_)) {
+            return;
+        }
+
+        if let Some(input) = input {
+            if matches!(
+                input,
+                Action::MoveLeft
+                    | Action::MoveRight
+                    | Action::RotateLeft
+                    | Action::RotateRight
+                    | Action::RotateLeft180
+                    | Action::RotateRight180
+            ) {
+                self.piece_inputs += 1;
+            }
+
+            match input {
+                Action::Quit => self.is_running = false,
+                Action::MoveLeft if self.player_x > 0 => {
+                    self.player_x -= 1;
+                    let (within_bounds, _) = self.is_shape_in_bounds();
+
+                    if within_bounds {
+                        self.last_move_was_rotation = false;
+                    } else {
+                        self.player_x += 1;
+                    }
+                }
+                Action::MoveRight => {
+                    self.player_x += 1;
+                    let (within_bounds, _) = self.is_shape_in_bounds();
+
+                    if within_bounds {
+                        self.last_move_was_rotation = false;
+                    } else {
+                        self.player_x -= 1;
+                    }
+                }
+                Action::RotateLeft => self.rotate_current_shape(-1),
+                Action::RotateRight => self.rotate_current_shape(1),
+                Action::RotateLeft180 => self.rotate_current_shape(-2),
+                Action::RotateRight180 => self.rotate_current_shape(2),
+                Action::Hold if self.can_hold_shape => {
+                    // The held shape goes back to the way it was spawned, so
+                    // that it comes out of the hold box the same way.
+                    if let Some(current_shape) = self.current_shape.as_mut() {
+                        (0..self.rotation).for_each(|_| current_shape.rotate(true));
+                    }
+                    self.rotation = 0;
+
+                    let current_shape = self.current_shape.take();
+                    self.current_shape = self.held_shape.take();
+                    self.previous_shape = current_shape.clone();
+                    self.held_shape = current_shape;
+
+                    self.move_to_spawn();
+                    // The piece that comes out of the hold box needs room too.
+                    if self.current_shape.is_some() {
+                        self.check_spawn_room();
+                    }
+                    self.piece_inputs = 0;
+                    self.last_move_was_rotation = false;
+                    self.phase = Phase::Active;
+
+                    self.can_hold_shape = false;
+                }
+                Action::Undo if self.rules.undo => self.undo(),
+                Action::Redo if self.rules.undo => self.redo(),
+                Action::Export if self.rules.export => {
+                    let fumen = crate::fumen::encode(&[self.fumen_page()]);
+                    self.exports.push(fumen);
+                }
+                Action::HardDrop => {
+                    let start_y = self.player_y;
+                    self.fall_until_hit();
+
+                    // Two points for every row that the piece dropped.
+                    self.score += 2 * self.player_y.saturating_sub(start_y) as u32;
+
+                    if self.settings.animations && self.player_y > start_y {
+                        self.add_trail(start_y);
+                    }
+
+                    self.fossilize_current_piece();
+                }
+                _ => (),
+            }
+        }
+
+        if self.rules.arcade && self.is_running {
+            self.update_lock();
+        }
+
+        // With arcade timing, a piece that just locked is followed by a wait
+        // instead.
+        if self.is_running && self.current_shape.is_none() && self.phase == Phase::Active {
+            self.spawn_next_shape();
+        }
+    }
+
+    // Draws the game onto the screen. Presenting the screen is up to the caller,
+    // since they might want to draw some more stuff on top of it first.
+    pub fn render(&mut self, screen: &mut Screen) {
+        screen.clear();
+        /*screen.fill_area_with_pixel(
+            &Pixel {
+                shape: [crate::unicode::LIGHT_SHADE, ' '],
+                color: screen::Color::Basic(screen::colors::basic::BRIGHT_BLACK), styles: None,
+            },
+            1,
+            1,
+            1 + 10,
+            1 + 20,
+        );*/
+
+        let scale = self.settings.scale();
+        let board_width = self.settings.board_width * scale;
+        let board_height = self.settings.board_height * scale;
+
+        screen
+            .draw_box(
+                0,
+                BOARD_TOP as u16,
+                (board_width + 1) as u16,
+                (board_height + 1) as u16,
+            )
+            .unwrap();
+
+        // Render the ghost piece
+        if let Some(current_shape) = self.current_shape.clone() {
+            let saved_y = self.player_y;
+            self.fall_until_hit();
+            let ghost_y = self.player_y;
+            self.player_y = saved_y;
+
+            self.draw_piece(screen, &current_shape, self.player_x, ghost_y, true);
+        }
+
+        self.render_panel(screen);
+
+        // Render the blocks onto the screen
+        let clear_progress = self.clear_progress();
+        self.blocks.iter().enumerate().for_each(|(i, row)| {
+            let is_clearing = clear_progress.is_some() && row.iter().all(|block| block.is_some());
+
+            row.iter().enumerate().for_each(|(j, block)| {
+                let pixel = match (block, clear_progress) {
+                    (Some(block), Some(progress)) if is_clearing => {
+                        self.clearing_pixel(*block, j, progress)
+                    }
+                    (Some(block), _) => self.block_pixel(*block),
+                    (None, _) => None,
+                };
+
+                if let Some(pixel) = pixel {
+                    self.draw_cell(screen, j as i32, i as i32, &pixel);
+                }
+            });
+        });
+
+        self.render_effects(screen);
+
+        if let Some(current_shape) = self.current_shape.as_ref() {
+            self.draw_piece(screen, current_shape, self.player_x, self.player_y, false);
+        }
+
+        self.render_popup(screen);
+    }
+
+    // Draws the popup over the board, centered on the row where the piece
+    // locked. It drifts up a little and fades out towards the end.
+    fn render_popup(&self, screen: &mut Screen) {
+        use crate::screen::{
+            colors::basic::BRIGHT_BLACK, Align, Color, Style, TextArea, TextOptions,
+        };
+
+        let popup = match self.popup.as_ref() {
+            Some(popup) => popup,
+            None => return,
+        };
+
+        let age_ms =
+            (self.frame - popup.started_at) as u64 * 1000 / self.settings.frame_rate as u64;
+        let progress = age_ms as f32 / POPUP_MS as f32;
+
+        let scale = self.settings.scale();
+        let board_width = self.settings.board_width * scale;
+        let board_top = BOARD_TOP + 1;
+        let board_bottom = BOARD_TOP + self.settings.board_height * scale;
+
+        // Same as in draw_cell(), but the popup has to stay on the board.
+        let row = popup.row as i32 - BUFFER_HEIGHT as i32;
+        let drift = (progress * 3.0) as i32;
+        let y = (BOARD_TOP as i32 + 1 + row * scale as i32 - drift)
+            .clamp(board_top as i32, board_bottom as i32) as u32;
+        let y = y.min((board_bottom + 1).saturating_sub(popup.lines.len() as u32));
+
+        popup
+            .lines
+            .iter()
+            .enumerate()
+            .for_each(|(i, (text, color))| {
+                // Bright colors dim down to their normal versions, and then to grey.
+                let color = if progress < 0.6 {
+                    *color
+                } else if progress < 0.8 && *color >= 90 {
+                    color - 60
+                } else {
+                    BRIGHT_BLACK
+                };
+
+                // The board starts one pixel in, after the border.
+                let area = TextArea {
+                    column: 2,
+                    row: (y + i as u32).max(board_top),
+                    width: board_width * 2,
+                    height: 1,
+                };
+
+                let options = TextOptions {
+                    style: Style {
+                        bold: progress < 0.6,
+                        ..Style::colored(Color::Basic(color))
+                    },
+                    align: Align::Center,
+                    wrap: false,
+                };
+
+                screen.draw_text_in(area, text, &options);
+            });
+    }
+
+    // What a block on the board looks like right now, if it can be seen at all.
+    // Fading blocks stay solid for the first half of the fade, and are shaded
+    // for the second half.
+    fn block_pixel(&self, block: Block) -> Option<Pixel> {
+        use crate::screen::Color;
+        use crate::unicode::LIGHT_SHADE;
+
+        let is_revealed = self.is_game_over || self.frame < self.reveal_until;
+        let age_ms = self.frame.saturating_sub(block.locked_at) as u64 * 1000
+            / self.settings.frame_rate as u64;
+
+        let shape = match self.rules.visibility {
+            _ if is_revealed => block.glyphs,
+            Visibility::Visible => block.glyphs,
+            Visibility::Fading if age_ms < FADE_MS / 2 => block.glyphs,
+            Visibility::Fading if age_ms < FADE_MS => [LIGHT_SHADE, LIGHT_SHADE],
+            Visibility::Fading | Visibility::Invisible => return None,
+        };
+
+        Some(Pixel {
+            shape,
+            color: Color::Basic(block.color),
+            styles: None,
+        })
+    }
+
+    // What a block in a row that's about to be cleared looks like. The row
+    // flashes for the first half of the line clear, and then crumbles away from
+    // the middle out.
+    fn clearing_pixel(&self, block: Block, column: usize, progress: f32) -> Option<Pixel> {
+        use crate::screen::{colors::basic::BRIGHT_WHITE, Color};
+        use crate::unicode::LIGHT_SHADE;
+
+        if progress < 0.5 {
+            let is_lit = (self.frame * 8 / self.settings.frame_rate as u32).is_multiple_of(2);
+
+            return Some(Pixel {
+                shape: block.glyphs,
+                color: Color::Basic(if is_lit { BRIGHT_WHITE } else { block.color }),
+                styles: None,
+            });
+        }
+
+        let middle = (self.settings.board_width as f32 - 1.0) / 2.0;
+        let reach = (progress - 0.5) * 2.0 * (middle + 1.0);
+
+        if (column as f32 - middle).abs() < reach {
+            return None;
+        }
+
+        Some(Pixel {
+            shape: [LIGHT_SHADE, LIGHT_SHADE],
+            color: Color::Basic(block.color),
+            styles: None,
+        })
+    }
+
+    fn render_effects(&self, screen: &mut Screen) {
+        use crate::screen::{colors::basic::BRIGHT_WHITE, Color};
+        use crate::unicode::LIGHT_SHADE;
+
+        for effect in self.effects.iter() {
+            let age_ms =
+                (self.frame - effect.started_at) as u64 * 1000 / self.settings.frame_rate as u64;
+            let progress = age_ms as f32 / effect.kind.duration_ms() as f32;
+
+            match effect.kind {
+                EffectKind::LockFlash => {
+                    for &(column, row) in effect.cells.iter() {
+                        if let Some(Some(block)) =
+                            self.blocks.get(row).and_then(|row| row.get(column))
+                        {
+                            let pixel = Pixel {
+                                shape: block.glyphs,
+                                color: Color::Basic(BRIGHT_WHITE),
+                                styles: None,
+                            };
+
+                            self.draw_cell(screen, column as i32, row as i32, &pixel);
+                        }
+                    }
+                }
+                EffectKind::Trail => {
+                    // The top of the trail goes first.
+                    let top = effect.cells.iter().map(|(_, row)| *row).min().unwrap_or(0);
+                    let bottom = effect.cells.iter().map(|(_, row)| *row).max().unwrap_or(0);
+                    let cutoff = top as f32 + progress * (bottom - top + 1) as f32;
+
+                    let pixel = Pixel {
+                        shape: [LIGHT_SHADE, LIGHT_SHADE],
+                        color: Color::Basic(effect.color),
+                        styles: None,
+                    };
+
+                    for &(column, row) in effect.cells.iter() {
+                        let is_empty = self
+                            .blocks
+                            .get(row)
+                            .and_then(|row| row.get(column))
+                            .is_some_and(|block| block.is_none());
+
+                        if row as f32 >= cutoff && is_empty {
+                            self.draw_cell(screen, column as i32, row as i32, &pixel);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The two lines at the top of the side panel, which say how the game is
+    // going.
+    fn status_lines(&self) -> (String, String) {
+        match (self.rules.ranking, self.rules.time_limit_ms) {
+            // The level is shown along with where the current section ends.
+            _ if self.rules.arcade => {
+                let section_end = ((self.arcade_level / 100 + 1) * 100).min(ARCADE_LAST_LEVEL);
+
+                (
+                    format!(
+                        "GRADE {:<3} SCORE {}",
+                        self.grade().unwrap_or_default(),
+                        self.score
+                    ),
+                    format!(
+                        "LEVEL {:03}/{:03} {}",
+                        self.arcade_level,
+                        section_end,
+                        format_time_ms(self.time_ms())
+                    ),
+                )
+            }
+            (Ranking::Time, _) => {
+                let goal = self.rules.line_goal.unwrap_or(0);
+
+                let progress = if self.rules.garbage {
+                    format!("GARBAGE {}", self.garbage_remaining())
+                } else {
+                    format!("LINES {}/{}", self.lines_cleared.min(goal), goal)
+                };
+
+                (
+                    format!("TIME  {}", format_time_ms(self.time_ms())),
+                    progress,
+                )
+            }
+            (Ranking::Score, Some(time_limit_ms)) => {
+                let time_left_ms = time_limit_ms.saturating_sub(self.time_ms());
+
+                (
+                    format!("SCORE {}", self.score),
+                    format!("TIME  {}", format_time_ms(time_left_ms)),
+                )
+            }
+            // Puzzles show what has to be done, and the pieces that are left.
+            _ if self.objective.is_some() => {
+                let description = self
+                    .objective
+                    .map(|objective| objective.description())
+                    .unwrap_or_default();
+
+                let queue: String = self
+                    .queue
+                    .iter()
+                    .flatten()
+                    .filter_map(crate::pieces::letter)
+                    .collect();
+
+                (description, format!("NEXT  {}", queue))
+            }
+            // The level doesn't do anything in practice modes.
+            (Ranking::Unranked, _) => (
+                format!("SCORE {}", self.score),
+                format!("LINES {}", self.lines_cleared),
+            ),
+            (Ranking::Score, None) => {
+                let lines = match self.rules.line_goal {
+                    Some(goal) if !self.is_endless => format!("{}/{}", self.lines_cleared, goal),
+                    _ => format!("{}", self.lines_cleared),
+                };
+
+                (
+                    format!("SCORE {}", self.score),
+                    format!("LEVEL {}  LINES {}", self.level(), lines),
+                )
+            }
+        }
+    }
+
+    // Where everything in the side panel goes. The replay viewer puts its own
+    // stuff where the controls usually are.
+    pub fn panel_areas(&self, screen: &Screen) -> PanelAreas {
+        use crate::screen::{Constraint::*, Direction};
+
+        let panel_x = self.panel_x();
+        let panel = Rect::new(
+            panel_x,
+            1,
+            screen.width().saturating_sub(panel_x),
+            screen.height().saturating_sub(1),
+        );
+
+        let areas = panel.split(
+            Direction::Vertical,
+            &[Length(2), Length(1), Length(9), Length(8), Fill],
+        );
+
+        PanelAreas {
+            status: areas[0],
+            progress: areas[1],
+            controls: areas[2],
+            hold: areas[3],
+            below: areas[4],
+        }
+    }
+
+    fn render_panel(&self, screen: &mut Screen) {
+        use crate::screen::{
+            colors::basic::GREEN, Blank, Color, Label, Panel, ProgressBar, Split, Widget,
+        };
+
+        let areas = self.panel_areas(screen);
+        let (first_line, second_line) = self.status_lines();
+
+        Label::new(format!("{}\n{}", first_line, second_line)).draw(screen, areas.status);
+
+        // Puzzles and the modes that go on forever don't have anywhere to get
+        // to.
+        if let (done, Some(goal)) = self.progress() {
+            if goal > 0 && self.objective.is_none() && !self.rules.garbage {
+                let bar = ProgressBar {
+                    progress: done as f32 / goal as f32,
+                    color: Color::Basic(GREEN),
+                };
+
+                // The bar is only as wide as the hold box.
+                let area = Rect {
+                    width: HOLD_BOX_WIDTH,
+                    ..areas.progress
+                };
+                bar.draw(screen, area);
+            }
+        }
+
+        let hold_box = Panel {
+            title: Some(String::from("HOLD")),
+            content: Box::new(|screen: &mut Screen, area: Rect| {
+                if let Some(held_shape) = self.held_shape.as_ref() {
+                    screen.draw_shape(held_shape, area.x as u16 + 3, area.y as u16 + 3, false);
+                }
+            }),
+        };
+
+        Split::horizontal(vec![
+            (Constraint::Length(HOLD_BOX_WIDTH), Box::new(hold_box)),
+            (Constraint::Fill, Box::new(Blank)),
+        ])
+        .draw(screen, areas.hold);
 
-mod shapes {
-    use crate::{
-        screen::{colors::basic::*, Color},
-        tetris::{Pixel, Shape},
-        unicode::FULL_BLOCK,
-    };
+        self.render_splits(screen, areas.below.y);
+    }
 
-    pub static SQUARE: Shape = Shape {
-        pixels: [(0, 0), (1, 0), (1, 1), (0, 1)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(BRIGHT_YELLOW),
-        },
-    };
+    // The split times, under the hold box. Each one is compared to the personal
+    // best: green if it's faster, red if it's slower. The splits that haven't
+    // been reached yet show the personal best instead.
+    fn render_splits(&self, screen: &mut Screen, y: u32) {
+        use crate::screen::{colors::basic::*, Align, Color};
 
-    pub static STRAIGHT: Shape = Shape {
-        pixels: [(0, -1), (0, 0), (0, 1), (0, 2)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(CYAN),
-        },
-    };
+        let interval = match self.rules.split_interval {
+            Some(interval) => interval,
+            None => return,
+        };
 
-    pub static TEE: Shape = Shape {
-        pixels: [(0, -1), (0, 0), (-1, 0), (1, 0)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(MAGENTA),
-        },
-    };
+        let goal = self.progress().1.unwrap_or(0);
+        let split_count = goal.div_ceil(interval) as usize;
+        let width = goal.to_string().len();
+        let panel_x = self.panel_x();
+
+        // If there are more splits than there is room for, the ones around the
+        // current split are shown.
+        let visible_count = (screen.height().saturating_sub(y) as usize).min(split_count);
+        let first = (self.splits.len() + 1)
+            .saturating_sub(visible_count)
+            .min(split_count - visible_count);
+
+        for i in first..first + visible_count {
+            let row = y + (i - first) as u32;
+            // Lines or levels, depending on the mode.
+            let mark = ((i as u32 + 1) * interval).min(goal);
+            let personal_best = self.personal_best_splits.get(i);
+
+            match (self.splits.get(i), personal_best) {
+                (Some(time), personal_best) => {
+                    screen.draw_text(
+                        panel_x,
+                        row,
+                        &format!("{:>width$} {}", mark, format_time_ms(*time)),
+                    );
+
+                    // The differences line up on the right of the panel.
+                    if let Some(personal_best) = personal_best {
+                        let difference = *time as i64 - *personal_best as i64;
+                        let color = if difference <= 0 { GREEN } else { RED };
+
+                        screen.draw_aligned_text(
+                            panel_x,
+                            row,
+                            SIDE_PANEL_WIDTH - 2,
+                            &format_time_difference(difference),
+                            Color::Basic(color),
+                            Align::Right,
+                        );
+                    }
+                }
+                (None, Some(personal_best)) => screen.draw_colored_text(
+                    panel_x,
+                    row,
+                    &format!("{:>width$} {}", mark, format_time_ms(*personal_best)),
+                    Color::Basic(BRIGHT_BLACK),
+                ),
+                (None, None) => {
+                    screen.draw_text(panel_x, row, &format!("{:>width$} -:--.---", mark))
+                }
+            }
+        }
+    }
 
-    pub static LEFT_SKEWED: Shape = Shape {
-        pixels: [(-1, 0), (0, 0), (0, -1), (1, -1)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(GREEN),
-        },
-    };
+    // Where the side panel starts, which is right next to the board.
+    pub fn panel_x(&self) -> u32 {
+        self.settings.board_width * self.settings.scale() + 2
+    }
 
-    pub static RIGHT_SKEWED: Shape = Shape {
-        pixels: [(-1, -1), (0, 0), (0, -1), (1, 0)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(RED),
-        },
-    };
+    // Draws a piece onto the board, where x is the column (starting from 1, like
+    // player_x) and y is the row of the board.
+    fn draw_piece(&self, screen: &mut Screen, shape: &Shape, x: u16, y: u16, ghost: bool) {
+        let mut pixel = shape.fill_pixel.clone();
 
-    pub static LEFT_L: Shape = Shape {
-        pixels: [(-1, 0), (0, 0), (0, -1), (0, -2)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(BLUE),
-        },
-    };
+        if ghost {
+            pixel.shape = [crate::unicode::LIGHT_SHADE, crate::unicode::LIGHT_SHADE];
+        }
 
-    pub static RIGHT_L: Shape = Shape {
-        pixels: [(1, 0), (0, 0), (0, -1), (0, -2)],
-        fill_pixel: Pixel {
-            shape: [FULL_BLOCK, FULL_BLOCK],
-            color: Color::Basic(YELLOW),
-        },
-    };
-}
+        shape.pixels.iter().for_each(|(pixel_x, pixel_y)| {
+            let column = x as i32 + *pixel_x as i32 - 1;
+            let row = y as i32 + *pixel_y as i32;
 
-static SHAPES: [&Shape; 7] = [
-    &shapes::SQUARE,
-    &shapes::STRAIGHT,
-    &shapes::TEE,
-    &shapes::LEFT_SKEWED,
-    &shapes::RIGHT_SKEWED,
-    &shapes::LEFT_L,
-    &shapes::RIGHT_L,
-];
+            self.draw_cell(screen, column, row, &pixel);
+        });
+    }
 
-// A Pseudorandom number generator, used to decide what piece to use next.
-struct RandomGenerator {
-    modulus: u64,
-    multiplier: u64,
-    increment: u64,
-    seed: u64,
-}
+    // Draws one cell of the board, which takes up 2x2 pixels in big mode. The
+    // rows of the buffer go above the top border, and get cut off wherever the
+    // screen runs out.
+    fn draw_cell(&self, screen: &mut Screen, column: i32, row: i32, pixel: &Pixel) {
+        let scale = self.settings.scale() as i32;
+        let row = row - BUFFER_HEIGHT as i32;
 
-impl RandomGenerator {
-    fn new(modulus: u64, multiplier: u64, increment: u64) -> RandomGenerator {
-        use std::time::{SystemTime, UNIX_EPOCH};
+        // The border is in between the board and the buffer.
+        let top = BOARD_TOP as i32 + row * scale + if row < 0 { 0 } else { 1 };
+        let left = column * scale + 1;
 
-        let now = SystemTime::now();
-        let seed = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        for dy in 0..scale {
+            for dx in 0..scale {
+                let (x, y) = (left + dx, top + dy);
 
-        RandomGenerator {
-            modulus,
-            multiplier,
-            increment,
-            seed,
+                if x >= 0 && y >= 0 && (x as u32) < screen.width() && (y as u32) < screen.height() {
+                    screen[x as u32][y as usize] = pixel.clone();
+                }
+            }
         }
     }
 
-    fn generate(&mut self) -> u64 {
-        let result = (self.multiplier * self.seed + self.increment) % self.modulus;
-        self.seed = result;
-        result
-    }
-}
+    // The list of controls in the side panel. This is kept separate from render()
+    // because the replay viewer uses that space for its own stuff.
+    pub fn render_controls(&self, screen: &mut Screen, controls: &Controls) {
+        use crate::screen::{Label, List, Split, Widget};
 
-pub struct Tetris {
-    screen: Screen,
-    is_running: bool,
+        let areas = self.panel_areas(screen);
 
-    random_generator: RandomGenerator,
+        let lines: Vec<String> = Action::ALL
+            .iter()
+            .filter(|action| {
+                !matches!(
+                    action,
+                    Action::Undo | Action::Redo | Action::Export | Action::Quit
+                )
+            })
+            .map(|action| format!("{} => {}", controls.key_name(*action), action.label()))
+            .collect();
+
+        Split::vertical(vec![
+            (Constraint::Length(1), Box::new(Label::new("CONTROLS"))),
+            (Constraint::Fill, Box::new(List::new(lines))),
+        ])
+        .draw(screen, areas.controls);
+
+        // Undoing gets its own spot below the hold box, along with how many
+        // placements can be taken back. That's where the splits go in other
+        // modes.
+        let mut extra_lines = Vec::new();
+
+        if self.rules.undo {
+            extra_lines.push(format!(
+                "{} => Undo ({})",
+                controls.key_name(Action::Undo),
+                self.undo_history.len()
+            ));
+            extra_lines.push(format!(
+                "{} => Redo ({})",
+                controls.key_name(Action::Redo),
+                self.redo_history.len()
+            ));
+        }
 
-    // This value is incremented every frame, and when it reaches the value of the framerate
-    // , it will be resetted back to zero and the playing piece will fall one unit down.
-    fall_timer: u16,
-    // The rate at which the fall timer will be decremented per tick.
-    fall_speed: f32,
+        if self.rules.export {
+            extra_lines.push(format!(
+                "{} => Export ({})",
+                controls.key_name(Action::Export),
+                self.exports.len()
+            ));
+        }
 
-    player_x: u16,
-    player_y: u16,
+        List::new(extra_lines).draw(screen, areas.below);
+    }
+}
 
-    score: u32,
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    blocks: Vec<[Option<u8>; GAME_WIDTH as usize]>,
+    // A small xorshift generator for the property tests below. The seeds are
+    // fixed, so a failure always comes back the same way.
+    struct Generator(u64);
 
-    previous_shape: Option<Shape>,
-    current_shape: Option<Shape>,
-    held_shape: Option<Shape>,
+    impl Generator {
+        fn new(seed: u64) -> Generator {
+            // Xorshift gets stuck on zero.
+            Generator(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
 
-    can_hold_shape: bool,
-}
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
 
-impl Tetris {
-    pub fn new() -> Result<Tetris, TryFromIntError> {
-        Ok(Tetris {
-            screen: Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT)?,
-            is_running: true,
+        // A number from 0 up to (but not including) the limit.
+        fn below(&mut self, limit: u64) -> u64 {
+            self.next() % limit
+        }
 
-            random_generator: RandomGenerator::new(101, 4, 1),
+        fn chance(&mut self, percent: u64) -> bool {
+            self.below(100) < percent
+        }
 
-            fall_timer: 0,
-            fall_speed: 1.0,
+        fn tetromino(&mut self) -> Shape {
+            let letters = ['I', 'O', 'T', 'S', 'Z', 'J', 'L'];
+            let mut shape =
+                crate::pieces::tetromino(letters[self.below(7) as usize]).expect("Bad letter");
 
-            player_x: PLAYER_STARTING_X,
-            player_y: PLAYER_STARTING_Y,
+            (0..self.below(4)).for_each(|_| shape.rotate(false));
+            shape
+        }
 
-            score: 0,
+        // Any old shape, like a custom piece set could have.
+        fn shape(&mut self) -> Shape {
+            let mut shape = self.tetromino();
+            shape.pixels = (0..1 + self.below(9))
+                .map(|_| (self.below(7) as i16 - 3, self.below(7) as i16 - 3))
+                .collect();
+            shape
+        }
+    }
 
-            blocks: vec![[None; GAME_WIDTH as usize]; GAME_HEIGHT as usize],
+    const CASES: u64 = 500;
 
-            previous_shape: None,
-            current_shape: None, // TODO: Select random shape
-            held_shape: None,
-            can_hold_shape: true,
+    fn block(locked_at: u32) -> Option<Block> {
+        Some(Block {
+            color: 31,
+            glyphs: [crate::unicode::FULL_BLOCK, crate::unicode::FULL_BLOCK],
+            locked_at,
         })
     }
 
-    pub fn is_running(&self) -> bool {
-        self.is_running
+    fn settings() -> Settings {
+        Settings {
+            line_clear_delay: 0,
+            ..Settings::default()
+        }
     }
 
-    // Checks if the current shape is within the bounds of the game.
-    fn is_shape_in_bounds(&self) -> (bool, bool) {
-        if let Some(current_shape) = self.current_shape.as_ref() {
-            let mut within_x_bounds = true;
-            let mut within_y_bounds = true;
+    fn empty_game() -> Tetris {
+        let mut game = Tetris::new(1, GameMode::Marathon, &settings());
+        game.current_shape = None;
+        game
+    }
 
-            current_shape.pixels.iter().for_each(|(block_x, block_y)| {
-                let block_x: i16 =
-                    block_x + <u16 as TryInto<i16>>::try_into(self.player_x).unwrap();
-                let block_y: i16 =
-                    block_y + <u16 as TryInto<i16>>::try_into(self.player_y).unwrap();
+    // Fills in random cells of the board up to some height, leaving the
+    // buffer alone so that there's always room for a piece at the top.
+    fn random_board(game: &mut Tetris, generator: &mut Generator) {
+        let top = game.blocks.len() - generator.below(GAME_HEIGHT as u64) as usize;
 
-                if block_y >= GAME_HEIGHT as i16 || block_y <= 0 {
-                    within_y_bounds = false;
-                    return;
+        for row in game.blocks[top..].iter_mut() {
+            for cell in row.iter_mut() {
+                if generator.chance(60) {
+                    *cell = block(0);
                 }
+            }
+        }
+    }
 
-                if block_x > GAME_WIDTH as i16 || block_x <= 0 {
-                    within_x_bounds = false;
-                    return;
-                }
+    // The cells of the board that the current piece is on.
+    fn current_cells(game: &Tetris) -> Vec<(usize, usize)> {
+        game.piece_cells(game.current_shape.as_ref().unwrap())
+    }
 
-                // Check that it is not colliding with fossilized blocks.
+    #[test]
+    fn four_rotations_are_the_identity() {
+        let mut generator = Generator::new(1);
 
-                if let Some(_) = self.blocks[<i16 as TryInto<usize>>::try_into(block_y).unwrap()]
-                    [<i16 as TryInto<usize>>::try_into(block_x - 1).unwrap()]
-                {
-                    within_x_bounds = false;
-                    within_y_bounds = false;
-                }
-            });
+        for _ in 0..CASES {
+            let original = generator.shape();
 
-            (within_x_bounds, within_y_bounds)
-        } else {
-            (false, false)
+            for rotate_left in [false, true] {
+                let mut shape = original.clone();
+                (0..4).for_each(|_| shape.rotate(rotate_left));
+                assert_eq!(shape, original);
+            }
+
+            let mut shape = original.clone();
+            shape.rotate(false);
+            shape.rotate(true);
+            assert_eq!(shape, original);
         }
     }
 
-    fn fossilize_current_piece(&mut self) {
-        if let Some(shape) = self.current_shape.as_ref() {
-            shape.pixels.iter().for_each(|(component_x, component_y)| {
-                self.blocks[<i16 as TryInto<usize>>::try_into(
-                    *component_y + <u16 as TryInto<i16>>::try_into(self.player_y).unwrap(),
-                )
-                .unwrap()][<i16 as TryInto<usize>>::try_into(
-                    *component_x + <u16 as TryInto<i16>>::try_into(self.player_x - 1).unwrap(),
-                )
-                .unwrap()] = if let crate::screen::Color::Basic(color) = shape.fill_pixel.color {
-                    Some(color)
-                } else {
-                    None
-                };
-            });
+    #[test]
+    fn shape_bounds_cover_the_whole_board() {
+        let shape = Shape {
+            pixels: vec![(0, 0)],
+            fill_pixel: Pixel::default(),
+        };
 
-            self.previous_shape = self.current_shape.take();
+        // Columns start from 1, while rows start from 0.
+        assert_eq!(shape.is_within_bounds(1, 0, 10, 40), (true, true));
+        assert_eq!(shape.is_within_bounds(10, 39, 10, 40), (true, true));
+        assert_eq!(shape.is_within_bounds(0, 0, 10, 40), (false, true));
+        assert_eq!(shape.is_within_bounds(11, 0, 10, 40), (false, true));
+        assert_eq!(shape.is_within_bounds(1, 40, 10, 40), (true, false));
+    }
 
-            let mut rows_cleared = 0;
+    // On an empty board the two bounds checks should always give the same
+    // answer.
+    #[test]
+    fn bounds_checks_agree_on_an_empty_board() {
+        let mut generator = Generator::new(2);
+        let mut game = empty_game();
+        let width = game.settings.board_width;
+        let height = game.settings.total_height();
 
-            // Now, iterate through the rows and clear the ones that are full.
-            let mut i = 0;
-            while i < self.blocks.len() {
-                let is_row_not_full =
-                    self.blocks[i]
-                        .iter()
-                        .find(|block| if let None = block { true } else { false });
+        for _ in 0..CASES {
+            let shape = generator.shape();
+            game.player_x = generator.below(width as u64 + 4) as u16;
+            game.player_y = 3 + generator.below(height as u64) as u16;
 
-                if let None = is_row_not_full {
-                    self.blocks.remove(i);
-                    self.blocks.insert(0, [None; 10]);
-                    rows_cleared += 1;
-                    continue;
-                }
+            let expected = shape.is_within_bounds(game.player_x, game.player_y, width, height);
+            game.current_shape = Some(shape);
 
-                i += 1;
-            }
+            assert_eq!(game.is_shape_in_bounds(), expected);
+        }
+    }
+
+    // Only the game's check looks at the blocks that are already on the board,
+    // which is why rotating has to use both of them.
+    #[test]
+    fn only_the_game_bounds_check_looks_at_blocks() {
+        let mut game = empty_game();
+        let shape = Shape {
+            pixels: vec![(0, 0)],
+            fill_pixel: Pixel::default(),
+        };
+        game.blocks[30][4] = block(0);
+        game.player_x = 5;
+        game.player_y = 30;
 
-            self.score += rows_cleared * 100;
+        let width = game.settings.board_width;
+        let height = game.settings.total_height();
+        assert_eq!(shape.is_within_bounds(5, 30, width, height), (true, true));
 
-            if rows_cleared > 0 {
-                self.score += (rows_cleared - 1) * 25
+        game.current_shape = Some(shape);
+        assert_eq!(game.is_shape_in_bounds(), (false, false));
+    }
+
+    #[test]
+    fn pieces_fall_until_they_land() {
+        let mut generator = Generator::new(3);
+
+        for _ in 0..CASES {
+            let mut game = empty_game();
+            random_board(&mut game, &mut generator);
+
+            game.current_shape = Some(generator.tetromino());
+            game.player_x = 1 + generator.below(game.settings.board_width as u64) as u16;
+            game.player_y = 3;
+            if game.is_shape_in_bounds() != (true, true) {
+                continue;
             }
 
-            self.fall_speed += 0.1 * rows_cleared as f32;
-        }
+            game.fall_until_hit();
+            assert_eq!(game.is_shape_in_bounds(), (true, true));
 
-        self.can_hold_shape = true;
+            game.player_y += 1;
+            assert!(!game.is_shape_in_bounds().1);
+        }
     }
 
-    fn fall_until_hit(&mut self) {
-        loop {
-            let (_, not_at_bottom) = self.is_shape_in_bounds();
-            if not_at_bottom {
-                self.player_y += 1;
-            } else {
-                self.player_y -= 1;
-                break;
+    #[test]
+    fn line_clears_keep_the_other_rows_in_order() {
+        let mut generator = Generator::new(4);
+
+        for _ in 0..CASES {
+            let mut game = empty_game();
+            let width = game.settings.board_width as usize;
+
+            // Every block remembers which row it started in.
+            for (i, row) in game.blocks.iter_mut().enumerate() {
+                let is_full = generator.chance(30);
+                for cell in row.iter_mut() {
+                    if is_full || generator.chance(50) {
+                        *cell = block(i as u32);
+                    }
+                }
+
+                if !is_full && row.iter().all(|cell| cell.is_some()) {
+                    row[generator.below(width as u64) as usize] = None;
+                }
             }
+
+            let kept: Vec<Vec<Option<Block>>> = game
+                .blocks
+                .iter()
+                .filter(|row| row.iter().any(|cell| cell.is_none()))
+                .cloned()
+                .collect();
+            let full_rows = game.full_rows();
+
+            assert_eq!(game.clear_full_rows(), full_rows);
+            assert_eq!(game.blocks.len(), game.settings.total_height() as usize);
+
+            let (empty, rest) = game.blocks.split_at(full_rows as usize);
+            assert!(empty.iter().flatten().all(|cell| cell.is_none()));
+            assert_eq!(rest, &kept[..]);
         }
     }
 
-    pub fn update(&mut self) {
-        if self.fall_timer >= <u8 as Into<u16>>::into(crate::FRAME_RATE) / 2 {
-            self.fall_timer = 0;
+    // Builds the board of the "four at once" puzzle, with a row on top that
+    // should be left alone, and drops an I piece into the gap.
+    fn four_at_once(settings: &Settings) -> Tetris {
+        let mut game = Tetris::new(1, GameMode::Marathon, settings);
+        let bottom = game.blocks.len();
 
-            // Only fall if we are not at the bottom.
-            let (_, not_at_bottom) = self.is_shape_in_bounds();
-            if not_at_bottom {
-                self.player_y += 1;
-            } else {
-                self.player_y -= 1;
-                self.fossilize_current_piece();
-            }
+        for row in game.blocks[bottom - 4..].iter_mut() {
+            row.iter_mut().take(9).for_each(|cell| *cell = block(0));
         }
+        game.blocks[bottom - 5][0] = block(1);
+
+        // The I piece starts out standing up.
+        game.current_shape = crate::pieces::tetromino('I');
+
+        // Wherever the vertical I ends up in the last column.
+        game.player_x = (1..=game.settings.board_width as u16)
+            .find(|x| {
+                game.player_x = *x;
+                game.player_y = 3;
+                current_cells(&game).iter().all(|(column, _)| *column == 9)
+            })
+            .unwrap();
+        game.fall_until_hit();
 
-        self.fall_timer += self.fall_speed as u16;
+        game
+    }
 
-        if let Ok(input) = self.screen.read_input() {
-            match input {
-                'q' => self.is_running = false,
-                'a' => {
-                    if self.player_x > 0 {
-                        self.player_x -= 1;
-                        let (within_bounds, _) = self.is_shape_in_bounds();
+    #[test]
+    fn fossilizing_clears_full_rows() {
+        let mut game = four_at_once(&settings());
+        let bottom = game.blocks.len();
+
+        game.fossilize_current_piece();
+
+        assert_eq!(game.lines_cleared, 4);
+        assert!(game.score > 0);
+        assert!(game.current_shape.is_none());
+        assert_eq!(game.blocks[bottom - 1][0], block(1));
+        assert_eq!(
+            game.blocks
+                .iter()
+                .flatten()
+                .filter(|cell| cell.is_some())
+                .count(),
+            1
+        );
+    }
 
-                        if !within_bounds {
-                            self.player_x += 1;
-                        }
-                    }
-                }
-                'd' => {
-                    self.player_x += 1;
-                    let (within_bounds, _) = self.is_shape_in_bounds();
+    // With a line clear delay the rows stay on the board for a while first.
+    #[test]
+    fn full_rows_wait_for_the_line_clear_delay() {
+        let mut game = four_at_once(&Settings::default());
 
-                    if !within_bounds {
-                        self.player_x -= 1;
-                    }
-                }
-                's' => {
-                    if let Some(current_shape) = self.current_shape.as_mut() {
-                        current_shape.rotate(true);
-
-                        // This is to prevent rotating the shape out of bounds.
-                        let (within_x_bounds, within_y_bounds) =
-                            current_shape.is_within_bounds(self.player_x, self.player_y);
-                        if !within_x_bounds || !within_y_bounds {
-                            // Undo the rotation if it results in the shape going out of bounds.
-                            current_shape.rotate(false);
-                        }
-                    }
-                }
-                'w' => {
-                    if let Some(current_shape) = self.current_shape.as_mut() {
-                        current_shape.rotate(false);
-
-                        // This is to prevent rotating the shape out of bounds.
-                        let (within_x_bounds, within_y_bounds) =
-                            current_shape.is_within_bounds(self.player_x, self.player_y);
-                        if !within_x_bounds || !within_y_bounds {
-                            // Undo the rotation if it results in the shape going out of bounds.
-                            current_shape.rotate(true);
-                        }
-                    }
-                }
-                'z' => {
-                    if let Some(current_shape) = self.current_shape.as_mut() {
-                        current_shape.rotate(true);
-                        current_shape.rotate(true);
-
-                        // false -> right
-                        // true -> left
-
-                        // This is to prevent rotating the shape out of bounds.
-                        let (within_x_bounds, within_y_bounds) =
-                            current_shape.is_within_bounds(self.player_x, self.player_y);
-                        if !within_x_bounds || !within_y_bounds {
-                            // Undo the rotation if it results in the shape going out of bounds.
-                            current_shape.rotate(false);
-                        }
-                    }
+        game.fossilize_current_piece();
+        assert_eq!(game.full_rows(), 4);
+        assert!(matches!(game.phase, Phase::Clearing(_)));
 
-                    // Checks are not needed here, as it is impossible to flip out of bounds.
-                }
-                'x' => {
-                    if let Some(current_shape) = self.current_shape.as_mut() {
-                        current_shape.rotate(false);
-                        current_shape.rotate(false);
-
-                        // This is to prevent rotating the shape out of bounds.
-                        let (within_x_bounds, within_y_bounds) =
-                            current_shape.is_within_bounds(self.player_x, self.player_y);
-                        if !within_x_bounds || !within_y_bounds {
-                            // Undo the rotation if it results in the shape going out of bounds.
-                            current_shape.rotate(true);
-                        }
-                    }
+        for _ in 0..game.line_clear_frames() {
+            game.update(None);
+        }
 
-                    // Checks are not needed here, as it is impossible to flip out of bounds.
-                }
-                'h' => {
-                    if self.can_hold_shape {
-                        let current_shape = self.current_shape.take();
-                        self.current_shape = self.held_shape.take();
-                        self.previous_shape = current_shape.clone();
-                        self.held_shape = current_shape;
+        assert_eq!(game.full_rows(), 0);
+        assert_eq!(game.lines_cleared, 4);
+        assert_eq!(game.phase, Phase::Active);
+    }
 
-                        self.player_x = PLAYER_STARTING_X;
-                        self.player_y = PLAYER_STARTING_Y;
+    // 67 is the one number that the piece generator gives straight back, so
+    // it must never be the state, whatever the seed.
+    #[test]
+    fn the_piece_generator_never_gets_stuck() {
+        for seed in (0..1000).chain([67, 168, u64::MAX]) {
+            let mut generator = RandomGenerator::new(SEED_MODULUS, 4, 1, seed);
+            let numbers: Vec<u64> = (0..20).map(|_| generator.generate()).collect();
 
-                        self.can_hold_shape = false;
-                    }
-                }
-                ' ' => {
-                    self.fall_until_hit();
-                    self.fossilize_current_piece();
-                }
-                _ => (),
-            }
+            assert!(numbers.windows(2).all(|pair| pair[0] != pair[1]));
+            assert!(numbers.iter().any(|number| number % 7 != numbers[0] % 7));
         }
     }
 
-    pub fn render(&mut self) {
-        self.screen.clear();
-        /*self.screen.fill_area_with_pixel(
-            &Pixel {
-                shape: [crate::unicode::LIGHT_SHADE, ' '],
-                color: screen::Color::Basic(screen::colors::basic::BRIGHT_BLACK),
-            },
-            1,
-            1,
-            1 + 10,
-            1 + 20,
-        );*/
+    #[test]
+    fn a_game_from_seed_67_gets_different_pieces() {
+        let mut game = Tetris::new(67, GameMode::Marathon, &settings());
+        let mut pieces = vec![game.current_shape.clone().unwrap()];
+
+        for _ in 0..6 {
+            game.update(Some(Action::HardDrop));
+            pieces.push(game.current_shape.clone().unwrap());
+        }
 
-        let saved_y = self.player_y;
+        assert!(pieces.iter().any(|piece| *piece != pieces[0]));
+    }
 
-        // Render the ghost piece
-        self.fall_until_hit();
-        if let Some(current_shape) = self.current_shape.as_ref() {
-            self.screen.draw_shape(
-                current_shape,
-                self.player_x,
-                // The y has to be offset because there are borders surroun-
-                // ding the screen, which will push everything down one uni-
-                // t.
-                self.player_y + 1,
-                true,
-            );
+    #[test]
+    fn every_mode_has_a_key_of_its_own() {
+        for mode in GameMode::ALL.iter().chain([GameMode::Puzzle].iter()) {
+            assert_eq!(GameMode::from_key(mode.key()), Some(*mode));
         }
 
-        self.player_y = saved_y;
+        assert_eq!(GameMode::from_key("tetris"), None);
+    }
 
-        self.screen
-            .draw_box(0, 0, (GAME_WIDTH + 1) as u16, (GAME_HEIGHT + 1) as u16)
-            .unwrap();
+    // The pieces of a fumen come out of a queue, which undoing has to put back
+    // the way it was.
+    #[test]
+    fn undoing_puts_fumen_pieces_back_in_the_queue() {
+        use crate::fumen::{Page, Placement, Rotation, FIELD_HEIGHT, FIELD_WIDTH};
+
+        let pages: Vec<Page> = ['T', 'O', 'L']
+            .iter()
+            .map(|letter| Page {
+                piece: Some(Placement {
+                    letter: *letter,
+                    rotation: Rotation::Spawn,
+                    x: 4,
+                    y: 0,
+                }),
+                ..Page::new(vec![vec![None; FIELD_WIDTH]; FIELD_HEIGHT])
+            })
+            .collect();
+
+        let mut game = Tetris::from_fumen(&pages, 1, &settings());
+        let letter = |game: &Tetris| crate::pieces::letter(game.current_shape.as_ref().unwrap());
+        assert_eq!(letter(&game), Some('T'));
+
+        game.update(Some(Action::HardDrop));
+        assert_eq!(letter(&game), Some('O'));
+
+        game.update(Some(Action::Undo));
+        assert_eq!(letter(&game), Some('T'));
+
+        game.update(Some(Action::HardDrop));
+        assert_eq!(letter(&game), Some('O'));
+    }
 
-        self.screen.draw_text(GAME_WIDTH + 2, 1, "SCORE");
-        self.screen
-            .draw_text(GAME_WIDTH + 2, 2, &format!("{}", self.score));
-
-        self.screen.draw_text(GAME_WIDTH + 2, 4, "CONTROLS");
-        self.screen.draw_text(GAME_WIDTH + 2, 5, "a => Move Left");
-        self.screen.draw_text(GAME_WIDTH + 2, 6, "d => Move Right");
-        self.screen
-            .draw_text(GAME_WIDTH + 2, 7, "w => Rotate Right");
-        self.screen.draw_text(GAME_WIDTH + 2, 8, "s => Rotate Left");
-        self.screen
-            .draw_text(GAME_WIDTH + 2, 9, "z => Rotate left 180 degrees");
-        self.screen
-            .draw_text(GAME_WIDTH + 2, 10, "x => Rotate right 180 degrees");
-        self.screen.draw_text(GAME_WIDTH + 2, 11, "h => Hold");
-        self.screen.draw_text(GAME_WIDTH + 2, 12, "[SPACE] => Drop");
-
-        let hold_box_x = (GAME_WIDTH + 2) as u16;
-        let hold_box_y = 13;
-        let hold_box_width = (GAME_HEIGHT - 13) as u16;
-        let hold_box_height = (GAME_HEIGHT - 13) as u16;
-
-        self.screen
-            .draw_box(hold_box_x, hold_box_y, hold_box_width, hold_box_height)
-            .unwrap();
+    // Writes the state of a game out and reads it back in, with one of the
+    // fields swapped for something else.
+    fn reload(game: &Tetris, mode: GameMode, key: &str, value: &str) -> Result<Tetris, String> {
+        let mut state = String::new();
+        game.write_state(&mut state);
+
+        let fields: Vec<(&str, &str)> = state
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(field, old)| (field, if field == key { value } else { old }))
+            .collect();
+
+        Tetris::read_state(mode, &game.settings, &fields)
+    }
+
+    #[test]
+    fn saved_state_reads_back_in() {
+        let mut game = Tetris::new(8, GameMode::Zen, &settings());
+        game.update(Some(Action::HardDrop));
+        game.update(Some(Action::MoveLeft));
+
+        let loaded = reload(&game, GameMode::Zen, "", "").unwrap();
+        assert_eq!(loaded.blocks, game.blocks);
+        assert_eq!(loaded.current_shape, game.current_shape);
+        assert_eq!(loaded.random_generator.seed, game.random_generator.seed);
+        assert_eq!(
+            (loaded.player_x, loaded.player_y),
+            (game.player_x, game.player_y)
+        );
+        assert_eq!(loaded.undo_history.len(), 1);
+    }
 
-        if let Some(held_shape) = self.held_shape.as_ref() {
-            self.screen
-                .draw_shape(&held_shape, hold_box_x + 4, hold_box_y + 4, false);
+    #[test]
+    fn saved_state_off_the_board_is_rejected() {
+        let game = Tetris::new(8, GameMode::Marathon, &settings());
+
+        for (key, value) in [
+            ("player_x", "40000"),
+            ("player_y", "40000"),
+            ("rng", "101"),
+            ("rng", "67"),
+            ("rng", "18446744073709551615"),
+        ] {
+            let error = reload(&game, GameMode::Marathon, key, value).err();
+            assert_eq!(error, Some(format!("invalid value for '{}'", key)));
         }
+    }
 
-        let current_shape = match self.current_shape.as_ref() {
-            Some(shape) => shape,
-            None => {
-                self.player_x = PLAYER_STARTING_X;
-                self.player_y = PLAYER_STARTING_Y;
-
-                self.current_shape = {
-                    loop {
-                        let generated_shape = SHAPES[<u64 as TryInto<usize>>::try_into(
-                            self.random_generator.generate(),
-                        )
-                        .unwrap()
-                            % 7]
-                        .clone();
-
-                        if let Some(previous_shape) = self.previous_shape.as_ref() {
-                            if generated_shape != *previous_shape {
-                                break Some(generated_shape);
-                            }
-                        } else {
-                            break Some(generated_shape);
-                        }
-                    }
+    // Plays lots of games with random inputs, checking after every frame that
+    // the piece isn't inside the stack and that the score has only gone up.
+    #[test]
+    fn random_play_keeps_the_invariants() {
+        let mut generator = Generator::new(5);
+        // Undoing takes points away on purpose, and quitting isn't much fun.
+        let actions: Vec<Action> = Action::ALL
+            .iter()
+            .copied()
+            .filter(|action| ![Action::Undo, Action::Redo, Action::Quit].contains(action))
+            .collect();
+
+        for game_number in 0..100 {
+            let mode = [GameMode::Marathon, GameMode::Zen, GameMode::Master][game_number % 3];
+            let settings = Settings {
+                line_clear_delay: [0, 250][game_number % 2],
+                ..Settings::default()
+            };
+            let mut game = Tetris::new(generator.below(1_000_000), mode, &settings);
+            let mut score = game.score;
+
+            for _ in 0..2000 {
+                let input = if generator.chance(40) {
+                    Some(actions[generator.below(actions.len() as u64) as usize])
+                } else {
+                    None
                 };
+                game.update(input);
 
-                // If the current shape is out of bounds as soon as it's spawned, then it's likely
-                // because the player has lost.
-                let (within_x_bounds, within_y_bounds) = self.is_shape_in_bounds();
-                if !within_x_bounds || !within_y_bounds {
-                    self.is_running = false;
-                    return;
+                if !game.is_running {
+                    break;
                 }
 
-                self.current_shape.as_ref().unwrap()
-            }
-        };
+                assert!(game.score >= score, "the score went down");
+                score = game.score;
 
-        // Render the blocks onto the screen
-        self.blocks.iter().enumerate().for_each(|(i, row)| {
-            for j in 0..GAME_WIDTH {
-                if let Some(color) = row[<u32 as TryInto<usize>>::try_into(j).unwrap()] {
-                    use crate::screen::Color;
-                    use crate::unicode::FULL_BLOCK;
-
-                    self.screen[j + 1][i + 1] = Pixel {
-                        shape: [FULL_BLOCK, FULL_BLOCK],
-                        color: Color::Basic(color),
-                    };
+                if game.current_shape.is_some() {
+                    assert_eq!(
+                        game.is_shape_in_bounds(),
+                        (true, true),
+                        "the piece is out of bounds or inside the stack"
+                    );
                 }
             }
-        });
-
-        self.screen
-            .draw_shape(current_shape, self.player_x, self.player_y, false);
-
-        self.screen.present();
+        }
     }
 }